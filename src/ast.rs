@@ -1,9 +1,26 @@
+use std::collections::HashSet;
+
+use crate::span::Span;
+
 // top level program struct for ast
 #[derive(Debug)]
 pub struct Program {
     pub declarations: Vec<Declaration>,
 }
 
+impl Program {
+    // file scope functions and variables declared static, other files can't see them
+    pub fn static_names(&self) -> HashSet<&str> {
+        self.declarations.iter()
+            .filter_map(|decl| match decl {
+                Declaration::Function(f) if f.storage_class == StorageClass::Static => Some(f.name.as_str()),
+                Declaration::Variable(v) if v.storage_class == StorageClass::Static => Some(v.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 // top level declarations
 #[derive(Debug)]
 pub enum Declaration {
@@ -15,28 +32,46 @@ pub enum Declaration {
     Typedef(TypedefDec),
 }
 
+impl Declaration {
+    pub fn span(&self) -> Span {
+        match self {
+            Declaration::Function(f) => f.span,
+            Declaration::Variable(v) => v.span,
+            Declaration::Struct(s) => s.span,
+            Declaration::Union(u) => u.span,
+            Declaration::Enum(e) => e.span,
+            Declaration::Typedef(t) => t.span,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UnionDec {
     pub name: Option<String>,
     pub fields: Vec<StructField>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct EnumDec {
     pub name: Option<String>,
     pub variants: Vec<EnumVariant>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct EnumVariant {
     pub name: String,
     pub value: Option<i64>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct TypedefDec {
     pub name: String,
     pub typ: QualifiedType,
+    pub span: Span,
+    pub name_span: Span,
 }
 
 #[derive(Debug)]
@@ -45,6 +80,17 @@ pub struct VarDec {
     pub typ: QualifiedType,
     pub init: Option<Expr>,
     pub storage_class: StorageClass,
+    pub span: Span,
+
+    /// where the name is, diagnostics about the name itself point here
+    pub name_span: Span,
+}
+
+impl VarDec {
+    // int x; and int x = 1; define x, extern int x; only says some file does
+    pub fn is_definition(&self) -> bool {
+        self.storage_class != StorageClass::Extern || self.init.is_some()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -59,12 +105,14 @@ pub enum StorageClass {
 pub struct StructDec {
     pub name: Option<String>,
     pub fields: Vec<StructField>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct StructField {
     pub name: String,
     pub typ: QualifiedType,
+    pub span: Span,
 }
 
 // function declaration
@@ -75,6 +123,8 @@ pub struct FunctionDec {
     pub return_type: QualifiedType,
     pub body: Option<Vec<Statement>>,
     pub storage_class: StorageClass,
+    pub span: Span,
+    pub name_span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -143,14 +193,28 @@ pub struct QualifiedType {
 pub struct Param {
     pub name: Option<String>,
     pub typ: QualifiedType,
+    pub span: Span,
+    pub name_span: Span,
 }
 
 // simple statements
 
 #[derive(Debug, Clone)]
-pub enum Statement {
-    // int x = 5;
-    VarDec(QualifiedType, String, Option<Expr>, StorageClass),
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
+    // int x = 5; the span is the name's
+    VarDec(QualifiedType, String, Option<Expr>, StorageClass, Span),
 
     // x = 5;
     Assign(Expr, Expr),
@@ -203,6 +267,7 @@ pub struct SwitchStmt {
 pub struct Case {
     pub value: Option<Expr>,
     pub stmts: Vec<Statement>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -214,7 +279,19 @@ pub struct DoWhileStmt {
 // simple expressions
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     // literal int value
     IntLiteral(i64),
 
@@ -246,6 +323,8 @@ pub enum Expr {
     CompoundAssign(CompoundOp, Box<Expr>, Box<Expr>),  // x += 5
     
     Call(Box<Expr>, Vec<Expr>),  // can be (*fn_ptr)(args)
+
+    InitList(Vec<Expr>),  // {1, 2, 3}, only as a declaration's initializer
 }
 
 // binary operations
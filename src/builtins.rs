@@ -0,0 +1,51 @@
+use crate::ast::Type;
+
+// functions the vm runs itself instead of bytecode, C code calls them like any other function.
+// codegen emits CALLN with the builtin's code in C
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Malloc,
+    Calloc,
+    Realloc,
+    Free,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 4] = [Builtin::Malloc, Builtin::Calloc, Builtin::Realloc, Builtin::Free];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Malloc => "malloc",
+            Builtin::Calloc => "calloc",
+            Builtin::Realloc => "realloc",
+            Builtin::Free => "free",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL.into_iter().find(|b| b.name() == name)
+    }
+
+    pub fn from_code(code: u16) -> Option<Builtin> {
+        Builtin::ALL.get(code as usize).copied()
+    }
+
+    // same prototypes as <stdlib.h>, size_t is unsigned long
+    pub fn params(&self) -> Vec<Type> {
+        let size_t = Type::Unsigned(Box::new(Type::Long));
+        let void_ptr = Type::Pointer(Box::new(Type::Void));
+        match self {
+            Builtin::Malloc => vec![size_t],
+            Builtin::Calloc => vec![size_t.clone(), size_t],
+            Builtin::Realloc => vec![void_ptr, size_t],
+            Builtin::Free => vec![void_ptr],
+        }
+    }
+
+    pub fn return_type(&self) -> Type {
+        match self {
+            Builtin::Free => Type::Void,
+            _ => Type::Pointer(Box::new(Type::Void)),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use crate::codegen::{CodeGenerator, Constant, FunctionChunk, Instruction, OpCode, Slot};
+use crate::heap::HEAP_START;
+use crate::vm::NULL_GUARD;
+
+/*
+    Bytecode files (.cvmb), what "cvm compile -o" writes and "cvm run" loads:
+
+    Header, 16 bytes:
+        magic       "CVMB"
+        version     bumped whenever the layout below or the instruction encoding changes
+        length      bytes in the body
+        checksum    crc-32 of the body
+
+    Body:
+        files           count, then the name of each source file, for the disassembler
+        functions       count, then each chunk:
+                            name, max_registers (u8), arity (u8), frame_size, file,
+                            instructions (count, then one 32 bit word each),
+                            lines (one per instruction),
+                            constants (count, then a tag byte, 0 int 1 float, and the 8 bytes),
+                            slots (count, then name, offset, size)
+        function_map    count, then name and index, sorted by name
+        data            length, then the data segment's initial bytes
+        rodata          length, then the string literals
+        data_slots      slots, the same as a function's
+        literals        slots of rodata, for --checked
+
+    - numbers are little endian u32 unless noted, a name is its length then utf-8
+    - instructions are the words codegen packed them into, see Instruction::encode
+    - the same program always gives the same bytes, so a file can be cached by its contents
+    - a loaded file is checked before it runs: every jump lands in its function, every constant,
+      function and register an instruction names exists, and data and rodata fit below the heap
+*/
+
+pub const MAGIC: &[u8; 4] = b"CVMB";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 16;
+
+// everything the vm needs to run a linked program
+pub struct Bytecode {
+    pub functions: Vec<FunctionChunk>,
+    pub function_map: HashMap<String, usize>,
+    pub data: Vec<u8>,
+    pub rodata: Vec<u8>,
+
+    /// globals and string literals, for --checked
+    pub data_slots: Vec<Slot>,
+    pub literals: Vec<Slot>,
+
+    /// the source files, a chunk's file is an index into these
+    pub files: Vec<String>,
+}
+
+impl Bytecode {
+    // files are the names of the programs given to gen_units, in the same order
+    pub fn new(codegen: CodeGenerator, files: Vec<String>) -> Self {
+        Bytecode {
+            literals: codegen.rodata.slots(),
+            functions: codegen.functions,
+            function_map: codegen.function_map,
+            data: codegen.data,
+            rodata: codegen.rodata.bytes,
+            data_slots: codegen.data_slots,
+            files,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Writer::default();
+
+        body.u32(self.files.len() as u32);
+        for file in &self.files {
+            body.name(file);
+        }
+
+        body.u32(self.functions.len() as u32);
+        for func in &self.functions {
+            body.name(&func.name);
+            body.u8(func.max_registers);
+            body.u8(func.arity);
+            body.u32(func.frame_size);
+            body.u32(func.file);
+
+            body.u32(func.instructions.len() as u32);
+            for &word in &func.instructions {
+                body.u32(word);
+            }
+            for &line in &func.lines {
+                body.u32(line);
+            }
+
+            body.u32(func.constants.len() as u32);
+            for constant in &func.constants {
+                let tag = match constant {
+                    Constant::Int(_) => 0,
+                    Constant::Float(_) => 1,
+                };
+                body.u8(tag);
+                body.u64(constant.raw() as u64);
+            }
+
+            body.slots(&func.slots);
+        }
+
+        let mut names: Vec<(&String, &usize)> = self.function_map.iter().collect();
+        names.sort();
+        body.u32(names.len() as u32);
+        for (name, &idx) in names {
+            body.name(name);
+            body.u32(idx as u32);
+        }
+
+        body.u32(self.data.len() as u32);
+        body.bytes.extend(&self.data);
+        body.u32(self.rodata.len() as u32);
+        body.bytes.extend(&self.rodata);
+        body.slots(&self.data_slots);
+        body.slots(&self.literals);
+
+        let mut file = Writer::default();
+        file.bytes.extend(MAGIC);
+        file.u32(VERSION);
+        file.u32(body.bytes.len() as u32);
+        file.u32(crc32(&body.bytes));
+        file.bytes.extend(body.bytes);
+        file.bytes
+    }
+
+    // Err says what's wrong with the file, nothing from it is trusted until the checksum matches
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err("not a cvm bytecode file".to_string());
+        }
+        let mut header = Reader { bytes: &bytes[4..HEADER_SIZE], pos: 0 };
+        let version = header.u32()?;
+        if version != VERSION {
+            return Err(format!("bytecode version {} is not supported, this cvm reads version {}", version, VERSION));
+        }
+        let length = header.u32()? as usize;
+        let checksum = header.u32()?;
+
+        let body = &bytes[HEADER_SIZE..];
+        if body.len() != length {
+            return Err(format!("file is {} bytes long, the header says {}", body.len() + HEADER_SIZE, length + HEADER_SIZE));
+        }
+        if crc32(body) != checksum {
+            return Err("checksum mismatch, the file is corrupted".to_string());
+        }
+
+        let mut body = Reader { bytes: body, pos: 0 };
+        let mut files = vec![];
+        for _ in 0..body.u32()? {
+            files.push(body.name()?);
+        }
+
+        let mut functions = vec![];
+        for _ in 0..body.u32()? {
+            let name = body.name()?;
+            let max_registers = body.u8()?;
+            let arity = body.u8()?;
+            let frame_size = body.u32()?;
+            let file = body.u32()?;
+            if file as usize >= files.len() {
+                return Err(format!("'{}' is from file {}, there are only {}", name, file, files.len()));
+            }
+
+            let mut instructions = vec![];
+            for _ in 0..body.u32()? {
+                instructions.push(body.u32()?);
+            }
+            let mut lines = vec![];
+            for _ in 0..instructions.len() {
+                lines.push(body.u32()?);
+            }
+
+            let mut constants = vec![];
+            for _ in 0..body.u32()? {
+                let tag = body.u8()?;
+                let raw = body.u64()?;
+                constants.push(match tag {
+                    0 => Constant::Int(raw as i64),
+                    1 => Constant::Float(f64::from_bits(raw)),
+                    _ => return Err(format!("bad constant tag {} in '{}'", tag, name)),
+                });
+            }
+
+            let slots = body.slots()?;
+            functions.push(FunctionChunk { name, instructions, constants, max_registers, frame_size, slots, arity, file, lines });
+        }
+
+        let mut function_map = HashMap::new();
+        for _ in 0..body.u32()? {
+            let name = body.name()?;
+            let idx = body.u32()? as usize;
+            if idx >= functions.len() {
+                return Err(format!("'{}' names function {}, there are only {}", name, idx, functions.len()));
+            }
+            function_map.insert(name, idx);
+        }
+
+        let len = body.u32()? as usize;
+        let data = body.take(len)?.to_vec();
+        let len = body.u32()? as usize;
+        let rodata = body.take(len)?.to_vec();
+        let data_slots = body.slots()?;
+        let literals = body.slots()?;
+
+        if body.pos != body.bytes.len() {
+            return Err(format!("{} unexpected bytes after the program", body.bytes.len() - body.pos));
+        }
+
+        let program = Bytecode { functions, function_map, data, rodata, data_slots, literals, files };
+        program.validate()?;
+        Ok(program)
+    }
+
+    // Err when running the program could take the vm somewhere the file doesn't describe.
+    // codegen never produces that, a file that does was made by hand or by something else
+    pub fn validate(&self) -> Result<(), String> {
+        for func in &self.functions {
+            for (pc, &word) in func.instructions.iter().enumerate() {
+                let instr = Instruction::decode(word).ok_or_else(|| format!("unknown opcode {} at pc {} in '{}'", word & 0x3f, pc, func.name))?;
+                self.check_instruction(func, pc, instr).map_err(|e| format!("{} at pc {} in '{}'", e, pc, func.name))?;
+            }
+        }
+
+        // the vm lays out the null guard, data and rodata in front of the heap, see VM::new
+        let end = NULL_GUARD + self.data.len().next_multiple_of(8) + self.rodata.len();
+        if end > HEAP_START {
+            return Err(format!("data and rodata end at address {}, past the heap at {}", end, HEAP_START));
+        }
+        Ok(())
+    }
+
+    fn check_instruction(&self, func: &FunctionChunk, pc: usize, instr: Instruction) -> Result<(), String> {
+        let jump_target = |offset: i64| {
+            let target = pc as i64 + 1 + offset;
+            if !(0..func.instructions.len() as i64).contains(&target) {
+                return Err(format!("jump to {}, outside the function's {} instructions", target, func.instructions.len()));
+            }
+            Ok(())
+        };
+        let registers = |regs: &[usize]| match regs.iter().find(|&&reg| reg > func.max_registers as usize) {
+            Some(reg) => Err(format!("register r{} past the function's last register r{}", reg, func.max_registers)),
+            None => Ok(()),
+        };
+
+        match instr {
+            Instruction::AsBx { offset, .. } => jump_target(offset as i64),
+            Instruction::ABx { opcode, a, bx } => {
+                registers(&[a as usize])?;
+                match opcode {
+                    OpCode::LOADK if bx as usize >= func.constants.len() => {
+                        Err(format!("constant {} named, there are only {}", bx, func.constants.len()))
+                    }
+                    OpCode::CLOSURE if bx as usize >= self.functions.len() => {
+                        Err(format!("function {} named, there are only {}", bx, self.functions.len()))
+                    }
+                    // the default JMP and then one per entry
+                    OpCode::JMPTAB => jump_target(bx as i64),
+                    _ => Ok(()),
+                }
+            }
+            Instruction::ABC { opcode, a, b, c } => {
+                let (a, b, c) = (a as usize, b as usize, c as usize);
+                match opcode {
+                    OpCode::RETURN if b == 2 => registers(&[a]),
+                    OpCode::RETURN => Ok(()),
+                    OpCode::TEST => registers(&[a]),
+                    // the function ref and the args after it
+                    OpCode::CALL | OpCode::CALLN => registers(&[a, a + b.saturating_sub(1)]),
+                    OpCode::MOV | OpCode::UNM | OpCode::NOT | OpCode::BNOT | OpCode::FUNM
+                    | OpCode::I2F | OpCode::U2F | OpCode::F2I | OpCode::F2U | OpCode::F2S
+                    | OpCode::SEXT | OpCode::ZEXT | OpCode::LOAD | OpCode::STORE => registers(&[a, b]),
+                    _ => registers(&[a, b, c]),
+                }
+            }
+        }
+    }
+}
+
+// crc-32 (the zlib one), bit at a time since files are small
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, val: u8) {
+        self.bytes.push(val);
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.bytes.extend(val.to_le_bytes());
+    }
+
+    fn u64(&mut self, val: u64) {
+        self.bytes.extend(val.to_le_bytes());
+    }
+
+    fn name(&mut self, name: &str) {
+        self.u32(name.len() as u32);
+        self.bytes.extend(name.as_bytes());
+    }
+
+    fn slots(&mut self, slots: &[Slot]) {
+        self.u32(slots.len() as u32);
+        for slot in slots {
+            self.name(&slot.name);
+            self.u32(slot.offset);
+            self.u32(slot.size);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len())
+            .ok_or("file ends in the middle of the program")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "name is not utf-8".to_string())
+    }
+
+    fn slots(&mut self) -> Result<Vec<Slot>, String> {
+        let mut slots = vec![];
+        for _ in 0..self.u32()? {
+            let name = self.name()?;
+            let offset = self.u32()?;
+            let size = self.u32()?;
+            slots.push(Slot { name, offset, size });
+        }
+        Ok(slots)
+    }
+}
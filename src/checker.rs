@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::codegen::{FunctionChunk, Slot};
+use crate::heap::HEAP_START;
+use crate::vm::NULL_GUARD;
+
+/*
+    Checked mode (--checked), a small AddressSanitizer for the vm:
+        - every variable in memory, string literal and malloc'd block is an object with a
+          start, a size and whether it's still alive
+        - a register remembers which object its value was made from. ADDR, GADDR, SADDR and
+          malloc hand one out, MOV/ADD/SUB carry it along and everything else drops it.
+          an 8 byte STORE of a pointer remembers it in memory too, so LOAD gets it back
+        - LOAD, STORE and COPY check that the bytes they touch are inside that object and that
+          it's still alive. a pointer that lost track of its object is looked up by address
+        - every byte of memory is marked once something is written to it, a LOAD of a byte
+          nothing wrote is an error. COPY just carries the marks along (struct padding)
+        - a freed block stays dead even after the heap hands its memory out again, so a
+          dangling pointer is still caught
+*/
+
+#[derive(Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    // the source of a COPY, it can carry unwritten bytes along
+    Copy,
+}
+
+impl Access {
+    fn verb(&self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Copy => "copy",
+        }
+    }
+}
+
+enum Origin {
+    Global(usize),
+    Literal(usize),
+    Local { function: usize, slot: usize },
+    Heap,
+}
+
+struct Object {
+    start: usize,
+    size: usize,
+    live: bool,
+    origin: Origin,
+}
+
+pub struct Checker {
+    objects: Vec<Object>,
+
+    /// names of the data segment and rodata objects
+    globals: Vec<Slot>,
+    literals: Vec<Slot>,
+
+    /// function names and the names of their slots, for the reports
+    functions: Vec<String>,
+    locals: Vec<Vec<String>>,
+
+    /// globals and string literals by address, these live as long as the program
+    statics: BTreeMap<usize, usize>,
+
+    /// objects in each active call's frame, innermost call last
+    frames: Vec<Vec<usize>>,
+
+    /// malloc'd blocks by address, a freed one stays until its address is handed out again
+    heap_blocks: BTreeMap<usize, usize>,
+
+    /// object each register's value was made from, parallel to the vm's stack
+    registers: Vec<Option<usize>>,
+
+    /// object each pointer stored in memory was made from, by the address it's stored at
+    pointers: BTreeMap<usize, usize>,
+
+    /// which bytes of memory have been written
+    initialized: Vec<bool>,
+}
+
+impl Checker {
+    pub fn new(globals: Vec<Slot>, literals: Vec<Slot>, functions: &[FunctionChunk], rodata: Range<usize>, stack_size: usize, memory_size: usize) -> Self {
+        let mut checker = Checker {
+            objects: vec![],
+            globals: vec![],
+            literals: vec![],
+            functions: functions.iter().map(|f| f.name.clone()).collect(),
+            locals: functions.iter().map(|f| f.slots.iter().map(|slot| slot.name.clone()).collect()).collect(),
+            statics: BTreeMap::new(),
+            frames: vec![],
+            heap_blocks: BTreeMap::new(),
+            registers: vec![None; stack_size],
+            pointers: BTreeMap::new(),
+            initialized: vec![false; memory_size],
+        };
+
+        // the data segment starts out zeroed or initialized, either way it's been written
+        checker.initialized[NULL_GUARD..rodata.end].fill(true);
+
+        for (i, slot) in globals.iter().enumerate() {
+            let id = checker.add_object(NULL_GUARD + slot.offset as usize, slot.size as usize, Origin::Global(i));
+            checker.statics.insert(NULL_GUARD + slot.offset as usize, id);
+        }
+        for (i, slot) in literals.iter().enumerate() {
+            let id = checker.add_object(rodata.start + slot.offset as usize, slot.size as usize, Origin::Literal(i));
+            checker.statics.insert(rodata.start + slot.offset as usize, id);
+        }
+        checker.globals = globals;
+        checker.literals = literals;
+        checker
+    }
+
+    fn add_object(&mut self, start: usize, size: usize, origin: Origin) -> usize {
+        self.objects.push(Object { start, size, live: true, origin });
+        self.objects.len() - 1
+    }
+
+    // a call's locals come alive, none of them written yet
+    pub fn push_frame(&mut self, function: usize, chunk: &FunctionChunk, frame_addr: usize) {
+        let objects = chunk.slots.iter().enumerate()
+            .map(|(slot, info)| self.add_object(frame_addr + info.offset as usize, info.size as usize, Origin::Local { function, slot }))
+            .collect();
+        self.frames.push(objects);
+        self.initialized[frame_addr..frame_addr + chunk.frame_size as usize].fill(false);
+    }
+
+    pub fn pop_frame(&mut self) {
+        for id in self.frames.pop().unwrap_or_default() {
+            self.objects[id].live = false;
+        }
+    }
+
+    pub fn register(&self, slot: usize) -> Option<usize> {
+        self.registers[slot]
+    }
+
+    pub fn set_register(&mut self, slot: usize, object: Option<usize>) {
+        self.registers[slot] = object;
+    }
+
+    // the local an ADDR lands in, ADDR of a struct field still belongs to the struct
+    pub fn local_at(&self, addr: usize) -> Option<usize> {
+        self.frames.last()?.iter().copied().find(|&id| self.contains(id, addr))
+    }
+
+    // the global or string literal a GADDR/SADDR lands in
+    pub fn static_at(&self, addr: usize) -> Option<usize> {
+        let (_, &id) = self.statics.range(..=addr).next_back()?;
+        self.contains(id, addr).then_some(id)
+    }
+
+    fn heap_block_at(&self, addr: usize) -> Option<usize> {
+        let (_, &id) = self.heap_blocks.range(..=addr).next_back()?;
+        self.contains(id, addr).then_some(id)
+    }
+
+    fn contains(&self, id: usize, addr: usize) -> bool {
+        let object = &self.objects[id];
+        addr >= object.start && addr < object.start + object.size.max(1)
+    }
+
+    // checks len bytes at addr through a pointer made from object, Err is the report
+    pub fn check(&self, addr: i64, len: usize, object: Option<usize>, access: Access) -> Result<(), String> {
+        if (0..NULL_GUARD as i64).contains(&addr) {
+            return Err(format!("null pointer dereference: {} of {} bytes at address {}", access.verb(), len, addr));
+        }
+        if usize::try_from(addr).ok().and_then(|start| start.checked_add(len)).is_none_or(|end| end > self.initialized.len()) {
+            return Err(format!("wild pointer: {} of {} bytes at address {}, outside memory", access.verb(), len, addr));
+        }
+
+        // a pointer that came from an integer is held to whatever object it lands in
+        let object = object.or_else(|| {
+            let addr = usize::try_from(addr).ok()?;
+            if addr >= HEAP_START {
+                self.heap_block_at(addr)
+            } else {
+                self.static_at(addr)
+            }
+        });
+
+        let Some(id) = object else {
+            if usize::try_from(addr).is_ok_and(|addr| addr >= HEAP_START) {
+                return Err(format!("heap out of bounds: {} of {} bytes at address {}, outside every block", access.verb(), len, addr));
+            }
+            return Ok(());
+        };
+
+        let obj = &self.objects[id];
+        let offset = addr - obj.start as i64;
+        // only built when there's something to report
+        let what = || format!("{} of {} bytes at offset {} of {}", access.verb(), len, offset, self.describe(id));
+        if !obj.live {
+            return Err(match obj.origin {
+                Origin::Local { .. } => format!("use after return: {}, which has returned", what()),
+                _ => format!("use after free: {}, which was freed", what()),
+            });
+        }
+        if offset < 0 || offset as usize + len > obj.size {
+            return Err(format!("out of bounds {} ({} bytes)", what(), obj.size));
+        }
+        if let (Access::Write, Origin::Literal(_)) = (access, &obj.origin) {
+            return Err(format!("write to read-only memory: {}", what()));
+        }
+        if let Access::Read = access {
+            let start = addr as usize;
+            if let Some(unset) = self.initialized[start..start + len].iter().position(|&set| !set) {
+                return Err(format!("read of uninitialized memory: {} bytes at offset {} of {}", len, offset + unset as i64, self.describe(id)));
+            }
+        }
+        Ok(())
+    }
+
+    // a STORE went through, the bytes are written and any pointer stored there is replaced
+    pub fn stored(&mut self, addr: usize, len: usize, object: Option<usize>) {
+        self.initialized[addr..addr + len].fill(true);
+        self.forget_pointers(addr..addr + len);
+        if let (8, Some(id)) = (len, object) {
+            self.pointers.insert(addr, id);
+        }
+    }
+
+    // the object a LOAD of len bytes from addr points into
+    pub fn loaded(&self, addr: usize, len: usize) -> Option<usize> {
+        if len == 8 { self.pointers.get(&addr).copied() } else { None }
+    }
+
+    pub fn copied(&mut self, dest: usize, src: usize, len: usize) {
+        self.initialized.copy_within(src..src + len, dest);
+        let moved: Vec<(usize, usize)> = self.pointers.range(src..src + len.saturating_sub(7))
+            .map(|(&addr, &id)| (addr - src + dest, id))
+            .collect();
+        self.forget_pointers(dest..dest + len);
+        self.pointers.extend(moved);
+    }
+
+    fn forget_pointers(&mut self, range: Range<usize>) {
+        let stale: Vec<usize> = self.pointers.range(range.start.saturating_sub(7)..range.end).map(|(&addr, _)| addr).collect();
+        for addr in stale {
+            self.pointers.remove(&addr);
+        }
+    }
+
+    pub fn initialize(&mut self, range: Range<usize>) {
+        self.initialized[range].fill(true);
+    }
+
+    // malloc handed out size bytes at addr, nothing written yet
+    pub fn allocated(&mut self, addr: usize, size: usize, memory_size: usize) {
+        if self.initialized.len() < memory_size {
+            self.initialized.resize(memory_size, false);
+        }
+        self.initialized[addr..addr + size].fill(false);
+        self.forget_pointers(addr..addr + size);
+
+        // dead blocks that used this memory are forgotten, a dangling pointer still has its object
+        let reused: Vec<usize> = self.heap_blocks.range(addr..addr + size.max(1)).map(|(&start, _)| start).collect();
+        for start in reused {
+            self.heap_blocks.remove(&start);
+        }
+
+        let id = self.add_object(addr, size, Origin::Heap);
+        self.heap_blocks.insert(addr, id);
+    }
+
+    // the live block malloc returned at addr
+    pub fn block(&self, addr: usize) -> Option<usize> {
+        self.heap_blocks.get(&addr).copied().filter(|&id| self.objects[id].live)
+    }
+
+    // checks addr can be passed to free or realloc, builtin is which one for the report
+    pub fn freeable(&self, addr: usize, builtin: &str) -> Result<usize, String> {
+        match self.heap_blocks.get(&addr) {
+            Some(&id) if self.objects[id].live => Ok(id),
+            Some(&id) if builtin == "free" => Err(format!("double free of {}", self.describe(id))),
+            Some(&id) => Err(format!("{} of {}, which was already freed", builtin, self.describe(id))),
+            None => match self.heap_block_at(addr) {
+                Some(id) => Err(format!("invalid {} of address {}, {} bytes into {}", builtin, addr, addr - self.objects[id].start, self.describe(id))),
+                None => Err(format!("invalid {} of address {}, which malloc never returned", builtin, addr)),
+            },
+        }
+    }
+
+    pub fn free(&mut self, addr: usize) -> Result<(), String> {
+        let id = self.freeable(addr, "free")?;
+        self.objects[id].live = false;
+        Ok(())
+    }
+
+    // the collector freed whatever isn't allocated anymore
+    pub fn collected(&mut self, allocated: impl Fn(usize) -> bool) {
+        for (&addr, &id) in &self.heap_blocks {
+            if !allocated(addr) {
+                self.objects[id].live = false;
+            }
+        }
+    }
+
+    fn describe(&self, id: usize) -> String {
+        let obj = &self.objects[id];
+        match obj.origin {
+            Origin::Global(i) => format!("global '{}'", self.globals[i].name),
+            Origin::Literal(i) => format!("string literal {}", self.literals[i].name),
+            Origin::Local { function, slot } => format!("local '{}' of '{}'", self.locals[function][slot], self.functions[function]),
+            Origin::Heap => format!("the {} byte block malloc returned at address {}", obj.size, obj.start),
+        }
+    }
+}
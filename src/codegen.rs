@@ -14,13 +14,22 @@
 // with their own registers and constants
 // codegen will generator code per function
 
-use std::{collections::{HashMap, HashSet}, hash::Hash, string, u8::MAX};
+use std::{collections::{HashMap, HashSet}, fmt, hash::Hash, string, u8::MAX};
 
 use bitvec::vec::BitVec;
 
-use crate::ast::{BinOp, CompoundOp, Declaration, EnumDec, Expr, FunctionDec, Program, Statement, UnaryOp};
+use crate::ast::{BinOp, Case, CompoundOp, Declaration, EnumDec, Expr, ExprKind, FunctionDec, Program, Statement, StatementKind, StorageClass, StructDec, StructField, Type, UnaryOp, UnionDec};
+use crate::builtins::Builtin;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::semantic::{common_type, integer_promotion};
+use crate::span::Span;
+use crate::vm::NULL_GUARD;
 
 // 6 bit opcode
+// spelled like the mnemonics in isa_spec.txt
+// an opcode's number in a .cvmb file is its position here, see OpCode::ALL
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     // iABC
     ADD, SUB, MUL, DIV, MOD, MOV,
@@ -30,16 +39,80 @@ pub enum OpCode {
     UNM, NOT, BNOT,
     BAND, BOR, BXOR, SHL, SHR,
     CALL,
+    CALLN, // call builtin C with B - 1 args in rA+1.., the result lands in rA
+
+    // iABC memory, C is a Width code
+    LOAD,  // rA = memory[rB]
+    STORE, // memory[rA] = rB
+    COPY,  // copy rC bytes from memory[rB] to memory[rA], for structs
+
+    // iABC unsigned versions, operands are read as u64
+    DIVU, MODU, SHRU,
+    LTU, LEU, GTU, GEU,
+
+    // iABC float versions, registers hold the f64 bits
+    FADD, FSUB, FMUL, FDIV, FUNM,
+    FEQ, FLT, FLE,
+    FNE, FGT, FGE,
+
+    // iABC conversions, rA = convert(rB)
+    I2F, U2F, // int -> double, signed or unsigned source
+    F2I, F2U, // double -> int, truncates toward zero
+    F2S,      // round a double to float precision
+    SEXT,     // keep the low C bits of rB, sign extended
+    ZEXT,     // keep the low C bits of rB, zero extended
 
     // iABx
     LOADK, 
     TEST,
     CLOSURE,
+    JMPTAB, // indexed jump into the JMPs that follow it
+    ADDR,   // rA = address of byte Bx in this call's stack frame
+    GADDR,  // rA = address of byte Bx in the global data segment
+    SADDR,  // rA = address of byte Bx in the read-only string data
 
     // iAsBx
     JMP, // unconditional jump
 }
 
+impl OpCode {
+    // in declaration order, so ALL[op as usize] == op
+    pub const ALL: [OpCode; 59] = [
+        OpCode::ADD, OpCode::SUB, OpCode::MUL, OpCode::DIV, OpCode::MOD, OpCode::MOV,
+        OpCode::EQ, OpCode::LT, OpCode::LE,
+        OpCode::NE, OpCode::GT, OpCode::GE,
+        OpCode::RETURN,
+        OpCode::UNM, OpCode::NOT, OpCode::BNOT,
+        OpCode::BAND, OpCode::BOR, OpCode::BXOR, OpCode::SHL, OpCode::SHR,
+        OpCode::CALL,
+        OpCode::CALLN,
+        OpCode::LOAD, OpCode::STORE, OpCode::COPY,
+        OpCode::DIVU, OpCode::MODU, OpCode::SHRU,
+        OpCode::LTU, OpCode::LEU, OpCode::GTU, OpCode::GEU,
+        OpCode::FADD, OpCode::FSUB, OpCode::FMUL, OpCode::FDIV, OpCode::FUNM,
+        OpCode::FEQ, OpCode::FLT, OpCode::FLE,
+        OpCode::FNE, OpCode::FGT, OpCode::FGE,
+        OpCode::I2F, OpCode::U2F,
+        OpCode::F2I, OpCode::F2U,
+        OpCode::F2S,
+        OpCode::SEXT,
+        OpCode::ZEXT,
+        OpCode::LOADK,
+        OpCode::TEST,
+        OpCode::CLOSURE,
+        OpCode::JMPTAB,
+        OpCode::ADDR,
+        OpCode::GADDR,
+        OpCode::SADDR,
+        OpCode::JMP,
+    ];
+
+    pub fn from_code(code: u8) -> Option<OpCode> {
+        OpCode::ALL.get(code as usize).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
     // iABC: three operand instructions (arithmetic, etc)
     ABC { 
@@ -63,19 +136,363 @@ pub enum Instruction {
     },
 }
 
+// where each field sits in an instruction word, the opcode is the low 6 bits
+//   iABC:  opcode | A << 6 | B << 14 | C << 23
+//   iABx:  opcode | A << 6 | Bx << 14
+//   iAsBx: opcode | sBx << 14, two's complement
+const POS_A: u32 = 6;
+const POS_B: u32 = 14;
+const POS_C: u32 = 23;
+const POS_BX: u32 = 14;
+const MAX_BC: u16 = (1 << 9) - 1;
+const MAX_BX: u32 = (1 << 18) - 1;
+const MAX_SBX: i32 = (1 << 17) - 1;
+
+impl Instruction {
+    // Err when an operand doesn't fit its field, encode would lose bits of it
+    pub fn check_range(&self) -> Result<(), String> {
+        match *self {
+            Instruction::ABC { opcode, b, c, .. } if b > MAX_BC || c > MAX_BC => {
+                Err(format!("{:?} operands {}, {} don't fit in 9 bits", opcode, b, c))
+            }
+            Instruction::ABx { opcode, bx, .. } if bx > MAX_BX => {
+                Err(format!("{:?} operand {} doesn't fit in 18 bits", opcode, bx))
+            }
+            Instruction::AsBx { opcode, offset } if !(-MAX_SBX - 1..=MAX_SBX).contains(&offset) => {
+                Err(format!("{:?} offset {} doesn't fit in 18 bits", opcode, offset))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // packs the instruction into one word, its operands have to pass check_range
+    pub fn encode(&self) -> u32 {
+        match *self {
+            Instruction::ABC { opcode, a, b, c } => {
+                opcode as u32 | (a as u32) << POS_A | (b as u32) << POS_B | (c as u32) << POS_C
+            }
+            Instruction::ABx { opcode, a, bx } => opcode as u32 | (a as u32) << POS_A | bx << POS_BX,
+            Instruction::AsBx { opcode, offset } => opcode as u32 | (offset as u32) << POS_BX,
+        }
+    }
+
+    // None when the opcode bits don't name an opcode
+    pub fn decode(word: u32) -> Option<Instruction> {
+        let opcode = OpCode::from_code((word & 0x3f) as u8)?;
+        Some(match opcode {
+            OpCode::LOADK | OpCode::CLOSURE | OpCode::JMPTAB | OpCode::ADDR | OpCode::GADDR | OpCode::SADDR => {
+                Instruction::ABx { opcode, a: arg_a(word) as u8, bx: arg_bx(word) as u32 }
+            }
+            OpCode::JMP => Instruction::AsBx { opcode, offset: arg_sbx(word) },
+            _ => Instruction::ABC { opcode, a: arg_a(word) as u8, b: arg_b(word) as u16, c: arg_c(word) as u16 },
+        })
+    }
+}
+
+// the fields of an instruction word, the vm reads its operands straight out of the word.
+// only words from encode are run, so the opcode is always a real one
+pub fn op(word: u32) -> OpCode {
+    OpCode::ALL[(word & 0x3f) as usize]
+}
+
+pub fn arg_a(word: u32) -> usize {
+    (word >> POS_A & 0xff) as usize
+}
+
+pub fn arg_b(word: u32) -> usize {
+    (word >> POS_B) as usize & MAX_BC as usize
+}
+
+pub fn arg_c(word: u32) -> usize {
+    (word >> POS_C) as usize
+}
+
+pub fn arg_bx(word: u32) -> usize {
+    (word >> POS_BX) as usize
+}
+
+// the arithmetic shift brings the sign back
+pub fn arg_sbx(word: u32) -> i32 {
+    word as i32 >> POS_BX
+}
+
+// constant table entry, LOADK puts either one in a register as raw bits
+#[derive(Debug, Clone, Copy)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+}
+
+impl Constant {
+    // what actually lives in a register
+    pub fn raw(&self) -> i64 {
+        match self {
+            Constant::Int(val) => *val,
+            Constant::Float(val) => val.to_bits() as i64,
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(val) => write!(f, "{}", val),
+            // {:?} keeps the .0 so floats don't look like ints
+            Constant::Float(val) => write!(f, "{:?}", val),
+        }
+    }
+}
+
+// C operand of LOAD/STORE: how many bytes move, and how they widen back out to 64 bits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Width {
+    I8 = 1,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    F32, // float lives in memory as 4 bytes but in a register as an f64
+}
+
+impl Width {
+    pub fn of(typ: &Type) -> Width {
+        if matches!(typ, Type::Float) {
+            return Width::F32;
+        }
+        match int_layout(typ) {
+            Some((8, false)) => Width::I8,
+            Some((8, true)) => Width::U8,
+            Some((16, false)) => Width::I16,
+            Some((16, true)) => Width::U16,
+            Some((32, false)) => Width::I32,
+            Some((32, true)) => Width::U32,
+            _ => Width::I64,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Option<Width> {
+        [Width::I8, Width::U8, Width::I16, Width::U16, Width::I32, Width::U32, Width::I64, Width::F32]
+            .into_iter()
+            .find(|w| *w as u16 == code)
+    }
+
+    pub fn bytes(&self) -> usize {
+        match self {
+            Width::I8 | Width::U8 => 1,
+            Width::I16 | Width::U16 => 2,
+            Width::I32 | Width::U32 | Width::F32 => 4,
+            Width::I64 => 8,
+        }
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Width::I8 => "i8",
+            Width::U8 => "u8",
+            Width::I16 => "i16",
+            Width::U16 => "u16",
+            Width::I32 => "i32",
+            Width::U32 => "u32",
+            Width::I64 => "i64",
+            Width::F32 => "f32",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a struct or union laid out the way a C compiler on a 64 bit target would
+pub struct Record {
+    pub size: u32,
+    pub align: u32,
+    pub fields: Vec<Field>,
+}
+
+pub struct Field {
+    pub name: String,
+    pub offset: u32,
+    pub typ: Type,
+}
+
+// the user defined types, sizes of everything else are fixed
+#[derive(Default)]
+pub struct Layouts {
+    // struct/union tag -> layout, they share one namespace like in C
+    records: HashMap<String, Record>,
+
+    // typedef name -> the type it aliases
+    typedefs: HashMap<String, Type>,
+}
+
+impl Layouts {
+    // swaps typedef names for what they alias, struct names stay as refs
+    // so self referencing structs don't loop forever
+    pub fn resolve(&self, typ: &Type) -> Type {
+        match typ {
+            Type::TypedefRef(name) | Type::Typedef { name, .. } => match self.typedefs.get(name) {
+                Some(aliased) => self.resolve(aliased),
+                None => typ.clone(),
+            },
+            Type::Pointer(inner) => Type::Pointer(Box::new(self.resolve(inner))),
+            Type::Array(elem, size) => Type::Array(Box::new(self.resolve(elem)), *size),
+            Type::Function { params, return_type } => Type::Function {
+                params: params.iter().map(|param| self.resolve(param)).collect(),
+                return_type: Box::new(self.resolve(return_type)),
+            },
+            other => other.clone(),
+        }
+    }
+
+    // bytes a value of the type takes up in memory
+    pub fn size_of(&self, typ: &Type) -> u32 {
+        match typ {
+            Type::Array(elem, size) => self.size_of(elem) * size.unwrap_or(0) as u32,
+            Type::StructRef(_) | Type::UnionRef(_) => self.record(typ).map_or(0, |record| record.size),
+            Type::TypedefRef(_) => self.size_of(&self.resolve(typ)),
+            Type::Void => 1,
+            Type::Double | Type::Pointer(_) => 8,
+            other => Width::of(other).bytes() as u32,
+        }
+    }
+
+    pub fn align_of(&self, typ: &Type) -> u32 {
+        match typ {
+            Type::Array(elem, _) => self.align_of(elem),
+            Type::StructRef(_) | Type::UnionRef(_) => self.record(typ).map_or(1, |record| record.align),
+            Type::TypedefRef(_) => self.align_of(&self.resolve(typ)),
+            other => self.size_of(other),
+        }
+    }
+
+    fn record(&self, typ: &Type) -> Option<&Record> {
+        match typ {
+            Type::StructRef(name) | Type::UnionRef(name) => self.records.get(name),
+            _ => None,
+        }
+    }
+
+    fn field(&self, typ: &Type, name: &str) -> Option<&Field> {
+        self.record(typ)?.fields.iter().find(|field| field.name == name)
+    }
+
+    // struct fields go in order, each at the next multiple of its alignment,
+    // union fields all start at 0. either way the size rounds up to the biggest alignment
+    fn add_record(&mut self, name: &str, fields: &[StructField], is_union: bool) {
+        let mut record = Record { size: 0, align: 1, fields: vec![] };
+        for field in fields {
+            let typ = self.resolve(&field.typ.base);
+            let (size, align) = (self.size_of(&typ), self.align_of(&typ));
+            let offset = if is_union { 0 } else { record.size.next_multiple_of(align) };
+
+            record.size = if is_union { record.size.max(size) } else { offset + size };
+            record.align = record.align.max(align);
+            record.fields.push(Field { name: field.name.clone(), offset, typ });
+        }
+        record.size = record.size.next_multiple_of(record.align);
+        self.records.insert(name.to_string(), record);
+    }
+}
+
+// string literals, each stored once with its terminating nul. the vm puts these right after the
+// data segment and rejects any write into them
+#[derive(Default)]
+pub struct Rodata {
+    pub bytes: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl Rodata {
+    // one slot per literal, nul included
+    pub fn slots(&self) -> Vec<Slot> {
+        let mut slots: Vec<Slot> = self.offsets.iter()
+            .map(|(text, &offset)| Slot { name: format!("{:?}", text), offset, size: string_bytes(text).len() as u32 })
+            .collect();
+        slots.sort_by_key(|slot| slot.offset);
+        slots
+    }
+
+    // offset of the string in rodata, adding it the first time it's seen
+    fn intern(&mut self, text: &str) -> u32 {
+        if let Some(&offset) = self.offsets.get(text) {
+            return offset;
+        }
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend(string_bytes(text));
+        self.offsets.insert(text.to_string(), offset);
+        offset
+    }
+}
+
+// a global or static local that lives in the data segment
+#[derive(Clone)]
+pub struct Global {
+    pub offset: u32,
+    pub typ: Type,
+}
+
+// a named variable in a stack frame or the data segment, the checked vm uses these to know
+// where each object starts and ends
+#[derive(Debug, Clone)]
+pub struct Slot {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+// what codegen needs to know about a function it's calling
+#[derive(Clone)]
+pub struct Signature {
+    params: Vec<Type>,
+    return_type: Type,
+}
+
 pub struct FunctionChunk {
     pub name: String,
-    pub instructions: Vec<Instruction>,
-    pub constants: Vec<i64>,
+
+    // each instruction packed into a word, see Instruction::encode
+    pub instructions: Vec<u32>,
+    pub constants: Vec<Constant>,
     pub max_registers: u8,
+
+    // bytes of stack memory each call needs for its address-taken locals
+    pub frame_size: u32,
+
+    // what lives where in that memory
+    pub slots: Vec<Slot>,
+
+    // registers a caller passes, the hidden struct return address included. CALL checks it
+    pub arity: u8,
+
+    // which of the linked files the function is from, and the source line each instruction
+    // was generated for (0 when there's none). only the disassembler looks at these
+    pub file: u32,
+    pub lines: Vec<u32>,
+}
+
+// where an assignment goes
+enum Place {
+    // a variable kept in its own register
+    Reg(u8),
+    // a temp (or pointer variable) register holding the address
+    Mem(u8),
 }
 
 pub struct LoopContext {
-    // loop condition instruction index
-    loop_start: usize,
+    // loop condition instruction index, None when continue has to jump forward
+    // to a condition that isn't generated yet (do-while)
+    loop_start: Option<usize>,
+
+    // forward continue JMPs, finished once the condition is reached
+    continue_jumps: Vec<usize>,
 
     // indices of JMP instructions that need to be finished
     break_jumps: Vec<usize>,
+
+    // break leaves a switch but continue belongs to the loop around it
+    is_switch: bool,
 }
 
 // builder for compiling single func
@@ -83,8 +500,15 @@ struct FunctionBuilder<'a> {
     /// func name
     name: String,
 
-    /// returned instructs
-    instructions: Vec<Instruction>,
+    /// returned instructs, already packed
+    instructions: Vec<u32>,
+
+    /// source line of each instruction, and the statement being generated
+    lines: Vec<u32>,
+    span: Span,
+
+    /// operands too big for their field, the function can't be encoded
+    errors: Vec<Diagnostic>,
 
     /// static 256 (8bit) registers, so using a bitvec to 
     /// track state. 0 = not in use, 1 = in use
@@ -93,12 +517,31 @@ struct FunctionBuilder<'a> {
     /// var name -> register id
     sym_table: HashMap<String, u8>,
 
+    /// var name -> declared type, so expressions know whether they need float ops
+    var_types: HashMap<String, Type>,
+
+    /// variables that have their address taken somewhere in the function, these live in
+    /// stack memory instead of a register since a register has no address
+    address_taken: HashSet<String>,
+
+    /// var name -> offset of its slot in the stack frame
+    slots: HashMap<String, u32>,
+
+    /// bytes of stack frame used so far
+    frame_size: u32,
+
+    /// every slot handed out so far, named for the checked vm
+    frame_slots: Vec<Slot>,
+
+    /// how many registers the params (and a struct return address) arrive in
+    arity: u8,
+
     /// max register allocated so can 
     /// allocate registers at compile time
     max_reg: u8,
 
     /// array of constants to check during compile time
-    constants: Vec<i64>,
+    constants: Vec<Constant>,
 
     /// set of permanent variable registers
     permanent_regs: HashSet<u8>,
@@ -106,22 +549,74 @@ struct FunctionBuilder<'a> {
     /// stack of loop contexts for nested loops
     loop_stack: Vec<LoopContext>,
 
+    /// label name -> instruction index of the labeled statement
+    labels: HashMap<String, usize>,
+
+    /// goto JMPs and the label they target, patched once the whole function is generated
+    goto_fixups: Vec<(usize, String)>,
+
     /// global function map from the parent code builder
     global_function_map: &'a HashMap<String, usize>,
+
+    /// function name -> param and return types, for converting arguments and typing calls
+    global_signatures: &'a HashMap<String, Signature>,
+
+    /// global variable name -> where it sits in the data segment
+    globals: &'a HashMap<String, Global>,
+
+    /// struct/union layouts and typedefs
+    layouts: &'a Layouts,
+
+    /// string literals, shared by every function
+    rodata: &'a mut Rodata,
+
+    /// hidden first param of a function returning a struct, the caller's slot to copy the result into
+    sret_reg: Option<u8>,
+
+    /// this function's static locals by where they're declared, already placed in the data segment
+    static_decls: HashMap<usize, Global>,
+
+    /// static locals declared so far, by name
+    statics: HashMap<String, Global>,
 }
 
 impl<'a> FunctionBuilder<'a> {
-    fn new(name: String, func_map: &'a HashMap<String, usize>) -> Self {
+    fn new(
+        name: String,
+        func_map: &'a HashMap<String, usize>,
+        signatures: &'a HashMap<String, Signature>,
+        globals: &'a HashMap<String, Global>,
+        layouts: &'a Layouts,
+        rodata: &'a mut Rodata,
+    ) -> Self {
         FunctionBuilder {
             name,
             instructions: vec![],
+            lines: vec![],
+            span: Span::default(),
+            errors: vec![],
             constants: vec![],
             register_state: BitVec::repeat(false, 256),
             sym_table: HashMap::new(),
+            var_types: HashMap::new(),
+            address_taken: HashSet::new(),
+            slots: HashMap::new(),
+            frame_size: 0,
+            frame_slots: vec![],
+            arity: 0,
             max_reg: 0,
             permanent_regs: HashSet::new(),
             loop_stack: vec![],
+            labels: HashMap::new(),
+            goto_fixups: vec![],
             global_function_map: func_map,
+            global_signatures: signatures,
+            globals,
+            layouts,
+            rodata,
+            sret_reg: None,
+            static_decls: HashMap::new(),
+            statics: HashMap::new(),
         }
     }
 
@@ -133,27 +628,20 @@ impl<'a> FunctionBuilder<'a> {
         first as u8
     }
 
+    // a call's registers, the function ref and its args. the callee's frame starts right after
+    // the function ref, so the block goes above every register in use. a first fit hole below
+    // a temporary still waiting on the call's result would let the callee clobber it
     fn allocate_register_block(&mut self, count: u8) -> u8 {
-        let mut start = 0;
-        while start + count as usize <= 256 {
-            let mut found = true;
-            for i in 0..count as usize {
-                if self.register_state[start + i] {
-                    start = start + i + 1;
-                    found = false;
-                    break;
-                }
-            }
-            if found {
-                for i in 0..count as usize {
-                    self.register_state.set(start + i, true);
-                }
-                let end = (start + count as usize - 1) as u8;
-                self.max_reg = self.max_reg.max(end);
-                return start as u8;
-            }
+        let start = self.register_state.last_one().map_or(0, |last| last + 1);
+        if start + count as usize > 256 {
+            panic!("out of registers: could not find {} consecutive free registers", count);
         }
-        panic!("out of registers: could not find {} consecutive free registers", count);
+        for i in 0..count as usize {
+            self.register_state.set(start + i, true);
+        }
+        let end = (start + count as usize - 1) as u8;
+        self.max_reg = self.max_reg.max(end);
+        start as u8
     }
 
     // free register
@@ -165,7 +653,24 @@ impl<'a> FunctionBuilder<'a> {
 
     /// emit instruction to instr vec
     fn emit(&mut self, instr: Instruction) {
-        self.instructions.push(instr);
+        let word = self.encode(instr);
+        self.instructions.push(word);
+        self.lines.push(self.span.line as u32);
+    }
+
+    // an operand too big for its field is a limit of the isa, reported against the statement
+    // and the function carries on with a dummy word so every such statement gets reported
+    fn encode(&mut self, instr: Instruction) -> u32 {
+        if let Err(e) = instr.check_range() {
+            let message = format!("{} in function '{}'", e, self.name);
+            if !self.errors.iter().any(|err| err.span == self.span && err.message == message) {
+                self.errors.push(Diagnostic::error(Code::OperandOutOfRange, self.span, message));
+            }
+            return 0;
+        }
+        let word = instr.encode();
+        debug_assert_eq!(Instruction::decode(word), Some(instr));
+        word
     }
 
     fn emit_jump_placeholder(&mut self) -> usize {
@@ -174,55 +679,119 @@ impl<'a> FunctionBuilder<'a> {
     }
 
     fn finish_jump(&mut self, jump_idx: usize) {
-        let offset = (self.instructions.len() - jump_idx - 1) as i32;
-        self.instructions[jump_idx] = Instruction::AsBx { 
+        self.finish_jump_to(jump_idx, self.instructions.len());
+    }
+
+    fn finish_jumps(&mut self, jumps: Vec<usize>) {
+        for jump_idx in jumps {
+            self.finish_jump(jump_idx);
+        }
+    }
+
+    // same as finish_jump but to any instruction, target can be behind the jump too
+    fn finish_jump_to(&mut self, jump_idx: usize, target: usize) {
+        let offset = target as i32 - jump_idx as i32 - 1;
+        self.instructions[jump_idx] = self.encode(Instruction::AsBx { 
             opcode: OpCode::JMP, 
             offset 
-        };
+        });
+    }
+
+    // labels are function scoped so a goto can point anywhere, even at a label we haven't seen yet.
+    // every goto is a placeholder until the end of the function when all the labels are known
+    // (semantic analysis already made sure each one exists)
+    fn resolve_gotos(&mut self) {
+        for (jump_idx, label) in std::mem::take(&mut self.goto_fixups) {
+            let target = *self.labels.get(&label).expect("goto to unknown label");
+            self.finish_jump_to(jump_idx, target);
+        }
     }
 
     fn add_constant(&mut self, value: i64) -> usize {
         // see if we can reuse constant to save space. this is just compile time cost so not too worried
-        if let Some(idx) = self.constants.iter().position(|&c| c == value) {
+        if let Some(idx) = self.constants.iter().position(|c| matches!(c, Constant::Int(v) if *v == value)) {
             return idx;
         }
-        self.constants.push(value);
+        self.constants.push(Constant::Int(value));
         self.constants.len() - 1
     }
 
-    fn finalize(self) -> FunctionChunk {
-        FunctionChunk {
+    fn add_float_constant(&mut self, value: f64) -> usize {
+        // compare bits so 0.0 and -0.0 stay separate
+        if let Some(idx) = self.constants.iter().position(|c| matches!(c, Constant::Float(v) if v.to_bits() == value.to_bits())) {
+            return idx;
+        }
+        self.constants.push(Constant::Float(value));
+        self.constants.len() - 1
+    }
+
+    fn finalize(self, file: u32) -> Result<FunctionChunk, Vec<Diagnostic>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        Ok(FunctionChunk {
             name: self.name,
             instructions: self.instructions,
             constants: self.constants,
             max_registers: self.max_reg,
-        }
+            frame_size: self.frame_size,
+            slots: self.frame_slots,
+            arity: self.arity,
+            file,
+            lines: self.lines,
+        })
     }
 
     // == compilation :D
 
     pub fn gen_statement(&mut self, stmt: &Statement) {
-        match stmt {
-            Statement::ExprStatement(expr) => {
+        // an instruction belongs to the innermost statement it's generated for, so the jump
+        // back at the end of a loop goes back to being the loop's once the body is done
+        let outer_span = std::mem::replace(&mut self.span, stmt.span);
+        self.gen_statement_kind(stmt);
+        self.span = outer_span;
+    }
+
+    fn gen_statement_kind(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::ExprStatement(expr) => {
                 self.gen_expr(expr, None);
             }
 
             // variable declaration just allocates a permanent register and 
             // stores the right hand side expression in that reg
-            Statement::VarDec(typ, name, expr, storage_class) => {
+            StatementKind::VarDec(typ, name, expr, storage_class, _) => {
+                let var_type = complete_array_type(&self.layouts.resolve(&typ.base), expr.as_ref());
+                self.var_types.insert(name.clone(), var_type.clone());
+
+                // a static was placed and initialized before the program started, the declaration only names it
+                if *storage_class == StorageClass::Static {
+                    let global = self.static_decls[&stmt.span.offset].clone();
+                    self.statics.insert(name.clone(), global);
+                    return;
+                }
+
+                // arrays and structs always need memory, a register only holds one value
+                if in_memory(&var_type) || self.address_taken.contains(name) {
+                    let slot = self.alloc_slot(name, &var_type);
+                    self.slots.insert(name.clone(), slot);
+
+                    if let Some(init_expr) = expr {
+                        self.gen_initializer(slot, &var_type, init_expr);
+                    }
                 // just reuse the expression register for the var reg
-                if let Some(init_expr) = expr {
-                    if matches!(init_expr, Expr::Identifier(_)) {
+                } else if let Some(init_expr) = expr {
+                    if matches!(init_expr.kind, ExprKind::Identifier(_)) {
                         // allocate new register and use target (generates MOV)
                         // this is for something like y = x
                         let var_reg = self.allocate_register();
                         self.permanent_regs.insert(var_reg);
                         self.sym_table.insert(name.clone(), var_reg);
-                        self.gen_expr(init_expr, Some(var_reg));
+                        self.gen_expr_as(init_expr, &typ.base, Some(var_reg));
                     } else {
                         // claim the expression's result register (optimal)
                         // this will help reuse the result register
-                        let expr_reg = self.gen_expr(init_expr, None);
+                        let expr_reg = self.gen_expr_as(init_expr, &typ.base, None);
 
 
                         // Check if expr_reg is already a permanent register (like from ++x)
@@ -250,22 +819,12 @@ impl<'a> FunctionBuilder<'a> {
                 }
             }
 
-            Statement::Assign(lhs, rhs) => {
-                if let Expr::Identifier(name) = lhs {
-                    let var_reg = *self.sym_table.get(name).expect("Variable not found");
-
-                    self.gen_expr(rhs, Some(var_reg));
-                } else {
-                    // for now just doing simple variable assignment
-                    // todo: add array[index] - value
-                    // todo: add ptr->field = value, 
-                    // etc
-                    eprintln!("Unsupported assignment target: {:?}", lhs);
-                    todo!()
-                }
+            StatementKind::Assign(lhs, rhs) => {
+                let value_reg = self.gen_assign(lhs, rhs);
+                self.free_register(value_reg);
             }
 
-            Statement::CompoundAssign(op, lhs, rhs) => {
+            StatementKind::CompoundAssign(op, lhs, rhs) => {
                 // this is easy, just turn the compound op into a binop and gen it
                 let bin_op = match op {
                     CompoundOp::AddAssign => BinOp::Add,
@@ -280,9 +839,12 @@ impl<'a> FunctionBuilder<'a> {
                     CompoundOp::RShiftAssign => BinOp::RShift,
                 };
                 
-                let assign_stmt = Statement::Assign(
-                    *lhs.clone(),
-                    Expr::BinOp(lhs.clone(), bin_op, rhs.clone())
+                let assign_stmt = Statement::new(
+                    StatementKind::Assign(
+                        *lhs.clone(),
+                        Expr::new(ExprKind::BinOp(lhs.clone(), bin_op, rhs.clone()), stmt.span)
+                    ),
+                    stmt.span,
                 );
                 
                 self.gen_statement(&assign_stmt);
@@ -297,12 +859,10 @@ impl<'a> FunctionBuilder<'a> {
             // LT r0(result) r1(x) k0(5) 
             // Test r0
             // Jmp +# << vm will check jump flag and pc++ if cond
-            Statement::If(cond, then_body, else_body) => {
-                let cond_reg = self.gen_expr(cond, None);
-                self.emit(Instruction::ABC { opcode: OpCode::TEST, a: cond_reg, b: 0, c: 0 });
-
-                // this save idx of jump to else for finishing later
-                let jmp_to_else = self.emit_jump_placeholder();
+            // (&& and || conditions turn into a few of these, see gen_branch)
+            StatementKind::If(cond, then_body, else_body) => {
+                // this save idx of jumps to else for finishing later
+                let jmps_to_else = self.gen_branch(cond, false);
 
                 for stmt in then_body {
                     self.gen_statement(stmt);
@@ -311,7 +871,7 @@ impl<'a> FunctionBuilder<'a> {
                 // if having trouble understanding, just generate some simple bytecode and look through it
                 if let Some(else_block) = else_body {
                     let jmp_skip_else = self.emit_jump_placeholder();
-                    self.finish_jump(jmp_to_else);
+                    self.finish_jumps(jmps_to_else);
 
                     for stmt in else_block { 
                         self.gen_statement(stmt);
@@ -319,13 +879,11 @@ impl<'a> FunctionBuilder<'a> {
 
                     self.finish_jump(jmp_skip_else);
                 } else {
-                    self.finish_jump(jmp_to_else);
+                    self.finish_jumps(jmps_to_else);
                 }
-
-                self.free_register(cond_reg);
             }
 
-            Statement::While(cond, then_body) => {
+            StatementKind::While(cond, then_body) => {
                 // ** VERY IMPORTANT **
                 // On every instuction, the vm will increment the program counter
                 // so a JMP -7 actually goes back just *6* places! 
@@ -336,16 +894,15 @@ impl<'a> FunctionBuilder<'a> {
                 
                 // push to loop stack to track contexts
                 self.loop_stack.push(LoopContext {
-                    loop_start,
+                    loop_start: Some(loop_start),
+                    continue_jumps: vec![],
                     break_jumps: vec![],
+                    is_switch: false,
                 });
 
                 // eq r0 r1 r2
                 // test r0
-                let cond_reg = self.gen_expr(cond, None);
-                self.emit(Instruction::ABC { opcode: OpCode::TEST, a: cond_reg, b: 0, c: 0 });
-
-                let exit_loop_jump = self.emit_jump_placeholder();
+                let exit_loop_jumps = self.gen_branch(cond, false);
 
                 for stmt in then_body {
                     self.gen_statement(stmt);
@@ -354,7 +911,7 @@ impl<'a> FunctionBuilder<'a> {
                 let offset = loop_start as i32 - self.instructions.len() as i32 - 1;
                 self.emit(Instruction::AsBx { opcode: OpCode::JMP, offset });
 
-                self.finish_jump(exit_loop_jump);
+                self.finish_jumps(exit_loop_jumps);
 
                 // now that we know where the loop ends we can get the context and patch
                 // all the break jumps that need patching
@@ -362,58 +919,59 @@ impl<'a> FunctionBuilder<'a> {
                 for jump_idx in ctx.break_jumps {
                     self.finish_jump(jump_idx);
                 }
-
-                // dont forget to clear regs
-                self.free_register(cond_reg);
             }
 
-            Statement::For(init, cond , incr , then_body) => {
+            // every clause is optional, for (;;) ends up as just the body and a back edge
+            StatementKind::For(init, cond , incr , then_body) => {
                 if let Some(init_stmt) = init {
                     self.gen_statement(init_stmt);
                 }
 
                 let loop_start = self.instructions.len();
 
+                // continue has to run the increment, which comes after the body
+                // without one it can just go straight back to the condition
                 self.loop_stack.push(LoopContext {
-                    loop_start,
+                    loop_start: if incr.is_some() { None } else { Some(loop_start) },
+                    continue_jumps: vec![],
                     break_jumps: vec![],
+                    is_switch: false,
                 });
 
-                let cond_reg = if let Some(cond_expr) = cond {
-                    let reg = self.gen_expr(cond_expr, None);
-                    self.emit(Instruction::ABC { opcode: OpCode::TEST, a: reg, b: 0, c: 0 });
-                    reg
-                } else {
-                    // infinite loop
-                    panic!("For loop without condition not yet supported");
+                // no condition means no TEST, only break (or return/goto) gets out
+                let exit_loop_jumps = match cond {
+                    Some(cond_expr) => self.gen_branch(cond_expr, false),
+                    None => vec![],
                 };
 
-                let exit_loop_jump = self.emit_jump_placeholder();
-
                 for stmt in then_body {
                     self.gen_statement(stmt);
                 }
 
                 // gen incr after body, before backward jmp
                 if let Some(incr_expr) = incr {
-                    self.gen_expr(incr_expr, None);
+                    let continue_jumps = std::mem::take(&mut self.loop_stack.last_mut().unwrap().continue_jumps);
+                    for jump_idx in continue_jumps {
+                        self.finish_jump(jump_idx);
+                    }
+
+                    let incr_reg = self.gen_expr(incr_expr, None);
+                    self.free_register(incr_reg);
                 }
 
                 let offset = loop_start as i32 - self.instructions.len() as i32 - 1;
                 self.emit(Instruction::AsBx { opcode: OpCode::JMP, offset });
 
-                self.finish_jump(exit_loop_jump);
+                self.finish_jumps(exit_loop_jumps);
 
                 // patch breakss
                 let ctx = self.loop_stack.pop().unwrap();
                 for jump_idx in ctx.break_jumps {
                     self.finish_jump(jump_idx);
                 }
-
-                self.free_register(cond_reg);
             }
 
-            Statement::Break => {
+            StatementKind::Break => {
                 // need to make a jump placeholder and fill it in later
                 // then just add this jump to the loop context's
                 // jumps that need patching
@@ -421,21 +979,41 @@ impl<'a> FunctionBuilder<'a> {
                 self.loop_stack.last_mut().unwrap().break_jumps.push(jump_idx);
             }
 
-            Statement::Continue => {
-                // continue just jumps back to loop start which we already have
-                let loop_start = self.loop_stack.last().unwrap().loop_start;
+            StatementKind::Continue => {
+                // skip over any switches we're inside of, they only catch breaks
+                let ctx_idx = self.loop_stack.iter().rposition(|ctx| !ctx.is_switch).unwrap();
 
-                // no patch needed because we already know everything
-                let offset = loop_start as i32 - self.instructions.len() as i32 - 1;
-                self.emit(Instruction::AsBx { opcode: OpCode::JMP, offset });
+                match self.loop_stack[ctx_idx].loop_start {
+                    // continue just jumps back to loop start which we already have
+                    // no patch needed because we already know everything
+                    Some(loop_start) => {
+                        let offset = loop_start as i32 - self.instructions.len() as i32 - 1;
+                        self.emit(Instruction::AsBx { opcode: OpCode::JMP, offset });
+                    }
+                    None => {
+                        let jump_idx = self.emit_jump_placeholder();
+                        self.loop_stack[ctx_idx].continue_jumps.push(jump_idx);
+                    }
+                }
             }
 
             // returns are pretty straight forward:
             // a is the result register to store the return
             // if b == 1, then it is non void and do the store result
             // else, the vm just skips and jumps back to the caller's PC
-            Statement::Return(expr) => {
-                let result_reg = self.gen_expr(expr, None);
+            StatementKind::Return(expr) => {
+                let return_type = self.global_signatures[&self.name].return_type.clone();
+                let result_reg = self.gen_expr_as(expr, &return_type, None);
+
+                // a struct is copied out to the caller's slot and its address is the return value
+                let result_reg = match self.sret_reg {
+                    Some(sret_reg) => {
+                        self.gen_store(sret_reg, result_reg, &return_type);
+                        self.free_register(result_reg);
+                        sret_reg
+                    }
+                    None => result_reg,
+                };
                 self.emit(Instruction::ABC { 
                     opcode: OpCode::RETURN, 
                     a: result_reg, 
@@ -444,7 +1022,7 @@ impl<'a> FunctionBuilder<'a> {
                 })
             }
 
-            Statement::ReturnVoid => {
+            StatementKind::ReturnVoid => {
                 self.emit(Instruction::ABC { 
                     opcode: OpCode::RETURN, 
                     a: 0,
@@ -453,85 +1031,930 @@ impl<'a> FunctionBuilder<'a> {
                 });
             }
 
-            Statement::Block(stmts) => {
+            StatementKind::Block(stmts) => {
                 for s in stmts {
                     self.gen_statement(s);
                 }
             }
 
-            other => {
-                eprintln!("Unimplemented stateme: {:?}", other);
-                todo!()
-            }
-        }
-    }
+            // do-while runs the body first and checks the condition at the bottom
+            // [body_start:]
+            //   [body instructions]
+            // [cond:]                  continue jumps here
+            //   [condition]
+            //   TEST rX
+            //   JMP +1                 false, skip the back edge
+            //   JMP body_start
+            // [loop_end:]
+            // (a && / || condition branches straight back instead, see gen_branch)
+            StatementKind::DoWhile(do_while_stmt) => {
+                let body_start = self.instructions.len();
 
-    // returns the register, takes optional target register as well
-    pub fn gen_expr(&mut self, expr: &Expr, target: Option<u8>) -> u8 {
-        match expr {
-            Expr::IntLiteral(val) => {
-                let result_reg = target.unwrap_or_else(|| self.allocate_register());
-                let const_idx = self.add_constant(*val);
-                self.emit(
-                    // loadk into dest register, the constant idx
-                    Instruction::ABx { 
-                        opcode: OpCode::LOADK, 
-                        a: result_reg, 
-                        bx: const_idx as u32
-                    }
-                );
-                result_reg
-            }
+                self.loop_stack.push(LoopContext {
+                    loop_start: None,
+                    continue_jumps: vec![],
+                    break_jumps: vec![],
+                    is_switch: false,
+                });
 
-            Expr::BinOp(lhs, op, rhs) => {
-                let left_reg = self.gen_expr(lhs, None);
-                let right_reg = self.gen_expr(rhs, None);
+                for stmt in &do_while_stmt.body {
+                    self.gen_statement(stmt);
+                }
 
-                // seeing if we can save an extra register allocation
-                let result_reg = if let Some(t) = target {
-                    // use target if specified
-                    t
-                } else if !self.permanent_regs.contains(&right_reg) {
-                    // if the right expr is not variable,
-                    // reuse the right reg
-                    right_reg 
-                } else {
-                    // else just make a new register cause we need
-                    self.allocate_register()
-                };
+                // the condition finally exists, so pending continues can land on it
+                let continue_jumps = std::mem::take(&mut self.loop_stack.last_mut().unwrap().continue_jumps);
+                for jump_idx in continue_jumps {
+                    self.finish_jump(jump_idx);
+                }
 
-                let opcode = match op {
-                    BinOp::Add => OpCode::ADD,
-                    BinOp::Sub => OpCode::SUB,
-                    BinOp::Mul => OpCode::MUL,
-                    BinOp::Div => OpCode::DIV,
-                    BinOp::Mod => OpCode::MOD,
+                for jump_idx in self.gen_branch(&do_while_stmt.condition, true) {
+                    self.finish_jump_to(jump_idx, body_start);
+                }
 
-                    BinOp::Eq => OpCode::EQ,
-                    BinOp::Lt => OpCode::LT,
-                    BinOp::Le => OpCode::LE,
-                    BinOp::Gt => OpCode::GT,
-                    BinOp::Ge => OpCode::GE,
-                    BinOp::NotEq => OpCode::NE,
+                let ctx = self.loop_stack.pop().unwrap();
+                for jump_idx in ctx.break_jumps {
+                    self.finish_jump(jump_idx);
+                }
+            }
 
-                    BinOp::BitAnd => OpCode::BAND,
-                    BinOp::BitOr => OpCode::BOR,
-                    BinOp::BitXor => OpCode::BXOR,
-                    BinOp::LShift => OpCode::SHL,
-                    BinOp::RShift => OpCode::SHR,
+            // switch is a dispatch up front (jump table or compare chain) and then every
+            // case body back to back, so fallthrough is just running into the next body
+            //   [dispatch]            JMP to each case body, or to default/end
+            //   [case 1 body]
+            //   [case 2 body]         a break is a JMP to the end
+            //   [default body]
+            // [end:]
+            StatementKind::Switch(switch_stmt) => {
+                let scrutinee = self.gen_expr(&switch_stmt.expr, None);
 
-                    other => {
-                        eprintln!("Unimplemented expression: {:?}", other);
-                        todo!()
-                    }
-                };
+                self.loop_stack.push(LoopContext {
+                    loop_start: None,
+                    continue_jumps: vec![],
+                    break_jumps: vec![],
+                    is_switch: true,
+                });
 
-                self.emit(
-                    Instruction::ABC { opcode, a: result_reg, b: left_reg as u16, c: right_reg as u16}
-                );
+                // jump tables need every case value at compile time
+                let values: Option<Vec<i64>> = switch_stmt.cases.iter()
+                    .filter_map(|case| case.value.as_ref())
+                    .map(case_constant)
+                    .collect();
 
-                // dont forget to free registers! :D >:D >_>
-                // but need to check if the return register is going into a variable!!
+                let (case_jumps, default_jumps) = match values {
+                    Some(values) if is_dense(&values) => self.gen_switch_table(scrutinee, &switch_stmt.cases, &values),
+                    _ => self.gen_switch_chain(scrutinee, &switch_stmt.cases),
+                };
+
+                let mut default_start = None;
+                for (case, jump) in switch_stmt.cases.iter().zip(case_jumps) {
+                    let start = self.instructions.len();
+                    if let Some(jump_idx) = jump {
+                        self.finish_jump_to(jump_idx, start);
+                    }
+                    if case.value.is_none() {
+                        default_start = Some(start);
+                    }
+                    for stmt in &case.stmts {
+                        self.gen_statement(stmt);
+                    }
+                }
+
+                // no default means no match just skips the whole switch
+                let default_target = default_start.unwrap_or(self.instructions.len());
+                for jump_idx in default_jumps {
+                    self.finish_jump_to(jump_idx, default_target);
+                }
+
+                let ctx = self.loop_stack.pop().unwrap();
+                for jump_idx in ctx.break_jumps {
+                    self.finish_jump(jump_idx);
+                }
+
+                self.free_register(scrutinee);
+            }
+
+            StatementKind::Goto(label) => {
+                let jump_idx = self.emit_jump_placeholder();
+                self.goto_fixups.push((jump_idx, label.clone()));
+            }
+
+            // a label doesn't emit anything, it just names where its statement starts
+            StatementKind::Label(label, stmt) => {
+                self.labels.insert(label.clone(), self.instructions.len());
+                self.gen_statement(stmt);
+            }
+
+        }
+    }
+
+    // dense cases index straight into a table of JMPs:
+    //   SUB    rI, rS, K(min)
+    //   JMPTAB rI, N           in range: skip to entry rI, otherwise fall into the default JMP
+    //   JMP    default
+    //   JMP    case min
+    //   ...
+    //   JMP    case max        values without a case go to default
+    // returns the JMP to patch for each case (None for default) and the JMPs that go to default
+    fn gen_switch_table(&mut self, scrutinee: u8, cases: &[Case], values: &[i64]) -> (Vec<Option<usize>>, Vec<usize>) {
+        let min = *values.iter().min().unwrap();
+        let max = *values.iter().max().unwrap();
+
+        let index_reg = if min == 0 {
+            scrutinee
+        } else {
+            let reg = self.allocate_register();
+            let const_idx = self.add_constant(min);
+            self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: reg, bx: const_idx as u32 });
+            self.emit(Instruction::ABC { opcode: OpCode::SUB, a: reg, b: scrutinee as u16, c: reg as u16 });
+            reg
+        };
+
+        let table_len = (max - min + 1) as u32;
+        self.emit(Instruction::ABx { opcode: OpCode::JMPTAB, a: index_reg, bx: table_len });
+        if index_reg != scrutinee {
+            self.free_register(index_reg);
+        }
+
+        let mut default_jumps = vec![self.emit_jump_placeholder()];
+        let mut case_jumps = vec![None; cases.len()];
+        for value in min..=max {
+            let jump_idx = self.emit_jump_placeholder();
+            let case = cases.iter().position(|case| case.value.as_ref().and_then(case_constant) == Some(value));
+            match case {
+                Some(i) => case_jumps[i] = Some(jump_idx),
+                None => default_jumps.push(jump_idx),
+            }
+        }
+
+        (case_jumps, default_jumps)
+    }
+
+    // sparse (or non constant) cases compare one at a time:
+    //   NE   rT, rS, rV
+    //   TEST rT                equal falls into the JMP
+    //   JMP  case
+    //   ...
+    //   JMP  default
+    fn gen_switch_chain(&mut self, scrutinee: u8, cases: &[Case]) -> (Vec<Option<usize>>, Vec<usize>) {
+        let mut case_jumps = vec![None; cases.len()];
+
+        for (i, case) in cases.iter().enumerate() {
+            let Some(value) = &case.value else {
+                continue;
+            };
+
+            let value_reg = match case_constant(value) {
+                Some(constant) => {
+                    let reg = self.allocate_register();
+                    let const_idx = self.add_constant(constant);
+                    self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: reg, bx: const_idx as u32 });
+                    reg
+                }
+                None => self.gen_expr(value, None),
+            };
+
+            let cmp_reg = self.allocate_register();
+            self.emit(Instruction::ABC { opcode: OpCode::NE, a: cmp_reg, b: scrutinee as u16, c: value_reg as u16 });
+            self.emit(Instruction::ABC { opcode: OpCode::TEST, a: cmp_reg, b: 0, c: 0 });
+            case_jumps[i] = Some(self.emit_jump_placeholder());
+
+            self.free_register(cmp_reg);
+            self.free_register(value_reg);
+        }
+
+        (case_jumps, vec![self.emit_jump_placeholder()])
+    }
+
+    // the same types semantic analysis settles on, only what codegen needs to pick opcodes
+    fn expr_type(&self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::IntLiteral(val) => if i32::try_from(*val).is_ok() { Type::Int } else { Type::Long },
+            ExprKind::FloatLiteral(_) => Type::Double,
+            ExprKind::CharLiteral(_) => Type::Char,
+            ExprKind::StringLiteral(text) => Type::Array(Box::new(Type::Char), Some(string_bytes(text).len())),
+            ExprKind::Null => Type::Pointer(Box::new(Type::Void)),
+            ExprKind::Identifier(name) => self.var_type(name),
+
+            ExprKind::BinOp(lhs, op, rhs) => match op {
+                // pointer +/- int stays a pointer, pointer - pointer is a count of elements
+                BinOp::Add | BinOp::Sub if is_pointer_like(&self.expr_type(lhs)) || is_pointer_like(&self.expr_type(rhs)) => {
+                    match (decay(self.expr_type(lhs)), decay(self.expr_type(rhs))) {
+                        (Type::Pointer(_), Type::Pointer(_)) => Type::Long,
+                        (ptr @ Type::Pointer(_), _) | (_, ptr) => ptr,
+                    }
+                }
+                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod
+                    | BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => common_type(&self.expr_type(lhs), &self.expr_type(rhs)),
+                BinOp::LShift | BinOp::RShift => integer_promotion(&self.expr_type(lhs)),
+                // comparisons and && / || are always int
+                _ => Type::Int,
+            },
+
+            ExprKind::UnaryOp(UnaryOp::Not, _) => Type::Int,
+            ExprKind::UnaryOp(UnaryOp::Neg | UnaryOp::BitNot, inner) => integer_promotion(&self.expr_type(inner)),
+            ExprKind::UnaryOp(_, inner) => self.expr_type(inner),
+
+            ExprKind::Assign(lhs, _) | ExprKind::CompoundAssign(_, lhs, _) => self.expr_type(lhs),
+            ExprKind::Ternary(_, then_expr, else_expr) => common_type(&self.expr_type(then_expr), &self.expr_type(else_expr)),
+            ExprKind::Cast(typ, _) => self.layouts.resolve(&typ.base),
+            ExprKind::FieldAccess(obj, field) => self.layouts.field(&self.expr_type(obj), field)
+                .map_or(Type::Int, |field| field.typ.clone()),
+            ExprKind::PtrMember(ptr, field) => match self.expr_type(ptr) {
+                Type::Pointer(record) => self.layouts.field(&record, field).map_or(Type::Int, |field| field.typ.clone()),
+                _ => Type::Int,
+            },
+            ExprKind::AddrOf(inner) => Type::Pointer(Box::new(self.expr_type(inner))),
+            ExprKind::Deref(ptr) | ExprKind::ArrayIndex(ptr, _) => match self.expr_type(ptr) {
+                Type::Pointer(pointee) | Type::Array(pointee, _) => *pointee,
+                // *f on a function is still the function
+                function @ Type::Function { .. } => function,
+                _ => Type::Int,
+            },
+            ExprKind::SizeofType(_) | ExprKind::SizeofExpr(_) => Type::Unsigned(Box::new(Type::Long)),
+
+            ExprKind::Call(func_expr, _) => self.call_signature(func_expr)
+                .map(|sig| sig.return_type)
+                .unwrap_or(Type::Int),
+
+            _ => Type::Int,
+        }
+    }
+
+    // reserves an aligned slot in the stack frame and returns its offset
+    fn alloc_slot(&mut self, name: &str, typ: &Type) -> u32 {
+        let offset = self.frame_size.next_multiple_of(self.layouts.align_of(typ));
+        let size = self.layouts.size_of(typ);
+        self.frame_size = offset + size;
+        self.frame_slots.push(Slot { name: name.to_string(), offset, size });
+        offset
+    }
+
+    // a function in the program, or a builtin the program didn't define itself
+    fn signature(&self, name: &str) -> Option<Signature> {
+        self.global_signatures.get(name).cloned().or_else(|| {
+            Builtin::from_name(name).map(|builtin| Signature { params: builtin.params(), return_type: builtin.return_type() })
+        })
+    }
+
+    // what a call goes through: a function by name, or anything with a function pointer type
+    fn call_signature(&self, func_expr: &Expr) -> Option<Signature> {
+        if let ExprKind::Identifier(name) = &func_expr.kind
+            && !self.is_variable(name) {
+            return self.signature(name);
+        }
+        match self.layouts.resolve(&self.expr_type(func_expr)) {
+            Type::Function { params, return_type } => Some(Signature { params, return_type: *return_type }),
+            Type::Pointer(pointee) => match *pointee {
+                Type::Function { params, return_type } => Some(Signature { params, return_type: *return_type }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn is_function(&self, expr: &Expr) -> bool {
+        matches!(self.layouts.resolve(&self.expr_type(expr)), Type::Function { .. })
+    }
+
+    fn is_variable(&self, name: &str) -> bool {
+        self.var_types.contains_key(name) || self.statics.contains_key(name) || self.globals.contains_key(name)
+    }
+
+    // a function's index when name refers to it and not to a variable that shadows it
+    fn function_index(&self, name: &str) -> Option<usize> {
+        if self.is_variable(name) {
+            return None;
+        }
+        self.global_function_map.get(name).copied()
+    }
+
+    // locals first, they shadow globals of the same name. a function name is a function
+    fn var_type(&self, name: &str) -> Type {
+        self.var_types.get(name)
+            .or_else(|| self.statics.get(name).map(|global| &global.typ))
+            .or_else(|| self.globals.get(name).map(|global| &global.typ))
+            .cloned()
+            .or_else(|| self.global_signatures.get(name).map(|sig| Type::Function {
+                params: sig.params.clone(),
+                return_type: Box::new(sig.return_type.clone()),
+            }))
+            .unwrap_or(Type::Int)
+    }
+
+    // the instruction that gets a variable's address, None for variables kept in a register
+    fn var_address(&self, name: &str) -> Option<(OpCode, u32)> {
+        if let Some(&slot) = self.slots.get(name) {
+            return Some((OpCode::ADDR, slot));
+        }
+        if let Some(global) = self.statics.get(name) {
+            return Some((OpCode::GADDR, global.offset));
+        }
+        if self.sym_table.contains_key(name) {
+            return None;
+        }
+        self.globals.get(name).map(|global| (OpCode::GADDR, global.offset))
+    }
+
+    fn in_register(&self, expr: &Expr) -> bool {
+        matches!(&expr.kind, ExprKind::Identifier(name) if self.var_address(name).is_none())
+    }
+
+    // how far ++/-- moves, a pointer steps over a whole element
+    fn step_size(&self, expr: &Expr) -> i64 {
+        match self.expr_type(expr) {
+            Type::Pointer(pointee) => self.layouts.size_of(&pointee) as i64,
+            _ => 1,
+        }
+    }
+
+    // the value of a memory location, except arrays and structs which are used through their
+    // address so there's nothing to load
+    fn gen_load(&mut self, addr_reg: u8, typ: &Type, target: Option<u8>) -> u8 {
+        let result_reg = target.unwrap_or_else(|| {
+            if self.permanent_regs.contains(&addr_reg) { self.allocate_register() } else { addr_reg }
+        });
+
+        if in_memory(typ) {
+            if result_reg != addr_reg {
+                self.emit(Instruction::ABC { opcode: OpCode::MOV, a: result_reg, b: addr_reg as u16, c: 0 });
+            }
+        } else {
+            self.emit(Instruction::ABC { opcode: OpCode::LOAD, a: result_reg, b: addr_reg as u16, c: Width::of(typ) as u16 });
+        }
+
+        if addr_reg != result_reg {
+            self.free_register(addr_reg);
+        }
+        result_reg
+    }
+
+    // ptr + n or ptr - n, n counts elements so it's scaled by their size first
+    //   LOADK rS, K(size)
+    //   MUL   rS, rN, rS
+    //   ADD   rA, rPtr, rS
+    fn gen_pointer_offset(&mut self, ptr: &Expr, op: OpCode, offset: &Expr, target: Option<u8>) -> u8 {
+        let elem_size = match decay(self.expr_type(ptr)) {
+            Type::Pointer(pointee) => self.layouts.size_of(&pointee),
+            _ => 1,
+        };
+
+        let ptr_reg = self.gen_expr(ptr, None);
+        let offset_reg = self.gen_expr_as(offset, &Type::Long, None);
+        let scaled_reg = if elem_size == 1 {
+            offset_reg
+        } else {
+            let size_reg = self.allocate_register();
+            let const_idx = self.add_constant(elem_size as i64);
+            self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: size_reg, bx: const_idx as u32 });
+            self.emit(Instruction::ABC { opcode: OpCode::MUL, a: size_reg, b: offset_reg as u16, c: size_reg as u16 });
+            self.free_register(offset_reg);
+            size_reg
+        };
+
+        let result_reg = target.unwrap_or_else(|| {
+            if self.permanent_regs.contains(&scaled_reg) { self.allocate_register() } else { scaled_reg }
+        });
+        self.emit(Instruction::ABC { opcode: op, a: result_reg, b: ptr_reg as u16, c: scaled_reg as u16 });
+
+        self.free_register(ptr_reg);
+        if scaled_reg != result_reg {
+            self.free_register(scaled_reg);
+        }
+        result_reg
+    }
+
+    // q - p is the number of elements between them
+    fn gen_pointer_difference(&mut self, lhs: &Expr, rhs: &Expr, target: Option<u8>) -> u8 {
+        let elem_size = match decay(self.expr_type(lhs)) {
+            Type::Pointer(pointee) => self.layouts.size_of(&pointee),
+            _ => 1,
+        };
+
+        let left_reg = self.gen_expr(lhs, None);
+        let right_reg = self.gen_expr(rhs, None);
+        let result_reg = target.unwrap_or_else(|| self.allocate_register());
+        self.emit(Instruction::ABC { opcode: OpCode::SUB, a: result_reg, b: left_reg as u16, c: right_reg as u16 });
+        self.free_register(left_reg);
+        self.free_register(right_reg);
+
+        if elem_size != 1 {
+            let size_reg = self.allocate_register();
+            let const_idx = self.add_constant(elem_size as i64);
+            self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: size_reg, bx: const_idx as u32 });
+            self.emit(Instruction::ABC { opcode: OpCode::DIV, a: result_reg, b: result_reg as u16, c: size_reg as u16 });
+            self.free_register(size_reg);
+        }
+        result_reg
+    }
+
+    // stores a declaration's initializer into a stack slot, brace lists go element by element
+    // and whatever they leave out is zeroed like C says
+    fn gen_initializer(&mut self, offset: u32, typ: &Type, init: &Expr) {
+        match (typ, &init.kind) {
+            // char s[] = "hi" copies the literal out of rodata
+            //   ADDR  rD, frame+offset
+            //   SADDR rS, rodata+K
+            //   LOADK rN, K(len)
+            //   COPY  [rD], [rS], rN
+            (Type::Array(_, size), ExprKind::StringLiteral(text)) => {
+                let len = string_bytes(text).len() as u32;
+                let size = size.map_or(len, |size| size as u32);
+                let copied = len.min(size);
+
+                let dest_reg = self.allocate_register();
+                let src_reg = self.allocate_register();
+                let len_reg = self.allocate_register();
+                let src_offset = self.rodata.intern(text);
+                let const_idx = self.add_constant(copied as i64);
+                self.emit(Instruction::ABx { opcode: OpCode::ADDR, a: dest_reg, bx: offset });
+                self.emit(Instruction::ABx { opcode: OpCode::SADDR, a: src_reg, bx: src_offset });
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: len_reg, bx: const_idx as u32 });
+                self.emit(Instruction::ABC { opcode: OpCode::COPY, a: dest_reg, b: src_reg as u16, c: len_reg as u16 });
+                self.free_register(len_reg);
+                self.free_register(src_reg);
+                self.free_register(dest_reg);
+
+                for i in copied..size {
+                    self.gen_zero_fill(offset + i, &Type::Char);
+                }
+            }
+            (Type::Array(elem, size), ExprKind::InitList(items)) => {
+                let elem_size = self.layouts.size_of(elem);
+                for i in 0..size.unwrap_or(items.len()) as u32 {
+                    let elem_offset = offset + i * elem_size;
+                    match items.get(i as usize) {
+                        Some(item) => self.gen_initializer(elem_offset, elem, item),
+                        None => self.gen_zero_fill(elem_offset, elem),
+                    }
+                }
+            }
+            // a union's list can only set its first field
+            (Type::StructRef(_) | Type::UnionRef(_), ExprKind::InitList(items)) => {
+                let fields: Vec<(u32, Type)> = self.layouts.record(typ)
+                    .map(|record| record.fields.iter().map(|field| (field.offset, field.typ.clone())).collect())
+                    .unwrap_or_default();
+                for (i, (field_offset, field_type)) in fields.iter().enumerate() {
+                    match items.get(i) {
+                        Some(item) => self.gen_initializer(offset + field_offset, field_type, item),
+                        None if matches!(typ, Type::StructRef(_)) => self.gen_zero_fill(offset + field_offset, field_type),
+                        None => {}
+                    }
+                }
+            }
+            _ => {
+                let value_reg = self.gen_expr_as(init, typ, None);
+                self.gen_slot_store(offset, typ, value_reg);
+                self.free_register(value_reg);
+            }
+        }
+    }
+
+    fn gen_zero_fill(&mut self, offset: u32, typ: &Type) {
+        if let Type::Array(elem, Some(count)) = typ {
+            let elem_size = self.layouts.size_of(elem);
+            for i in 0..*count as u32 {
+                self.gen_zero_fill(offset + i * elem_size, elem);
+            }
+            return;
+        }
+        if let Some(record) = self.layouts.record(typ) {
+            let fields: Vec<(u32, Type)> = record.fields.iter().map(|field| (field.offset, field.typ.clone())).collect();
+            for (field_offset, field_type) in fields {
+                self.gen_zero_fill(offset + field_offset, &field_type);
+            }
+            return;
+        }
+
+        let zero_reg = self.allocate_register();
+        let const_idx = self.add_constant(0);
+        self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: zero_reg, bx: const_idx as u32 });
+        self.gen_slot_store(offset, typ, zero_reg);
+        self.free_register(zero_reg);
+    }
+
+    fn gen_slot_store(&mut self, offset: u32, typ: &Type, value_reg: u8) {
+        let addr_reg = self.allocate_register();
+        self.emit(Instruction::ABx { opcode: OpCode::ADDR, a: addr_reg, bx: offset });
+        self.gen_store(addr_reg, value_reg, typ);
+        self.free_register(addr_reg);
+    }
+
+    // a struct value is its address, so storing one copies the whole thing
+    //   LOADK rS, K(size)
+    //   COPY  [rA], [rV], rS
+    fn gen_store(&mut self, addr_reg: u8, value_reg: u8, typ: &Type) {
+        if self.layouts.record(typ).is_some() {
+            let size_reg = self.allocate_register();
+            let const_idx = self.add_constant(self.layouts.size_of(typ) as i64);
+            self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: size_reg, bx: const_idx as u32 });
+            self.emit(Instruction::ABC { opcode: OpCode::COPY, a: addr_reg, b: value_reg as u16, c: size_reg as u16 });
+            self.free_register(size_reg);
+        } else {
+            self.emit(Instruction::ABC { opcode: OpCode::STORE, a: addr_reg, b: value_reg as u16, c: Width::of(typ) as u16 });
+        }
+    }
+
+    // address of s.field or p->field, the struct's address plus the field's offset
+    fn gen_member_addr(&mut self, base: &Expr, through_pointer: bool, field: &str) -> u8 {
+        let record_type = match self.expr_type(base) {
+            Type::Pointer(pointee) if through_pointer => *pointee,
+            typ => typ,
+        };
+        let offset = self.layouts.field(&record_type, field).expect("field checked by semantic analysis").offset;
+
+        // a struct variable's address is already a constant, fold the offset into it
+        if let (false, ExprKind::Identifier(name)) = (through_pointer, &base.kind)
+            && let Some((opcode, bx)) = self.var_address(name) {
+            let addr_reg = self.allocate_register();
+            self.emit(Instruction::ABx { opcode, a: addr_reg, bx: bx + offset });
+            return addr_reg;
+        }
+
+        let base_reg = self.gen_expr(base, None);
+        if offset == 0 {
+            return base_reg;
+        }
+
+        let addr_reg = if self.permanent_regs.contains(&base_reg) { self.allocate_register() } else { base_reg };
+        let offset_reg = self.allocate_register();
+        let const_idx = self.add_constant(offset as i64);
+        self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: offset_reg, bx: const_idx as u32 });
+        self.emit(Instruction::ABC { opcode: OpCode::ADD, a: addr_reg, b: base_reg as u16, c: offset_reg as u16 });
+        self.free_register(offset_reg);
+        addr_reg
+    }
+
+    // where an lvalue lives, a variable's own register or an address in memory
+    fn gen_place(&mut self, expr: &Expr) -> Place {
+        match &expr.kind {
+            ExprKind::Identifier(name) => match self.var_address(name) {
+                Some((opcode, bx)) => {
+                    let addr_reg = self.allocate_register();
+                    self.emit(Instruction::ABx { opcode, a: addr_reg, bx });
+                    Place::Mem(addr_reg)
+                }
+                None => Place::Reg(*self.sym_table.get(name).expect("Variable not found")),
+            },
+
+            // the pointer's value is the address, even if it's sitting in a variable's register
+            ExprKind::Deref(ptr) => Place::Mem(self.gen_expr(ptr, None)),
+
+            // arr[i] is *(arr + i)
+            ExprKind::ArrayIndex(arr, index) => Place::Mem(self.gen_pointer_offset(arr, OpCode::ADD, index, None)),
+
+            ExprKind::FieldAccess(obj, field) => Place::Mem(self.gen_member_addr(obj, false, field)),
+            ExprKind::PtrMember(ptr, field) => Place::Mem(self.gen_member_addr(ptr, true, field)),
+
+            other => {
+                eprintln!("Unsupported assignment target: {:?}", other);
+                todo!()
+            }
+        }
+    }
+
+    // stores rhs into lhs and returns the register holding the stored value
+    fn gen_assign(&mut self, lhs: &Expr, rhs: &Expr) -> u8 {
+        let typ = self.expr_type(lhs);
+        match self.gen_place(lhs) {
+            Place::Reg(var_reg) => self.gen_expr_as(rhs, &typ, Some(var_reg)),
+            Place::Mem(addr_reg) => {
+                let value_reg = self.gen_expr_as(rhs, &typ, None);
+                self.gen_store(addr_reg, value_reg, &typ);
+                self.free_register(addr_reg);
+                value_reg
+            }
+        }
+    }
+
+    // ++/-- on a value in memory:
+    //   LOAD  rOld, [rAddr]
+    //   ADD   rNew, rOld, K(1)
+    //   STORE [rAddr], rNew
+    // the prefix forms give back rNew, the postfix ones rOld
+    fn gen_memory_step(&mut self, op: &UnaryOp, expr: &Expr, target: Option<u8>) -> u8 {
+        let typ = self.expr_type(expr);
+        let width = Width::of(&typ) as u16;
+        let Place::Mem(addr_reg) = self.gen_place(expr) else {
+            unreachable!("in_register already handled register variables");
+        };
+
+        let old_reg = self.allocate_register();
+        self.emit(Instruction::ABC { opcode: OpCode::LOAD, a: old_reg, b: addr_reg as u16, c: width });
+
+        let new_reg = self.allocate_register();
+        let const_idx = self.add_constant(self.step_size(expr));
+        self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: new_reg, bx: const_idx as u32 });
+        let opcode = if matches!(op, UnaryOp::PreInc | UnaryOp::PostInc) { OpCode::ADD } else { OpCode::SUB };
+        self.emit(Instruction::ABC { opcode, a: new_reg, b: old_reg as u16, c: new_reg as u16 });
+        self.wrap_to_type(new_reg, &typ);
+        self.emit(Instruction::ABC { opcode: OpCode::STORE, a: addr_reg, b: new_reg as u16, c: width });
+        self.free_register(addr_reg);
+
+        let (result_reg, other_reg) = if matches!(op, UnaryOp::PreInc | UnaryOp::PreDec) {
+            (new_reg, old_reg)
+        } else {
+            (old_reg, new_reg)
+        };
+        self.free_register(other_reg);
+
+        match target {
+            Some(t) => {
+                self.emit(Instruction::ABC { opcode: OpCode::MOV, a: t, b: result_reg as u16, c: 0 });
+                self.free_register(result_reg);
+                t
+            }
+            None => result_reg,
+        }
+    }
+
+    // the vm computes in 64 bits, this brings an int result back into its type's range in place
+    // (int overflow wraps, unsigned char 255 + 1 is 0, etc)
+    fn wrap_to_type(&mut self, reg: u8, typ: &Type) {
+        match int_layout(typ) {
+            Some((bits, unsigned)) if bits < 64 => {
+                let opcode = if unsigned { OpCode::ZEXT } else { OpCode::SEXT };
+                self.emit(Instruction::ABC { opcode, a: reg, b: reg as u16, c: bits });
+            }
+            _ => {}
+        }
+    }
+
+    // gen_expr, then convert the result to `to` (assignments, returns, arguments, casts, mixed operands)
+    fn gen_expr_as(&mut self, expr: &Expr, to: &Type, target: Option<u8>) -> u8 {
+        let from = self.expr_type(expr);
+        let steps = conversion_steps(&from, to);
+        if steps.is_empty() {
+            return self.gen_expr(expr, target);
+        }
+
+        // int literals headed for a float can just be float constants
+        if let (ExprKind::IntLiteral(val), Some(Type::Double)) = (&expr.kind, float_target(to)) {
+            let result_reg = target.unwrap_or_else(|| self.allocate_register());
+            let const_idx = self.add_float_constant(*val as f64);
+            self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: const_idx as u32 });
+            return result_reg;
+        }
+
+        // convert in place when it's a temp, variables have to keep their own value
+        let src_reg = self.gen_expr(expr, None);
+        let result_reg = target.unwrap_or_else(|| {
+            if self.permanent_regs.contains(&src_reg) { self.allocate_register() } else { src_reg }
+        });
+
+        let mut from_reg = src_reg;
+        for (opcode, bits) in steps {
+            self.emit(Instruction::ABC { opcode, a: result_reg, b: from_reg as u16, c: bits });
+            from_reg = result_reg;
+        }
+
+        if src_reg != result_reg {
+            self.free_register(src_reg);
+        }
+        result_reg
+    }
+
+    // compiles a condition straight into jumps instead of a 0/1 value
+    // emits code that jumps when the condition is jump_if and falls through otherwise,
+    // returning the JMPs that still need a target
+    //
+    // a && b with jump_if false is just both halves jumping to the same place:
+    //   [a] TEST rA, JMP false
+    //   [b] TEST rB, JMP false
+    // and the right side never runs once the left has decided
+    fn gen_branch(&mut self, cond: &Expr, jump_if: bool) -> Vec<usize> {
+        match &cond.kind {
+            ExprKind::BinOp(lhs, op @ (BinOp::And | BinOp::Or), rhs) => {
+                // && can only jump on false early, || only on true
+                let short_circuit = matches!(op, BinOp::Or);
+                if short_circuit == jump_if {
+                    let mut jumps = self.gen_branch(lhs, jump_if);
+                    jumps.extend(self.gen_branch(rhs, jump_if));
+                    jumps
+                } else {
+                    // the left side deciding means skipping the right side and falling through
+                    let skip_rhs = self.gen_branch(lhs, short_circuit);
+                    let jumps = self.gen_branch(rhs, jump_if);
+                    self.finish_jumps(skip_rhs);
+                    jumps
+                }
+            }
+
+            ExprKind::UnaryOp(UnaryOp::Not, inner) => self.gen_branch(inner, !jump_if),
+
+            _ => {
+                // TEST skips the next instruction when the value is nonzero
+                let mut cond_reg = self.gen_expr(cond, None);
+
+                // TEST looks at raw bits, and -0.0 has some set, so compare floats against 0.0 properly
+                if is_float_type(&self.expr_type(cond)) {
+                    let zero_reg = self.allocate_register();
+                    let const_idx = self.add_float_constant(0.0);
+                    self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: zero_reg, bx: const_idx as u32 });
+                    self.emit(Instruction::ABC { opcode: OpCode::FNE, a: zero_reg, b: cond_reg as u16, c: zero_reg as u16 });
+                    self.free_register(cond_reg);
+                    cond_reg = zero_reg;
+                }
+
+                self.emit(Instruction::ABC { opcode: OpCode::TEST, a: cond_reg, b: 0, c: 0 });
+                self.free_register(cond_reg);
+
+                if jump_if {
+                    self.emit(Instruction::AsBx { opcode: OpCode::JMP, offset: 1 });
+                }
+                vec![self.emit_jump_placeholder()]
+            }
+        }
+    }
+
+    // returns the register, takes optional target register as well
+    pub fn gen_expr(&mut self, expr: &Expr, target: Option<u8>) -> u8 {
+        match &expr.kind {
+            ExprKind::IntLiteral(val) => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let const_idx = self.add_constant(*val);
+                self.emit(
+                    // loadk into dest register, the constant idx
+                    Instruction::ABx { 
+                        opcode: OpCode::LOADK, 
+                        a: result_reg, 
+                        bx: const_idx as u32
+                    }
+                );
+                result_reg
+            }
+
+            ExprKind::Null => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let zero_idx = self.add_constant(0);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: zero_idx as u32 });
+                result_reg
+            }
+
+            // as a value && and || are still jumps, they just land on a LOADK of 0 or 1
+            //   [branch on false]
+            //   LOADK rX, 1
+            //   JMP +1
+            // [false:]
+            //   LOADK rX, 0
+            ExprKind::BinOp(_, BinOp::And | BinOp::Or, _) => {
+                let false_jumps = self.gen_branch(expr, false);
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+
+                let one_idx = self.add_constant(1);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: one_idx as u32 });
+                let skip_false = self.emit_jump_placeholder();
+
+                self.finish_jumps(false_jumps);
+                let zero_idx = self.add_constant(0);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: zero_idx as u32 });
+                self.finish_jump(skip_false);
+
+                result_reg
+            }
+
+            ExprKind::Cast(typ, inner) => self.gen_expr_as(inner, &self.layouts.resolve(&typ.base), target),
+
+            ExprKind::CharLiteral(c) => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let const_idx = self.add_constant(*c as i64);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: const_idx as u32 });
+                result_reg
+            }
+
+            // a string literal is the address of its bytes in rodata
+            ExprKind::StringLiteral(text) => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let offset = self.rodata.intern(text);
+                self.emit(Instruction::ABx { opcode: OpCode::SADDR, a: result_reg, bx: offset });
+                result_reg
+            }
+
+            ExprKind::FloatLiteral(val) => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let const_idx = self.add_float_constant(*val);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: const_idx as u32 });
+                result_reg
+            }
+
+            ExprKind::BinOp(lhs, op @ (BinOp::Add | BinOp::Sub), rhs)
+                if is_pointer_like(&self.expr_type(lhs)) || is_pointer_like(&self.expr_type(rhs)) =>
+            {
+                let (lhs_type, rhs_type) = (self.expr_type(lhs), self.expr_type(rhs));
+                let opcode = if *op == BinOp::Add { OpCode::ADD } else { OpCode::SUB };
+                match (is_pointer_like(&lhs_type), is_pointer_like(&rhs_type)) {
+                    (true, true) => self.gen_pointer_difference(lhs, rhs, target),
+                    (true, false) => self.gen_pointer_offset(lhs, opcode, rhs, target),
+                    _ => self.gen_pointer_offset(rhs, opcode, lhs, target),
+                }
+            }
+
+            ExprKind::SizeofType(typ) => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let const_idx = self.add_constant(self.layouts.size_of(&typ.base) as i64);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: const_idx as u32 });
+                result_reg
+            }
+
+            // the operand is never evaluated, only its type matters
+            ExprKind::SizeofExpr(inner) => {
+                let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                let const_idx = self.add_constant(self.layouts.size_of(&self.expr_type(inner)) as i64);
+                self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: result_reg, bx: const_idx as u32 });
+                result_reg
+            }
+
+            ExprKind::BinOp(lhs, op, rhs) => {
+                // the operands decide int or float ops, not the result (a < b is an int either way)
+                // both sides get converted to the common type first, except shifts which keep the left type
+                let (left_reg, right_reg, operand_type) = if matches!(op, BinOp::LShift | BinOp::RShift) {
+                    let operand_type = integer_promotion(&self.expr_type(lhs));
+                    (self.gen_expr(lhs, None), self.gen_expr(rhs, None), operand_type)
+                } else {
+                    let (lhs_type, rhs_type) = (self.expr_type(lhs), self.expr_type(rhs));
+                    // pointers compare as plain unsigned addresses
+                    let operand_type = if is_pointer_like(&lhs_type) || is_pointer_like(&rhs_type) {
+                        Type::Unsigned(Box::new(Type::Long))
+                    } else {
+                        common_type(&lhs_type, &rhs_type)
+                    };
+                    let left_reg = self.gen_expr_as(lhs, &operand_type, None);
+                    let right_reg = self.gen_expr_as(rhs, &operand_type, None);
+                    (left_reg, right_reg, operand_type)
+                };
+                let is_float = is_float_type(&operand_type);
+                let is_unsigned = matches!(operand_type, Type::Unsigned(_));
+
+                // seeing if we can save an extra register allocation
+                let result_reg = if let Some(t) = target {
+                    // use target if specified
+                    t
+                } else if !self.permanent_regs.contains(&right_reg) {
+                    // if the right expr is not variable,
+                    // reuse the right reg
+                    right_reg 
+                } else {
+                    // else just make a new register cause we need
+                    self.allocate_register()
+                };
+
+                let opcode = match op {
+                    BinOp::Add if is_float => OpCode::FADD,
+                    BinOp::Sub if is_float => OpCode::FSUB,
+                    BinOp::Mul if is_float => OpCode::FMUL,
+                    BinOp::Div if is_float => OpCode::FDIV,
+
+                    BinOp::Eq if is_float => OpCode::FEQ,
+                    BinOp::Lt if is_float => OpCode::FLT,
+                    BinOp::Le if is_float => OpCode::FLE,
+                    BinOp::Gt if is_float => OpCode::FGT,
+                    BinOp::Ge if is_float => OpCode::FGE,
+                    BinOp::NotEq if is_float => OpCode::FNE,
+
+                    BinOp::Div if is_unsigned => OpCode::DIVU,
+                    BinOp::Mod if is_unsigned => OpCode::MODU,
+                    BinOp::RShift if is_unsigned => OpCode::SHRU,
+                    BinOp::Lt if is_unsigned => OpCode::LTU,
+                    BinOp::Le if is_unsigned => OpCode::LEU,
+                    BinOp::Gt if is_unsigned => OpCode::GTU,
+                    BinOp::Ge if is_unsigned => OpCode::GEU,
+
+                    BinOp::Add => OpCode::ADD,
+                    BinOp::Sub => OpCode::SUB,
+                    BinOp::Mul => OpCode::MUL,
+                    BinOp::Div => OpCode::DIV,
+                    BinOp::Mod => OpCode::MOD,
+
+                    BinOp::Eq => OpCode::EQ,
+                    BinOp::Lt => OpCode::LT,
+                    BinOp::Le => OpCode::LE,
+                    BinOp::Gt => OpCode::GT,
+                    BinOp::Ge => OpCode::GE,
+                    BinOp::NotEq => OpCode::NE,
+
+                    BinOp::BitAnd => OpCode::BAND,
+                    BinOp::BitOr => OpCode::BOR,
+                    BinOp::BitXor => OpCode::BXOR,
+                    BinOp::LShift => OpCode::SHL,
+                    BinOp::RShift => OpCode::SHR,
+
+                    other => {
+                        eprintln!("Unimplemented expression: {:?}", other);
+                        todo!()
+                    }
+                };
+
+                self.emit(
+                    Instruction::ABC { opcode, a: result_reg, b: left_reg as u16, c: right_reg as u16}
+                );
+
+                // these can leave the type's range (int overflow, INT_MIN / -1, shifting bits out),
+                // the rest can't so they don't need wrapping
+                if !is_float && matches!(op, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::LShift) {
+                    self.wrap_to_type(result_reg, &operand_type);
+                }
+
+                // dont forget to free registers! :D >:D >_>
+                // but need to check if the return register is going into a variable!!
                 // so make sure youre not clearing permanent var regs
                 self.free_register(left_reg);
 
@@ -545,7 +1968,21 @@ impl<'a> FunctionBuilder<'a> {
             // if we see an identifier, we need the value
             // so get the register of where that value lives
             // and move it into the target and return the register
-            Expr::Identifier(name) => {
+            ExprKind::Identifier(name) => {
+                // a function name is its reference, the same thing a direct call puts in the call's base
+                if let Some(func_idx) = self.function_index(name) {
+                    let result_reg = target.unwrap_or_else(|| self.allocate_register());
+                    self.emit(Instruction::ABx { opcode: OpCode::CLOSURE, a: result_reg, bx: func_idx as u32 });
+                    return result_reg;
+                }
+
+                // spilled variables and globals get loaded out of memory
+                if let Some((opcode, bx)) = self.var_address(name) {
+                    let addr_reg = target.unwrap_or_else(|| self.allocate_register());
+                    self.emit(Instruction::ABx { opcode, a: addr_reg, bx });
+                    return self.gen_load(addr_reg, &self.var_type(name), Some(addr_reg));
+                }
+
                 let var_reg = *self.sym_table.get(name).unwrap();
 
                 if let Some(target) = target {
@@ -563,20 +2000,61 @@ impl<'a> FunctionBuilder<'a> {
                 }
             }
 
-            Expr::Assign(lhs, rhs) => {
-                if let Expr::Identifier(name) = lhs.as_ref() {
-                    let var_reg = *self.sym_table.get(name).expect("Variable not found");
+            ExprKind::Assign(lhs, rhs) => {
+                let value_reg = self.gen_assign(lhs, rhs);
+                match target {
+                    Some(t) if t != value_reg => {
+                        self.emit(Instruction::ABC { opcode: OpCode::MOV, a: t, b: value_reg as u16, c: 0 });
+                        self.free_register(value_reg);
+                        t
+                    }
+                    _ => value_reg,
+                }
+            }
+
+            // *f and &f are still just f, calling through a function pointer doesn't need a load
+            ExprKind::Deref(inner) if self.is_function(expr) => self.gen_expr(inner, target),
+            ExprKind::AddrOf(inner) if self.is_function(inner) => self.gen_expr(inner, target),
 
-                    self.gen_expr(rhs, Some(var_reg));
+            // *p is a load from whatever address p holds
+            ExprKind::Deref(ptr) => {
+                let typ = self.expr_type(expr);
+                let addr_reg = self.gen_expr(ptr, None);
+                self.gen_load(addr_reg, &typ, target)
+            }
 
-                    var_reg
-                } else {
-                    eprintln!("Unsupported assignment target: {:?}", lhs);
-                    todo!()
-                }
+            ExprKind::ArrayIndex(arr, index) => {
+                let typ = self.expr_type(expr);
+                let addr_reg = self.gen_pointer_offset(arr, OpCode::ADD, index, None);
+                self.gen_load(addr_reg, &typ, target)
+            }
+
+            ExprKind::FieldAccess(obj, field) => {
+                let typ = self.expr_type(expr);
+                let addr_reg = self.gen_member_addr(obj, false, field);
+                self.gen_load(addr_reg, &typ, target)
+            }
+
+            ExprKind::PtrMember(ptr, field) => {
+                let typ = self.expr_type(expr);
+                let addr_reg = self.gen_member_addr(ptr, true, field);
+                self.gen_load(addr_reg, &typ, target)
             }
 
-            Expr::CompoundAssign(op, lhs, rhs) => {
+            ExprKind::AddrOf(inner) => match self.gen_place(inner) {
+                Place::Mem(addr_reg) => match target {
+                    Some(t) if t != addr_reg => {
+                        self.emit(Instruction::ABC { opcode: OpCode::MOV, a: t, b: addr_reg as u16, c: 0 });
+                        self.free_register(addr_reg);
+                        t
+                    }
+                    _ => addr_reg,
+                },
+                // address_taken spills every variable that & is used on, so this can't happen
+                Place::Reg(_) => panic!("address of a register variable"),
+            },
+
+            ExprKind::CompoundAssign(op, lhs, rhs) => {
                 let bin_op = match op {
                     CompoundOp::AddAssign => BinOp::Add,
                     CompoundOp::SubAssign => BinOp::Sub,
@@ -590,44 +2068,76 @@ impl<'a> FunctionBuilder<'a> {
                     CompoundOp::RShiftAssign => BinOp::RShift,
                 };
                 
-                let assign_expr = Expr::Assign(
-                    lhs.clone(),
-                    Box::new(Expr::BinOp(lhs.clone(), bin_op, rhs.clone()))
+                let assign_expr = Expr::new(
+                    ExprKind::Assign(
+                        lhs.clone(),
+                        Box::new(Expr::new(ExprKind::BinOp(lhs.clone(), bin_op, rhs.clone()), expr.span))
+                    ),
+                    expr.span,
                 );
                 
                 self.gen_expr(&assign_expr, target)
             }
 
-            Expr::Call(func_expr, args) => {
+            ExprKind::Call(func_expr, args) => {
+                // a struct result needs somewhere to go, the callee gets this slot's address ahead of the args
+                let signature = self.call_signature(func_expr);
+                let return_type = signature.as_ref().map(|sig| sig.return_type.clone());
+                let sret_slot = return_type
+                    .filter(|typ| self.layouts.record(typ).is_some())
+                    .map(|typ| self.alloc_slot("(struct return)", &typ));
+                let first_arg = 1 + sret_slot.is_some() as u8;
+
                 // need to allocate a full register block for this since the
                 // vm will just take the register count and scan, not individual registers
-                let block_size = 1 + args.len() as u8;
+                let block_size = first_arg + args.len() as u8;
                 let base = self.allocate_register_block(block_size);
 
-                // get func ref
-                if let Expr::Identifier(name) = func_expr.as_ref() {
-                    let func_idx = *self.global_function_map.get(name)
-                        .expect(&format!("Unknown function: {}", name));
-                    self.emit(Instruction::ABx {
-                        opcode: OpCode::CLOSURE,
-                        a: base,
-                        bx: func_idx as u32,
-                    });
-                } else {
-                    panic!("only direct function calls are supported for now");
+                // get func ref, a builtin has none and gets called by its code instead.
+                // anything else is a function pointer, CALL checks what it holds at runtime
+                let mut builtin = None;
+                match &func_expr.kind {
+                    ExprKind::Identifier(name) if !self.is_variable(name) => match self.global_function_map.get(name) {
+                        Some(&func_idx) => self.emit(Instruction::ABx {
+                            opcode: OpCode::CLOSURE,
+                            a: base,
+                            bx: func_idx as u32,
+                        }),
+                        None => builtin = Some(Builtin::from_name(name).unwrap_or_else(|| panic!("Unknown function: {}", name))),
+                    },
+                    _ => {
+                        self.gen_expr(func_expr, Some(base));
+                    }
                 }
 
                 // generating parameters into their allocated registers
+                // converted to the parameter types like an assignment would
+                let param_types = signature.map(|sig| sig.params);
+                if let Some(slot) = sret_slot {
+                    self.emit(Instruction::ABx { opcode: OpCode::ADDR, a: base + 1, bx: slot });
+                }
                 for (i, arg) in args.iter().enumerate() {
-                    self.gen_expr(arg, Some(base + 1 + i as u8));
+                    let arg_reg = base + first_arg + i as u8;
+                    match param_types.as_ref().and_then(|params| params.get(i)) {
+                        Some(param_type) => self.gen_expr_as(arg, param_type, Some(arg_reg)),
+                        None => self.gen_expr(arg, Some(arg_reg)),
+                    };
                 }
 
-                self.emit(Instruction::ABC {
-                    opcode: OpCode::CALL,
-                    a: base,
-                    b: (args.len() + 1) as u16,
-                    c: 2,
-                });
+                match builtin {
+                    Some(builtin) => self.emit(Instruction::ABC {
+                        opcode: OpCode::CALLN,
+                        a: base,
+                        b: block_size as u16,
+                        c: builtin as u16,
+                    }),
+                    None => self.emit(Instruction::ABC {
+                        opcode: OpCode::CALL,
+                        a: base,
+                        b: block_size as u16,
+                        c: 2,
+                    }),
+                }
 
                 for i in 1..block_size {
                     self.register_state.set((base + i) as usize, false);
@@ -649,12 +2159,21 @@ impl<'a> FunctionBuilder<'a> {
                 }
             }
 
-            Expr::UnaryOp(op, expr) => {
+            // ++/-- on anything living in memory is a load, add and store back
+            ExprKind::UnaryOp(op @ (UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec), inner)
+                if !self.in_register(inner) => self.gen_memory_step(op, inner, target),
+
+            ExprKind::UnaryOp(op, expr) => {
                 match op {
                     UnaryOp::Neg => {
+                        let typ = integer_promotion(&self.expr_type(expr));
+                        let opcode = if is_float_type(&typ) { OpCode::FUNM } else { OpCode::UNM };
                         let expr_reg = self.gen_expr(expr, None);
                         let result_reg = target.unwrap_or_else(|| self.allocate_register());
-                        self.emit(Instruction::ABC { opcode: OpCode::UNM, a:result_reg , b: expr_reg as u16, c: 0 });
+                        self.emit(Instruction::ABC { opcode, a:result_reg , b: expr_reg as u16, c: 0 });
+                        if !is_float_type(&typ) {
+                            self.wrap_to_type(result_reg, &typ);
+                        }
                         result_reg
                     }
 
@@ -669,19 +2188,23 @@ impl<'a> FunctionBuilder<'a> {
                         let expr_reg = self.gen_expr(expr, None);
                         let result_reg = target.unwrap_or_else(|| self.allocate_register());
                         self.emit(Instruction::ABC { opcode: OpCode::BNOT, a:result_reg , b: expr_reg as u16, c: 0 });
+                        // ~ sets the high bits of an unsigned value
+                        let typ = integer_promotion(&self.expr_type(expr));
+                        self.wrap_to_type(result_reg, &typ);
                         result_reg
                     }
 
                     // decrement from the variable's permanent register and return said perm reg
                     UnaryOp::PreInc => {
-                        if let Expr::Identifier(name) = expr.as_ref() {
+                        if let ExprKind::Identifier(name) = &expr.kind {
                             let var_reg = *self.sym_table.get(name).expect("Variable not found");
 
-                            let const_idx = self.add_constant(1);
+                            let const_idx = self.add_constant(self.step_size(expr));
                             let one_reg = self.allocate_register();
 
                             self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: one_reg, bx: const_idx as u32 });
                             self.emit(Instruction::ABC { opcode: OpCode::ADD, a: var_reg, b: var_reg as u16, c: one_reg as u16 });
+                            self.wrap_to_type(var_reg, &self.expr_type(expr));
 
                             self.free_register(one_reg);
                             var_reg
@@ -691,14 +2214,15 @@ impl<'a> FunctionBuilder<'a> {
                     }
 
                     UnaryOp::PreDec => {
-                        if let Expr::Identifier(name) = expr.as_ref() {
+                        if let ExprKind::Identifier(name) = &expr.kind {
                             let var_reg = *self.sym_table.get(name).expect("Variable not found");
 
-                            let const_idx = self.add_constant(1);
+                            let const_idx = self.add_constant(self.step_size(expr));
                             let one_reg = self.allocate_register();
 
                             self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: one_reg, bx: const_idx as u32 });
                             self.emit(Instruction::ABC { opcode: OpCode::SUB, a: var_reg, b: var_reg as u16, c: one_reg as u16 });
+                            self.wrap_to_type(var_reg, &self.expr_type(expr));
 
                             self.free_register(one_reg);
                             var_reg
@@ -711,16 +2235,17 @@ impl<'a> FunctionBuilder<'a> {
                     // then decrement the value in the permanent reg
                     // and return the temp register with the old value
                     UnaryOp::PostDec => {
-                        if let Expr::Identifier(name) = expr.as_ref() {
+                        if let ExprKind::Identifier(name) = &expr.kind {
                             let var_reg = *self.sym_table.get(name).expect("Variable not ofund");
                             let temp_reg = self.allocate_register();
 
-                            let const_idx = self.add_constant(1);
+                            let const_idx = self.add_constant(self.step_size(expr));
                             let one_reg = self.allocate_register();
 
                             self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: one_reg, bx: const_idx as u32 });
                             self.emit(Instruction::ABC { opcode: OpCode::MOV, a: temp_reg, b: var_reg as u16, c: 0 });
                             self.emit(Instruction::ABC { opcode: OpCode::SUB, a: var_reg, b: var_reg as u16, c: one_reg as u16 });
+                            self.wrap_to_type(var_reg, &self.expr_type(expr));
 
                             self.free_register(one_reg);
                             temp_reg
@@ -730,16 +2255,17 @@ impl<'a> FunctionBuilder<'a> {
                     }
 
                     UnaryOp::PostInc => {
-                        if let Expr::Identifier(name) = expr.as_ref() {
+                        if let ExprKind::Identifier(name) = &expr.kind {
                             let var_reg = *self.sym_table.get(name).expect("Variable not ofund");
                             let temp_reg = self.allocate_register();
 
-                            let const_idx = self.add_constant(1);
+                            let const_idx = self.add_constant(self.step_size(expr));
                             let one_reg = self.allocate_register();
 
                             self.emit(Instruction::ABx { opcode: OpCode::LOADK, a: one_reg, bx: const_idx as u32 });
                             self.emit(Instruction::ABC { opcode: OpCode::MOV, a: temp_reg, b: var_reg as u16, c: 0 });
                             self.emit(Instruction::ABC { opcode: OpCode::ADD, a: var_reg, b: var_reg as u16, c: one_reg as u16 });
+                            self.wrap_to_type(var_reg, &self.expr_type(expr));
 
                             self.free_register(one_reg);
                             temp_reg
@@ -761,11 +2287,46 @@ impl<'a> FunctionBuilder<'a> {
 
 
 
+// what one file can name at file scope, filled in as its declarations go by
+#[derive(Default)]
+struct UnitScope {
+    // function name -> index, its own functions and the ones another file defines for it
+    functions: HashMap<String, usize>,
+
+    // function name -> signature, shared with every builder
+    signatures: HashMap<String, Signature>,
+
+    // global name -> its place in the data segment
+    globals: HashMap<String, Global>,
+
+    // function name -> its static locals, keyed by where each one is declared
+    static_locals: HashMap<String, HashMap<usize, Global>>,
+
+    // struct/union layouts and typedefs
+    layouts: Layouts,
+}
+
 pub struct CodeGenerator {
     // all function chunks
     pub functions: Vec<FunctionChunk>,
 
+    // functions any file can call by name, a static function is only in its own file's scope
     pub function_map: HashMap<String, usize>,
+
+    // the same for globals, what an extern in another file resolves to
+    exported_globals: HashMap<String, Global>,
+
+    // functions given an index so far, their chunks are generated in the same order
+    function_count: usize,
+
+    // initial contents of the data segment, the vm copies it into memory before main runs
+    pub data: Vec<u8>,
+
+    // string literals, placed after the data segment
+    pub rodata: Rodata,
+
+    // every global and static in the data segment
+    pub data_slots: Vec<Slot>,
 }
 
 impl CodeGenerator {
@@ -773,106 +2334,211 @@ impl CodeGenerator {
         CodeGenerator { 
             functions: vec![],
             function_map: HashMap::new(),
+            exported_globals: HashMap::new(),
+            function_count: 0,
+            data: vec![],
+            rodata: Rodata::default(),
+            data_slots: vec![],
         }
     }
 
-    pub fn print_instructions(&self) {
-        for func in &self.functions {
-            println!("\n=== Function: {} ===", func.name);
-            println!("Registers: {} (r0-r{})", func.max_registers + 1, func.max_registers);
-            
-            for (i, instr) in func.instructions.iter().enumerate() {
-                match instr {
-                    Instruction::ABC { opcode, a, b, c } => {
-                        match opcode {
-                            OpCode::ADD => println!("{:04}: ADD r{}, r{}, r{}", i, a, b, c),
-                            OpCode::SUB => println!("{:04}: SUB r{}, r{}, r{}", i, a, b, c),
-                            OpCode::MUL => println!("{:04}: MUL r{}, r{}, r{}", i, a, b, c),
-                            OpCode::DIV => println!("{:04}: DIV r{}, r{}, r{}", i, a, b, c),
-                            OpCode::MOD => println!("{:04}: MOD r{}, r{}, r{}", i, a, b, c),
-                            OpCode::EQ => println!("{:04}: EQ r{}, r{}, r{}", i, a, b, c),
-                            OpCode::LT => println!("{:04}: LT r{}, r{}, r{}", i, a, b, c),
-                            OpCode::LE => println!("{:04}: LE r{}, r{}, r{}", i, a, b, c),
-                            OpCode::GT => println!("{:04}: GT r{}, r{}, r{}", i, a, b, c),
-                            OpCode::GE => println!("{:04}: GE r{}, r{}, r{}", i, a, b, c),
-                            OpCode::NE => println!("{:04}: NE r{}, r{}, r{}", i, a, b, c),
-                            OpCode::BAND => println!("{:04}: BAND r{}, r{}, r{}", i, a, b, c),
-                            OpCode::BOR => println!("{:04}: BOR r{}, r{}, r{}", i, a, b, c),
-                            OpCode::BXOR => println!("{:04}: BXOR r{}, r{}, r{}", i, a, b, c),
-                            OpCode::SHL => println!("{:04}: SHL r{}, r{}, r{}", i, a, b, c),
-                            OpCode::SHR => println!("{:04}: SHR r{}, r{}, r{}", i, a, b, c),
-                            OpCode::CALL => println!("{:04}: CALL r{}, {}, {}", i, a, b, c),
-                            
-                            OpCode::MOV => println!("{:04}: MOV r{}, r{}", i, a, b),
-                            
-                            OpCode::TEST => println!("{:04}: TEST r{}", i, a),
-
-                            OpCode::RETURN => {
-                                if *b == 2 {
-                                    println!("{:04}: RETURN r{}", i, a);
-                                } else {
-                                    println!("{:04}: RETURN", i);
-                                }
-                            }
-                            
-                            _ => println!("{:04}: UNKNOWN r{}, r{}, r{}", i, a, b, c),
-                        }
-                    }
+    // a program split over several files. each file is compiled with only its own declarations
+    // in view, but every file's functions and globals get their place before any code is
+    // generated so a prototype or an extern can resolve to what another file defines.
+    // the linker has already checked every name used resolves to exactly one definition.
+    // the errors' files are indices into programs
+    pub fn gen_units(&mut self, programs: &[&Program]) -> Result<(), Vec<Diagnostic>> {
+        // initializers are written once every global and static has its place, a pointer into
+        // rodata needs to know where the data segment ends
+        let mut scopes = vec![];
+        let mut inits = vec![];
+        for program in programs {
+            let mut unit_inits = vec![];
+            scopes.push(self.place_unit(program, &mut unit_inits));
+            inits.push(unit_inits);
+        }
 
-                    Instruction::ABx { opcode, a, bx } => {
-                        match opcode {
-                            OpCode::LOADK => println!("{:04}: LOADK r{}, K{}", i, a, bx),
-                            OpCode::CLOSURE => println!("{:04}: CLOSURE r{}, F{}", i, a, bx),
-                            _ => println!("{:04}: UNKNOWN r{}, #{}", i, a, bx),
-                        }
-                    }
+        for (scope, program) in scopes.iter_mut().zip(programs) {
+            self.import(scope, program);
+        }
+
+        let rodata_addr = (NULL_GUARD + self.data.len().next_multiple_of(8)) as i64;
+        let mut errors = vec![];
+        for (file, (scope, unit_inits)) in scopes.iter().zip(&inits).enumerate() {
+            for (global, init, statics) in unit_inits {
+                let mut const_scope = ConstScope { layouts: &scope.layouts, globals: &scope.globals, statics, functions: &scope.functions, rodata: &mut self.rodata, rodata_addr };
+                if let Err(err) = const_scope.write_initializer(&mut self.data, global.offset, &global.typ, init) {
+                    errors.push(err.in_file(file));
+                }
+            }
+        }
 
-                    Instruction::AsBx { opcode, offset } => {
-                        match opcode {
-                            OpCode::JMP => println!("{:04}: JMP {}", i, offset),
-                            _ => println!("{:04}: UNKNOWN {}", i, offset),
+        for (file, (scope, program)) in scopes.iter_mut().zip(programs).enumerate() {
+            for decl in &program.declarations {
+                match decl {
+                    Declaration::Function(func) if func.body.is_some() => {
+                        if let Err(func_errors) = self.gen_function(scope, func, file as u32) {
+                            errors.extend(func_errors.into_iter().map(|err| err.in_file(file)));
                         }
                     }
+                    _ => {}
                 }
             }
-            
-            println!("\nConstants:");
-            for (i, val) in func.constants.iter().enumerate() {
-                println!("  K{}: {}", i, val);
-            }
         }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(())
     }
 
-    pub fn gen_program(&mut self, program: &Program) {
-        // first pass collects function names into function_map
-        let mut count = 0;
+    // gives the file's function definitions an index and its globals and statics their place
+    fn place_unit<'p>(&mut self, program: &'p Program, inits: &mut Vec<(Global, &'p Expr, HashMap<String, Global>)>) -> UnitScope {
+        let mut scope = UnitScope::default();
+        let file_statics = program.static_names();
+
         for decl in &program.declarations {
             match decl {
+                // a prototype for malloc and friends just refers to the builtin
+                Declaration::Function(func) if is_builtin_prototype(func) => {}
                 Declaration::Function(func) => {
-                    self.function_map.insert(func.name.clone(), count);
-                    count +=1;
+                    scope.signatures.insert(func.name.clone(), Signature {
+                        params: func.params.iter().map(|p| scope.layouts.resolve(&p.typ.base)).collect(),
+                        return_type: scope.layouts.resolve(&func.return_type.base),
+                    });
+
+                    if let Some(body) = &func.body {
+                        scope.functions.insert(func.name.clone(), self.function_count);
+                        if !file_statics.contains(func.name.as_str()) {
+                            self.function_map.insert(func.name.clone(), self.function_count);
+                        }
+                        self.function_count += 1;
+                        self.gen_static_locals(&mut scope, &func.name, body, inits);
+                    }
+                }
+
+                // extern only says the variable is defined somewhere else. int x; then int x = 1;
+                // is the same variable, the second one just brings the initializer
+                Declaration::Variable(var) if var.is_definition() => {
+                    let global = match scope.globals.get(&var.name) {
+                        Some(global) => global.clone(),
+                        None => {
+                            let global = self.gen_global(&scope.layouts, &var.name, &var.typ.base, var.init.as_ref());
+                            if !file_statics.contains(var.name.as_str()) {
+                                self.exported_globals.insert(var.name.clone(), global.clone());
+                            }
+                            scope.globals.insert(var.name.clone(), global.clone());
+                            global
+                        }
+                    };
+                    if let Some(init) = &var.init {
+                        inits.push((global, init, HashMap::new()));
+                    }
+                }
+                Declaration::Struct(StructDec { name: Some(name), fields, .. }) => scope.layouts.add_record(name, fields, false),
+                Declaration::Union(UnionDec { name: Some(name), fields, .. }) => scope.layouts.add_record(name, fields, true),
+                Declaration::Typedef(typedef) => {
+                    let aliased = scope.layouts.resolve(&typedef.typ.base);
+                    scope.layouts.typedefs.insert(typedef.name.clone(), aliased);
                 }
                 _ => {}
             }
         }
+        scope
+    }
 
-        // second pass compiles
+    // prototypes and externs the file didn't define itself refer to another file's definition
+    fn import(&self, scope: &mut UnitScope, program: &Program) {
+        let file_statics = program.static_names();
         for decl in &program.declarations {
             match decl {
-                Declaration::Function(func) => self.gen_function(func),
+                Declaration::Function(func) if !scope.functions.contains_key(&func.name) && !file_statics.contains(func.name.as_str()) => {
+                    if let Some(&func_idx) = self.function_map.get(&func.name) {
+                        scope.functions.insert(func.name.clone(), func_idx);
+                    }
+                }
+                Declaration::Variable(var) if !scope.globals.contains_key(&var.name) && !file_statics.contains(var.name.as_str()) => {
+                    if let Some(global) = self.exported_globals.get(&var.name) {
+                        // extern int a[]; gets its size from the definition
+                        let typ = match (scope.layouts.resolve(&var.typ.base), &global.typ) {
+                            (Type::Array(elem, None), Type::Array(_, size)) => Type::Array(elem, *size),
+                            (typ, _) => typ,
+                        };
+                        scope.globals.insert(var.name.clone(), Global { offset: global.offset, typ });
+                    }
+                }
                 _ => {}
             }
         }
     }
 
-    fn gen_function(&mut self, func: &FunctionDec) {
-        let mut builder = FunctionBuilder::new(func.name.clone(), &self.function_map);
-        
-        for (i, param) in func.params.iter().enumerate() {
+    // globals and statics get zeroed space in the data segment, gen_units writes the initializers in after
+    fn gen_global(&mut self, layouts: &Layouts, name: &str, typ: &Type, init: Option<&Expr>) -> Global {
+        let typ = complete_array_type(&layouts.resolve(typ), init);
+        let offset = (self.data.len() as u32).next_multiple_of(layouts.align_of(&typ));
+        let size = layouts.size_of(&typ);
+        self.data.resize((offset + size) as usize, 0);
+        self.data_slots.push(Slot { name: name.to_string(), offset, size });
+        Global { offset, typ }
+    }
+
+    // static locals live as long as the program, so they're placed up front like globals.
+    // an initializer can take the address of a static declared before it
+    fn gen_static_locals<'p>(&mut self, scope: &mut UnitScope, func_name: &str, body: &'p [Statement], inits: &mut Vec<(Global, &'p Expr, HashMap<String, Global>)>) {
+        let mut decls = vec![];
+        static_locals_in_stmts(body, &mut decls);
+
+        let mut by_name = HashMap::new();
+        let mut by_decl = HashMap::new();
+        for stmt in decls {
+            if let StatementKind::VarDec(typ, name, init, ..) = &stmt.kind {
+                let global = self.gen_global(&scope.layouts, name, &typ.base, init.as_ref());
+                if let Some(init) = init {
+                    inits.push((global.clone(), init, by_name.clone()));
+                }
+                by_name.insert(name.clone(), global.clone());
+                by_decl.insert(stmt.span.offset, global);
+            }
+        }
+        scope.static_locals.insert(func_name.to_string(), by_decl);
+    }
+
+    fn gen_function(&mut self, scope: &mut UnitScope, func: &FunctionDec, file: u32) -> Result<(), Vec<Diagnostic>> {
+        let static_decls = scope.static_locals.remove(&func.name).unwrap_or_default();
+        let mut builder = FunctionBuilder::new(func.name.clone(), &scope.functions, &scope.signatures, &scope.globals, &scope.layouts, &mut self.rodata);
+        builder.static_decls = static_decls;
+        // the param copies and the return at the end belong to the function's own line
+        builder.span = func.span;
+        if let Some(body) = &func.body {
+            address_taken_in_stmts(body, &mut builder.address_taken);
+        }
+
+        // returning a struct means the caller passes where to put it ahead of the real params
+        if scope.layouts.record(&scope.signatures[&func.name].return_type).is_some() {
+            let reg = builder.allocate_register();
+            builder.permanent_regs.insert(reg);
+            builder.sret_reg = Some(reg);
+        }
+
+        builder.arity = builder.sret_reg.is_some() as u8 + func.params.len() as u8;
+        for param in &func.params {
             let reg = builder.allocate_register();
             builder.permanent_regs.insert(reg);
             if let Some(name) = &param.name {
                 builder.sym_table.insert(name.clone(), reg);
+                builder.var_types.insert(name.clone(), scope.layouts.resolve(&param.typ.base));
+            }
+        }
+
+        // params arrive in registers, the ones that need an address get copied to the frame first.
+        // a struct arrives as the address of the caller's copy and gets its own copy here
+        for param in &func.params {
+            let Some(name) = &param.name else { continue };
+            let typ = builder.var_types[name].clone();
+            if builder.address_taken.contains(name) || in_memory(&typ) {
+                let param_reg = builder.sym_table[name];
+                let slot = builder.alloc_slot(name, &typ);
+                builder.slots.insert(name.clone(), slot);
+                builder.gen_slot_store(slot, &typ, param_reg);
             }
         }
 
@@ -881,8 +2547,510 @@ impl CodeGenerator {
                 builder.gen_statement(stmt);
             }
         }
+        builder.resolve_gotos();
+
+        // falling off the end returns 0, same as main in c99 (other non-void functions get a warning)
+        builder.emit(Instruction::ABC {
+            opcode: OpCode::RETURN,
+            a: 0,
+            b: 1,
+            c: 0
+        });
         
-        let chunk = builder.finalize();
+        let chunk = builder.finalize(file)?;
         self.functions.push(chunk);
+        Ok(())
+    }
+}
+
+fn is_builtin_prototype(func: &FunctionDec) -> bool {
+    func.body.is_none() && Builtin::from_name(&func.name).is_some()
+}
+
+// case labels are constant expressions, literals (and negated literals) are all we fold for now
+fn case_constant(expr: &Expr) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::IntLiteral(val) => Some(*val),
+        ExprKind::CharLiteral(c) => Some(*c as i64),
+        ExprKind::UnaryOp(UnaryOp::Neg, inner) => case_constant(inner).map(|v| -v),
+        _ => None,
+    }
+}
+
+// what a global or static initializer can see: the layouts, the variables already placed
+// and the string literals
+struct ConstScope<'a> {
+    layouts: &'a Layouts,
+    globals: &'a HashMap<String, Global>,
+    statics: &'a HashMap<String, Global>,
+    functions: &'a HashMap<String, usize>,
+    rodata: &'a mut Rodata,
+
+    // where rodata starts in memory
+    rodata_addr: i64,
+}
+
+impl ConstScope<'_> {
+    fn lookup(&self, name: &str) -> Option<&Global> {
+        self.statics.get(name).or_else(|| self.globals.get(name))
     }
-}
\ No newline at end of file
+
+    // folds a constant expression. ints are folded as long and floats as double,
+    // the result is converted to the variable's type when it's written out
+    fn value(&mut self, expr: &Expr) -> Option<Constant> {
+        match &expr.kind {
+            ExprKind::IntLiteral(val) => Some(Constant::Int(*val)),
+            ExprKind::CharLiteral(c) => Some(Constant::Int(*c as i64)),
+            ExprKind::BoolLiteral(b) => Some(Constant::Int(*b as i64)),
+            ExprKind::Null => Some(Constant::Int(0)),
+            ExprKind::FloatLiteral(val) => Some(Constant::Float(*val)),
+            ExprKind::StringLiteral(text) => Some(Constant::Int(self.rodata_addr + self.rodata.intern(text) as i64)),
+
+            ExprKind::UnaryOp(op, inner) => {
+                let value = self.value(inner)?;
+                match (op, value) {
+                    (UnaryOp::Neg, Constant::Int(val)) => Some(Constant::Int(val.wrapping_neg())),
+                    (UnaryOp::Neg, Constant::Float(val)) => Some(Constant::Float(-val)),
+                    (UnaryOp::Not, _) => Some(Constant::Int(!is_truthy(value) as i64)),
+                    (UnaryOp::BitNot, Constant::Int(val)) => Some(Constant::Int(!val)),
+                    _ => None,
+                }
+            }
+
+            ExprKind::BinOp(lhs, op, rhs) => fold_binop(op, self.value(lhs)?, self.value(rhs)?),
+
+            ExprKind::Ternary(cond, then_expr, else_expr) => {
+                if is_truthy(self.value(cond)?) { self.value(then_expr) } else { self.value(else_expr) }
+            }
+
+            ExprKind::Cast(typ, inner) => Some(convert_constant(self.value(inner)?, &self.layouts.resolve(&typ.base))),
+
+            ExprKind::SizeofType(typ) => Some(Constant::Int(self.layouts.size_of(&self.layouts.resolve(&typ.base)) as i64)),
+            ExprKind::SizeofExpr(inner) => match &inner.kind {
+                ExprKind::Identifier(name) => Some(Constant::Int(self.layouts.size_of(&self.lookup(name)?.typ) as i64)),
+                ExprKind::StringLiteral(text) => Some(Constant::Int(string_bytes(text).len() as i64)),
+                _ => None,
+            },
+
+            ExprKind::Identifier(_) | ExprKind::AddrOf(_) if self.function_ref(expr).is_some() => self.function_ref(expr).map(Constant::Int),
+
+            // &global, and an array name decays to its address
+            ExprKind::AddrOf(inner) => self.address(inner).map(Constant::Int),
+            ExprKind::Identifier(name) if matches!(self.lookup(name)?.typ, Type::Array(..)) => self.address(expr).map(Constant::Int),
+
+            _ => None,
+        }
+    }
+
+    // f or &f for a function f, what CLOSURE would put in a register
+    fn function_ref(&self, expr: &Expr) -> Option<i64> {
+        let name = match &expr.kind {
+            ExprKind::Identifier(name) => name,
+            ExprKind::AddrOf(inner) => match &inner.kind {
+                ExprKind::Identifier(name) => name,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if self.lookup(name).is_some() {
+            return None;
+        }
+        self.functions.get(name).map(|&func_idx| func_idx as i64 + 1)
+    }
+
+    // where a global (or an element or field of one) ends up in memory, with the null guard in front of the data segment
+    fn address(&mut self, expr: &Expr) -> Option<i64> {
+        Some(self.place(expr)?.0)
+    }
+
+    fn place(&mut self, expr: &Expr) -> Option<(i64, Type)> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => {
+                let global = self.lookup(name)?;
+                Some(((NULL_GUARD as u32 + global.offset) as i64, global.typ.clone()))
+            }
+            ExprKind::ArrayIndex(arr, index) => {
+                let (addr, Type::Array(elem, _)) = self.place(arr)? else { return None };
+                let Constant::Int(index) = self.value(index)? else { return None };
+                Some((addr + index * self.layouts.size_of(&elem) as i64, *elem))
+            }
+            ExprKind::FieldAccess(obj, name) => {
+                let (addr, typ) = self.place(obj)?;
+                let field = self.layouts.field(&typ, name)?;
+                Some((addr + field.offset as i64, field.typ.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    // the bytes a constant initializer leaves in memory for a value of this type
+    fn bytes(&mut self, typ: &Type, expr: &Expr) -> Option<Vec<u8>> {
+        let bytes = match convert_constant(self.value(expr)?, typ) {
+            Constant::Float(val) if *typ == Type::Float => (val as f32).to_le_bytes().to_vec(),
+            Constant::Float(val) => val.to_le_bytes().to_vec(),
+            Constant::Int(val) => val.to_le_bytes()[..self.layouts.size_of(typ) as usize].to_vec(),
+        };
+        Some(bytes)
+    }
+
+    // writes an initializer into the data segment, anything it leaves out stays zero.
+    // semantic analysis folds the same way, so an error here means the two disagree
+    fn write_initializer(&mut self, data: &mut [u8], offset: u32, typ: &Type, init: &Expr) -> Result<(), Diagnostic> {
+        match (typ, &init.kind) {
+            // char s[] = "hi" gets the characters themselves, not a pointer to them
+            (Type::Array(_, size), ExprKind::StringLiteral(text)) => {
+                let bytes = string_bytes(text);
+                let len = size.map_or(bytes.len(), |size| size.min(bytes.len()));
+                let start = offset as usize;
+                data[start..start + len].copy_from_slice(&bytes[..len]);
+            }
+            (Type::Array(elem, _), ExprKind::InitList(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.write_initializer(data, offset + i as u32 * self.layouts.size_of(elem), elem, item)?;
+                }
+            }
+            (Type::StructRef(_) | Type::UnionRef(_), ExprKind::InitList(items)) => {
+                let fields = self.layouts.record(typ).map_or(&[][..], |record| &record.fields);
+                for (field, item) in fields.iter().zip(items) {
+                    self.write_initializer(data, offset + field.offset, &field.typ, item)?;
+                }
+            }
+            _ => {
+                let Some(bytes) = self.bytes(typ, init) else {
+                    return Err(Diagnostic::error(Code::NonConstantInitializer, init.span, "Initializer element is not a compile-time constant"));
+                };
+                let start = offset as usize;
+                data[start..start + bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn is_truthy(value: Constant) -> bool {
+    match value {
+        Constant::Int(val) => val != 0,
+        Constant::Float(val) => val != 0.0,
+    }
+}
+
+// a constant converted the way an assignment to typ would, ints wrap into the type's width
+pub(crate) fn convert_constant(value: Constant, typ: &Type) -> Constant {
+    match (value, float_target(typ)) {
+        (Constant::Int(val), Some(Type::Float)) => Constant::Float(val as f32 as f64),
+        (Constant::Int(val), Some(_)) => Constant::Float(val as f64),
+        (Constant::Float(val), Some(Type::Float)) => Constant::Float(val as f32 as f64),
+        (Constant::Float(_), Some(_)) => value,
+        (Constant::Float(val), None) => convert_constant(Constant::Int(val as i64), typ),
+        (Constant::Int(val), None) => match int_layout(typ) {
+            Some((64, _)) | None => Constant::Int(val),
+            Some((bits, true)) => Constant::Int(val & ((1 << bits) - 1)),
+            Some((bits, false)) => Constant::Int(val << (64 - bits) >> (64 - bits)),
+        },
+    }
+}
+
+// None when the result isn't a constant, like dividing by zero
+pub(crate) fn fold_binop(op: &BinOp, lhs: Constant, rhs: Constant) -> Option<Constant> {
+    let bool_int = |b: bool| Some(Constant::Int(b as i64));
+    match (op, lhs, rhs) {
+        (BinOp::And, _, _) => bool_int(is_truthy(lhs) && is_truthy(rhs)),
+        (BinOp::Or, _, _) => bool_int(is_truthy(lhs) || is_truthy(rhs)),
+
+        (_, Constant::Int(l), Constant::Int(r)) => Some(Constant::Int(match op {
+            BinOp::Add => l.wrapping_add(r),
+            BinOp::Sub => l.wrapping_sub(r),
+            BinOp::Mul => l.wrapping_mul(r),
+            BinOp::Div => l.checked_div(r)?,
+            BinOp::Mod => l.checked_rem(r)?,
+            BinOp::BitAnd => l & r,
+            BinOp::BitOr => l | r,
+            BinOp::BitXor => l ^ r,
+            BinOp::LShift => l.wrapping_shl(r as u32),
+            BinOp::RShift => l.wrapping_shr(r as u32),
+            _ => compare_constants(op, l, r)? as i64,
+        })),
+
+        _ => {
+            let as_float = |value| match value {
+                Constant::Int(val) => val as f64,
+                Constant::Float(val) => val,
+            };
+            let (l, r) = (as_float(lhs), as_float(rhs));
+            match op {
+                BinOp::Add => Some(Constant::Float(l + r)),
+                BinOp::Sub => Some(Constant::Float(l - r)),
+                BinOp::Mul => Some(Constant::Float(l * r)),
+                BinOp::Div => Some(Constant::Float(l / r)),
+                _ => bool_int(compare_constants(op, l, r)?),
+            }
+        }
+    }
+}
+
+fn compare_constants<T: PartialOrd>(op: &BinOp, l: T, r: T) -> Option<bool> {
+    Some(match op {
+        BinOp::Eq => l == r,
+        BinOp::NotEq => l != r,
+        BinOp::Lt => l < r,
+        BinOp::Gt => l > r,
+        BinOp::Le => l <= r,
+        BinOp::Ge => l >= r,
+        _ => return None,
+    })
+}
+
+// a table is worth it once there are a few cases and at least half the slots are used
+fn is_dense(values: &[i64]) -> bool {
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return false;
+    };
+    let span = max as i128 - min as i128 + 1;
+    values.len() >= 4 && span <= 2 * values.len() as i128
+}
+
+fn is_float_type(typ: &Type) -> bool {
+    matches!(typ, Type::Float | Type::Double)
+}
+
+// (bit width, unsigned) of an integer type, pointers are plain 64 bit unsigned
+fn int_layout(typ: &Type) -> Option<(u16, bool)> {
+    match typ {
+        Type::Char => Some((8, false)),
+        Type::Short => Some((16, false)),
+        Type::Int | Type::EnumRef(_) | Type::Enum { .. } => Some((32, false)),
+        Type::Long | Type::LongLong => Some((64, false)),
+        Type::Signed(inner) => int_layout(inner).map(|(bits, _)| (bits, false)),
+        Type::Unsigned(inner) => int_layout(inner).map(|(bits, _)| (bits, true)),
+        Type::Pointer(_) | Type::Array(..) => Some((64, true)),
+        _ => None,
+    }
+}
+
+fn is_pointer_like(typ: &Type) -> bool {
+    matches!(typ, Type::Pointer(_) | Type::Array(..))
+}
+
+// an array in an expression is a pointer to its first element
+fn decay(typ: Type) -> Type {
+    match typ {
+        Type::Array(elem, _) => Type::Pointer(elem),
+        other => other,
+    }
+}
+
+// arrays and structs can't fit in a register, they're always used through their address
+fn in_memory(typ: &Type) -> bool {
+    matches!(typ, Type::Array(..) | Type::StructRef(_) | Type::UnionRef(_))
+}
+
+// int arr[] = {1, 2, 3} takes its length from the initializer
+fn complete_array_type(typ: &Type, init: Option<&Expr>) -> Type {
+    match (typ, init.map(|init| &init.kind)) {
+        (Type::Array(elem, None), Some(ExprKind::InitList(items))) => Type::Array(elem.clone(), Some(items.len())),
+        (Type::Array(elem, None), Some(ExprKind::StringLiteral(text))) => Type::Array(elem.clone(), Some(string_bytes(text).len())),
+        _ => typ.clone(),
+    }
+}
+
+// what a string literal puts in memory, utf-8 with a nul on the end
+fn string_bytes(text: &str) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+// Float stays Float, Double stays Double, anything else isn't a float target
+fn float_target(typ: &Type) -> Option<Type> {
+    match typ {
+        Type::Float => Some(Type::Float),
+        Type::Double => Some(Type::Double),
+        _ => None,
+    }
+}
+
+// conversion ops (and their C operand) to turn a `from` value into a `to` value.
+// registers always hold a value already in range for their type (ints sign/zero extended
+// from their width), so widening to a type that holds every value is free
+fn conversion_steps(from: &Type, to: &Type) -> Vec<(OpCode, u16)> {
+    let narrow = |bits: u16, unsigned: bool| match (bits, unsigned) {
+        (64, _) => None,
+        (_, true) => Some((OpCode::ZEXT, bits)),
+        (_, false) => Some((OpCode::SEXT, bits)),
+    };
+
+    match (float_target(from), float_target(to)) {
+        (Some(Type::Double), Some(Type::Float)) => vec![(OpCode::F2S, 0)],
+        (Some(_), Some(_)) => vec![],
+
+        // int -> float, rounded again for single precision
+        (None, Some(to_float)) => {
+            let op = match int_layout(from) {
+                Some((64, true)) => OpCode::U2F,
+                _ => OpCode::I2F,
+            };
+            let mut steps = vec![(op, 0)];
+            if to_float == Type::Float {
+                steps.push((OpCode::F2S, 0));
+            }
+            steps
+        }
+
+        // float -> int truncates, then wraps into the target width
+        (Some(_), None) => match int_layout(to) {
+            Some((bits, unsigned)) => {
+                let op = if unsigned { OpCode::F2U } else { OpCode::F2I };
+                std::iter::once((op, 0)).chain(narrow(bits, unsigned)).collect()
+            }
+            None => vec![],
+        },
+
+        (None, None) => match (int_layout(from), int_layout(to)) {
+            (Some((from_bits, from_unsigned)), Some((to_bits, to_unsigned))) => {
+                let fits = (from_unsigned == to_unsigned && from_bits <= to_bits)
+                    || (from_unsigned && !to_unsigned && from_bits < to_bits);
+                if fits {
+                    vec![]
+                } else {
+                    narrow(to_bits, to_unsigned).into_iter().collect()
+                }
+            }
+            _ => vec![],
+        },
+    }
+}
+
+// every variable that & is applied to somewhere in the function
+fn address_taken_in_stmts(stmts: &[Statement], names: &mut HashSet<String>) {
+    for stmt in stmts {
+        address_taken_in_stmt(stmt, names);
+    }
+}
+
+fn address_taken_in_stmt(stmt: &Statement, names: &mut HashSet<String>) {
+    match &stmt.kind {
+        StatementKind::VarDec(_, _, init, ..) => {
+            if let Some(init) = init {
+                address_taken_in_expr(init, names);
+            }
+        }
+        StatementKind::Assign(lhs, rhs) => {
+            address_taken_in_expr(lhs, names);
+            address_taken_in_expr(rhs, names);
+        }
+        StatementKind::CompoundAssign(_, lhs, rhs) => {
+            address_taken_in_expr(lhs, names);
+            address_taken_in_expr(rhs, names);
+        }
+        StatementKind::Return(expr) | StatementKind::ExprStatement(expr) => address_taken_in_expr(expr, names),
+        StatementKind::If(cond, then_body, else_body) => {
+            address_taken_in_expr(cond, names);
+            address_taken_in_stmts(then_body, names);
+            if let Some(else_body) = else_body {
+                address_taken_in_stmts(else_body, names);
+            }
+        }
+        StatementKind::While(cond, body) => {
+            address_taken_in_expr(cond, names);
+            address_taken_in_stmts(body, names);
+        }
+        StatementKind::For(init, cond, incr, body) => {
+            if let Some(init) = init {
+                address_taken_in_stmt(init, names);
+            }
+            for expr in cond.iter().chain(incr.iter()) {
+                address_taken_in_expr(expr, names);
+            }
+            address_taken_in_stmts(body, names);
+        }
+        StatementKind::DoWhile(do_while_stmt) => {
+            address_taken_in_stmts(&do_while_stmt.body, names);
+            address_taken_in_expr(&do_while_stmt.condition, names);
+        }
+        StatementKind::Switch(switch_stmt) => {
+            address_taken_in_expr(&switch_stmt.expr, names);
+            for case in &switch_stmt.cases {
+                address_taken_in_stmts(&case.stmts, names);
+            }
+        }
+        StatementKind::Label(_, inner) => address_taken_in_stmt(inner, names),
+        StatementKind::Block(stmts) => address_taken_in_stmts(stmts, names),
+        StatementKind::ReturnVoid | StatementKind::Break | StatementKind::Continue | StatementKind::Goto(_) => {}
+    }
+}
+
+// every static declaration in a function body, in source order
+fn static_locals_in_stmts<'a>(stmts: &'a [Statement], found: &mut Vec<&'a Statement>) {
+    for stmt in stmts {
+        static_locals_in_stmt(stmt, found);
+    }
+}
+
+fn static_locals_in_stmt<'a>(stmt: &'a Statement, found: &mut Vec<&'a Statement>) {
+    match &stmt.kind {
+        StatementKind::VarDec(_, _, _, StorageClass::Static, _) => found.push(stmt),
+        StatementKind::If(_, then_body, else_body) => {
+            static_locals_in_stmts(then_body, found);
+            if let Some(else_body) = else_body {
+                static_locals_in_stmts(else_body, found);
+            }
+        }
+        StatementKind::While(_, body) => static_locals_in_stmts(body, found),
+        StatementKind::For(init, _, _, body) => {
+            if let Some(init) = init {
+                static_locals_in_stmt(init, found);
+            }
+            static_locals_in_stmts(body, found);
+        }
+        StatementKind::DoWhile(do_while_stmt) => static_locals_in_stmts(&do_while_stmt.body, found),
+        StatementKind::Switch(switch_stmt) => {
+            for case in &switch_stmt.cases {
+                static_locals_in_stmts(&case.stmts, found);
+            }
+        }
+        StatementKind::Label(_, inner) => static_locals_in_stmt(inner, found),
+        StatementKind::Block(stmts) => static_locals_in_stmts(stmts, found),
+        _ => {}
+    }
+}
+
+fn address_taken_in_expr(expr: &Expr, names: &mut HashSet<String>) {
+    match &expr.kind {
+        ExprKind::AddrOf(inner) => {
+            if let ExprKind::Identifier(name) = &inner.kind {
+                names.insert(name.clone());
+            }
+            address_taken_in_expr(inner, names);
+        }
+        ExprKind::BinOp(lhs, _, rhs)
+        | ExprKind::ArrayIndex(lhs, rhs)
+        | ExprKind::Assign(lhs, rhs)
+        | ExprKind::CompoundAssign(_, lhs, rhs) => {
+            address_taken_in_expr(lhs, names);
+            address_taken_in_expr(rhs, names);
+        }
+        ExprKind::UnaryOp(_, inner)
+        | ExprKind::FieldAccess(inner, _)
+        | ExprKind::Deref(inner)
+        | ExprKind::PtrMember(inner, _)
+        | ExprKind::Cast(_, inner)
+        | ExprKind::SizeofExpr(inner) => address_taken_in_expr(inner, names),
+        ExprKind::Ternary(cond, then_expr, else_expr) => {
+            address_taken_in_expr(cond, names);
+            address_taken_in_expr(then_expr, names);
+            address_taken_in_expr(else_expr, names);
+        }
+        ExprKind::Call(func, args) => {
+            address_taken_in_expr(func, names);
+            for arg in args {
+                address_taken_in_expr(arg, names);
+            }
+        }
+        ExprKind::InitList(items) => {
+            for item in items {
+                address_taken_in_expr(item, names);
+            }
+        }
+        _ => {}
+    }
+}
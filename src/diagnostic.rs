@@ -0,0 +1,343 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::span::Span;
+
+// stable codes so tooling (and our tests) can match on something other than the english text
+// never renumber these, only append
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    Redeclaration,
+    UndeclaredIdentifier,
+    TypeMismatch,
+    ReturnMismatch,
+    NotAnLvalue,
+    InvalidOperands,
+    InvalidCall,
+    InvalidMemberAccess,
+    InvalidIndex,
+    InvalidDeref,
+    MisplacedJump,
+    InvalidSwitch,
+    UnknownType,
+    DuplicateLabel,
+    SyntaxError,
+    UndefinedLabel,
+    JumpPastInitialization,
+    NonConstantInitializer,
+    DuplicateSymbol,
+    UndefinedSymbol,
+    ConflictingSymbol,
+    OperandOutOfRange,
+
+    // warnings
+    UnusedVariable,
+    UnusedParameter,
+    Unreachable,
+    MissingReturn,
+    AssignInCondition,
+    ConstantComparison,
+    UnusedLabel,
+}
+
+// every warning code, all of them are on by default
+pub const WARNINGS: [Code; 7] = [
+    Code::UnusedVariable,
+    Code::UnusedParameter,
+    Code::Unreachable,
+    Code::MissingReturn,
+    Code::AssignInCondition,
+    Code::ConstantComparison,
+    Code::UnusedLabel,
+];
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::Redeclaration => "E0001",
+            Code::UndeclaredIdentifier => "E0002",
+            Code::TypeMismatch => "E0003",
+            Code::ReturnMismatch => "E0004",
+            Code::NotAnLvalue => "E0005",
+            Code::InvalidOperands => "E0006",
+            Code::InvalidCall => "E0007",
+            Code::InvalidMemberAccess => "E0008",
+            Code::InvalidIndex => "E0009",
+            Code::InvalidDeref => "E0010",
+            Code::MisplacedJump => "E0011",
+            Code::InvalidSwitch => "E0012",
+            Code::UnknownType => "E0013",
+            Code::DuplicateLabel => "E0014",
+            Code::UndefinedLabel => "E0015",
+            Code::JumpPastInitialization => "E0016",
+            Code::NonConstantInitializer => "E0017",
+            Code::SyntaxError => "E0100",
+            Code::DuplicateSymbol => "E0200",
+            Code::UndefinedSymbol => "E0201",
+            Code::ConflictingSymbol => "E0202",
+            Code::OperandOutOfRange => "E0300",
+            Code::UnusedVariable => "W0001",
+            Code::UnusedParameter => "W0002",
+            Code::Unreachable => "W0003",
+            Code::MissingReturn => "W0004",
+            Code::AssignInCondition => "W0005",
+            Code::ConstantComparison => "W0006",
+            Code::UnusedLabel => "W0007",
+        }
+    }
+
+    // name used by -W<name> / -Wno-<name>, same spelling as gcc/clang where there is one
+    pub fn flag_name(&self) -> Option<&'static str> {
+        match self {
+            Code::UnusedVariable => Some("unused-variable"),
+            Code::UnusedParameter => Some("unused-parameter"),
+            Code::Unreachable => Some("unreachable-code"),
+            Code::MissingReturn => Some("return-type"),
+            Code::AssignInCondition => Some("parentheses"),
+            Code::ConstantComparison => Some("tautological-compare"),
+            Code::UnusedLabel => Some("unused-label"),
+            _ => None,
+        }
+    }
+
+    pub fn from_flag_name(name: &str) -> Option<Code> {
+        WARNINGS.iter().copied().find(|c| c.flag_name() == Some(name))
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// secondary location, like "previously declared here"
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+
+    /// which of the sources the span is in, the linker points into more than one file
+    pub file: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub file: usize,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+// a file a diagnostic can point into
+pub struct Source<'a> {
+    pub name: &'a str,
+    pub text: &'a str,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            file: 0,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(code: Code, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, span, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), file: self.file });
+        self
+    }
+
+    pub fn with_label_in(mut self, file: usize, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), file });
+        self
+    }
+
+    pub fn in_file(mut self, file: usize) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    // rustc style report:
+    //
+    // error[E0001]: Duplicate declaration of variable 'x'
+    //  --> test.c:3:5
+    //   |
+    // 3 |     int x;
+    //   |     ^^^
+    //   |
+    // 2 |     int x;
+    //   |     --- previously declared here
+    pub fn render(&self, source: &str, filename: &str) -> String {
+        self.render_in(&[Source { name: filename, text: source }])
+    }
+
+    // same report when the spans can be in different files, a label in another file gets
+    // a ::: line naming it
+    pub fn render_in(&self, sources: &[Source]) -> String {
+        let lines: Vec<Vec<&str>> = sources.iter().map(|source| source.text.lines().collect()).collect();
+
+        // gutter is as wide as the biggest line number we print
+        let max_line = self.labels.iter()
+            .map(|l| l.span.line)
+            .fold(self.span.line, usize::max);
+        let width = max_line.to_string().len();
+        let pad = " ".repeat(width);
+
+        let mut out = format!("{}[{}]: {}", self.severity, self.code, self.message);
+        if let Some(flag) = self.code.flag_name() {
+            out += &format!(" [-W{}]", flag);
+        }
+        out += "\n";
+        out += &format!("{}--> {}:{}\n", pad, sources[self.file].name, self.span);
+        out += &format!("{} |\n", pad);
+        out += &snippet(&lines[self.file], self.span, '^', "", width);
+
+        for label in &self.labels {
+            out += &format!("{} |\n", pad);
+            if label.file != self.file {
+                out += &format!("{}::: {}:{}\n", pad, sources[label.file].name, label.span);
+                out += &format!("{} |\n", pad);
+            }
+            out += &snippet(&lines[label.file], label.span, '-', &label.message, width);
+        }
+
+        for note in &self.notes {
+            out += &format!("{} = note: {}\n", pad, note);
+        }
+
+        out
+    }
+}
+
+// one line of source with a marker under the token the span starts at
+fn snippet(lines: &[&str], span: Span, marker: char, message: &str, width: usize) -> String {
+    let Some(text) = span.line.checked_sub(1).and_then(|i| lines.get(i)) else {
+        return String::new();
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+    let start = (span.col - 1).min(chars.len());
+
+    // spans only know where they start, so underline the identifier/number there or a single char
+    let len = chars[start..]
+        .iter()
+        .take_while(|c| c.is_alphanumeric() || **c == '_')
+        .count()
+        .max(1);
+
+    // keep tabs so the marker lines up with the source
+    let indent: String = chars[..start].iter().map(|&c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let underline = marker.to_string().repeat(len);
+
+    let mut out = format!("{:>width$} | {}\n", span.line, text, width = width);
+    if message.is_empty() {
+        out += &format!("{:width$} | {}{}\n", "", indent, underline, width = width);
+    } else {
+        out += &format!("{:width$} | {}{} {}\n", "", indent, underline, message, width = width);
+    }
+    out
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}[{}]: {}", self.span, self.severity, self.code, self.message)
+    }
+}
+
+// what to do with warnings, driven by the -W flags on the command line
+pub struct WarningOptions {
+    enabled: HashSet<Code>,
+    suppress_all: bool,
+    pub as_errors: bool,
+}
+
+impl WarningOptions {
+    pub fn new() -> Self {
+        WarningOptions {
+            enabled: WARNINGS.iter().copied().collect(),
+            suppress_all: false,
+            as_errors: false,
+        }
+    }
+
+    // -w, -Wall, -Werror, -W<name>, -Wno-<name>
+    pub fn apply_flag(&mut self, flag: &str) -> Result<(), String> {
+        match flag {
+            "-w" => self.suppress_all = true,
+            "-Wall" => self.enabled.extend(WARNINGS),
+            "-Werror" => self.as_errors = true,
+            "-Wno-error" => self.as_errors = false,
+            _ => {
+                let (name, enable) = match flag.strip_prefix("-Wno-") {
+                    Some(name) => (name, false),
+                    None => match flag.strip_prefix("-W") {
+                        Some(name) => (name, true),
+                        None => return Err(format!("unknown option '{}'", flag)),
+                    },
+                };
+                let code = Code::from_flag_name(name)
+                    .ok_or_else(|| format!("unknown warning option '{}'", flag))?;
+                if enable {
+                    self.enabled.insert(code);
+                } else {
+                    self.enabled.remove(&code);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // drops disabled warnings and promotes the rest under -Werror, errors pass through untouched
+    pub fn filter(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter(|d| d.is_error() || (!self.suppress_all && self.enabled.contains(&d.code)))
+            .map(|mut d| {
+                if self.as_errors {
+                    d.severity = Severity::Error;
+                }
+                d
+            })
+            .collect()
+    }
+}
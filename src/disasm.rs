@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::builtins::Builtin;
+use crate::bytecode::Bytecode;
+use crate::codegen::{FunctionChunk, Instruction, OpCode, Slot, Width};
+
+/*
+    Disassembler (cvm disasm, and the BYTECODE section of a normal run):
+        - one line per instruction, its pc then the mnemonic and operands the way isa_spec.txt
+          writes them
+        - a JMP's target is printed as an absolute label, L0012 is pc 12, and the label sits
+          above the instruction it lands on
+        - K, F, frame, data and rodata operands get what they refer to after a ;
+        - with the sources each source line is printed above the instructions generated for
+          it, a line shows up again when the code goes back to it (a loop's condition)
+*/
+
+// sources[file] is the text of program.files[file], None or a missing entry leaves that
+// file's source lines out
+pub fn disassemble(program: &Bytecode, sources: &[Option<String>]) -> String {
+    let mut out = String::new();
+    for (idx, func) in program.functions.iter().enumerate() {
+        let source: Option<Vec<&str>> = sources.get(func.file as usize)
+            .and_then(|text| text.as_deref())
+            .map(|text| text.lines().collect());
+        function(&mut out, program, idx, func, source.as_deref());
+    }
+    out
+}
+
+fn function(out: &mut String, program: &Bytecode, idx: usize, func: &FunctionChunk, source: Option<&[&str]>) {
+    let file = program.files.get(func.file as usize).map_or("?", |name| name.as_str());
+    writeln!(out, "\n=== Function: {} (F{}, {}) ===", func.name, idx, file).unwrap();
+    writeln!(out, "Registers: {} (r0-r{}), arity {}, frame {} bytes", func.max_registers as usize + 1, func.max_registers, func.arity, func.frame_size).unwrap();
+
+    let instructions: Vec<Option<Instruction>> = func.instructions.iter().map(|&word| Instruction::decode(word)).collect();
+    let labels: BTreeSet<usize> = instructions.iter().enumerate()
+        .filter_map(|(pc, instr)| match instr {
+            Some(Instruction::AsBx { offset, .. }) => Some(jump_target(pc, *offset)),
+            _ => None,
+        })
+        .collect();
+
+    let mut last_line = 0;
+    for (pc, instr) in instructions.iter().enumerate() {
+        let line = func.lines.get(pc).copied().unwrap_or(0);
+        if let Some(source) = source
+            && line != 0
+            && line != last_line
+            && let Some(text) = source.get(line as usize - 1) {
+            writeln!(out, "{:>6} | {}", line, text).unwrap();
+        }
+        last_line = line;
+
+        if labels.contains(&pc) {
+            writeln!(out, "L{:04}:", pc).unwrap();
+        }
+
+        let Some(instr) = instr else {
+            writeln!(out, "  {:04}: .word {:#010x}", pc, func.instructions[pc]).unwrap();
+            continue;
+        };
+        let opcode = match *instr {
+            Instruction::ABC { opcode, .. } | Instruction::ABx { opcode, .. } | Instruction::AsBx { opcode, .. } => opcode,
+        };
+        let (operands, comment) = operands(program, func, pc, instr);
+        let text = format!("  {:04}: {:<8} {}", pc, format!("{:?}", opcode), operands);
+        match comment {
+            Some(comment) => writeln!(out, "{:<40} ; {}", text, comment).unwrap(),
+            None => writeln!(out, "{}", text.trim_end()).unwrap(),
+        }
+    }
+
+    // a jump to the very end, past the last instruction
+    if labels.contains(&instructions.len()) {
+        writeln!(out, "L{:04}:", instructions.len()).unwrap();
+    }
+
+    if !func.constants.is_empty() {
+        writeln!(out, "\nConstants:").unwrap();
+        for (i, val) in func.constants.iter().enumerate() {
+            writeln!(out, "  K{}: {}", i, val).unwrap();
+        }
+    }
+}
+
+// the operands as they're written, and what they refer to when there's something to say
+fn operands(program: &Bytecode, func: &FunctionChunk, pc: usize, instr: &Instruction) -> (String, Option<String>) {
+    match *instr {
+        Instruction::ABx { opcode, a, bx } => match opcode {
+            OpCode::LOADK => (format!("r{}, K{}", a, bx), func.constants.get(bx as usize).map(|k| k.to_string())),
+            OpCode::CLOSURE => (format!("r{}, F{}", a, bx), program.functions.get(bx as usize).map(|f| f.name.clone())),
+            OpCode::JMPTAB => (format!("r{}, {}", a, bx), Some(format!("r{} in 0..{} picks a JMP after the default", a, bx))),
+            OpCode::ADDR => (format!("r{}, frame+{}", a, bx), slot_name(&func.slots, bx)),
+            OpCode::GADDR => (format!("r{}, data+{}", a, bx), slot_name(&program.data_slots, bx)),
+            OpCode::SADDR => (format!("r{}, rodata+{}", a, bx), slot_name(&program.literals, bx)),
+            _ => (format!("r{}, {}", a, bx), None),
+        },
+
+        Instruction::AsBx { offset, .. } => (format!("L{:04}", jump_target(pc, offset)), None),
+
+        Instruction::ABC { opcode, a, b, c } => match opcode {
+            OpCode::MOV | OpCode::UNM | OpCode::NOT | OpCode::BNOT | OpCode::FUNM
+            | OpCode::I2F | OpCode::U2F | OpCode::F2I | OpCode::F2U | OpCode::F2S => (format!("r{}, r{}", a, b), None),
+            OpCode::SEXT | OpCode::ZEXT => (format!("r{}, r{}, {}", a, b, c), Some(format!("low {} bits", c))),
+            OpCode::LOAD => (format!("r{}, [r{}], {}", a, b, width(c)), None),
+            OpCode::STORE => (format!("[r{}], r{}, {}", a, b, width(c)), None),
+            OpCode::COPY => (format!("[r{}], [r{}], r{}", a, b, c), None),
+            OpCode::CALL => (format!("r{}, {}, {}", a, b, c), Some(format!("{} args, {} results", b.saturating_sub(1), c.saturating_sub(1)))),
+            OpCode::CALLN => (format!("r{}, {}, {}", a, b, Builtin::from_code(c).map_or("?", |builtin| builtin.name())), None),
+            OpCode::RETURN if b == 2 => (format!("r{}", a), None),
+            OpCode::RETURN => (String::new(), None),
+            OpCode::TEST => (format!("r{}", a), Some("skips the next instruction if nonzero".to_string())),
+            _ => (format!("r{}, r{}, r{}", a, b, c), None),
+        },
+    }
+}
+
+// pc is incremented before a JMP runs, so the offset counts from the next instruction
+fn jump_target(pc: usize, offset: i32) -> usize {
+    (pc as i64 + 1 + offset as i64) as usize
+}
+
+fn width(code: u16) -> String {
+    Width::from_code(code).map_or(format!("?{}", code), |width| width.to_string())
+}
+
+// the variable an offset lands in, name+n when it's partway in
+fn slot_name(slots: &[Slot], offset: u32) -> Option<String> {
+    let slot = slots.iter().find(|slot| offset >= slot.offset && offset < slot.offset + slot.size.max(1))?;
+    Some(match offset - slot.offset {
+        0 => slot.name.clone(),
+        n => format!("{}+{}", slot.name, n),
+    })
+}
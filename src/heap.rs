@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+// the heap sits above everything else, stack frames have to stay below it.
+// memory grows to fit the heap as it's used, up to the limit
+pub const HEAP_START: usize = 1 << 20;
+const HEAP_LIMIT: usize = 64 << 20;
+
+// the first collection waits for this many live bytes, after that it waits for the heap to double
+const GC_THRESHOLD: usize = 64 << 10;
+
+/*
+    Blocks:
+        - every block is 8 byte aligned and a multiple of 8 long, malloc(0) still gets 8
+        - bookkeeping lives out here, not in memory, so a C program can't scribble over it
+        - free gaps are merged with their neighbours, a gap that reaches top just lowers top
+        - first fit, then fresh memory past top
+
+    Collector (--gc):
+        - conservative mark-sweep, anything that looks like an address inside a block keeps
+          the block alive, interior pointers included
+        - roots are the live registers, the data segment and the stack frames, the vm passes
+          them in as 8 byte words
+        - marked blocks get scanned for more addresses, everything left unmarked is freed
+        - runs when malloc would push the live bytes past the threshold, or when it runs out
+*/
+
+#[derive(Default)]
+pub struct HeapStats {
+    pub allocations: usize,
+    pub allocated_bytes: usize,
+    pub frees: usize,
+    pub collections: usize,
+    pub reclaimed_blocks: usize,
+    pub reclaimed_bytes: usize,
+    pub peak_bytes: usize,
+}
+
+pub struct Heap {
+    /// address -> size of every block handed out and not freed yet
+    blocks: BTreeMap<usize, usize>,
+
+    /// address -> size of the gaps below top
+    free: BTreeMap<usize, usize>,
+
+    /// end of the heap, past here memory has never been handed out
+    top: usize,
+
+    live_bytes: usize,
+
+    /// collect before live_bytes passes this
+    next_collection: usize,
+
+    /// collector mode, unreachable blocks are reclaimed without a free
+    pub collect: bool,
+
+    pub stats: HeapStats,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            blocks: BTreeMap::new(),
+            free: BTreeMap::new(),
+            top: HEAP_START,
+            live_bytes: 0,
+            next_collection: GC_THRESHOLD,
+            collect: false,
+            stats: HeapStats::default(),
+        }
+    }
+
+    // address of a new block of at least size bytes, None when the heap is full
+    pub fn alloc(&mut self, memory: &mut Vec<u8>, size: usize) -> Option<usize> {
+        if size > HEAP_LIMIT - HEAP_START {
+            return None;
+        }
+        let size = size.max(1).next_multiple_of(8);
+
+        let gap = self.free.iter().find(|&(_, &len)| len >= size).map(|(&addr, &len)| (addr, len));
+        let addr = match gap {
+            Some((addr, len)) => {
+                self.free.remove(&addr);
+                if len > size {
+                    self.free.insert(addr + size, len - size);
+                }
+                addr
+            }
+            None => {
+                if self.top + size > HEAP_LIMIT {
+                    return None;
+                }
+                let addr = self.top;
+                self.top += size;
+                if memory.len() < self.top {
+                    memory.resize(self.top, 0);
+                }
+                addr
+            }
+        };
+
+        self.blocks.insert(addr, size);
+        self.live_bytes += size;
+        self.stats.allocations += 1;
+        self.stats.allocated_bytes += size;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.live_bytes);
+        Some(addr)
+    }
+
+    // free(p), p has to be exactly what malloc returned
+    pub fn free(&mut self, addr: usize) -> Result<(), String> {
+        let size = self.blocks.remove(&addr)
+            .ok_or_else(|| format!("free of address {} which is not an allocated block", addr))?;
+        self.release(addr, size);
+        self.stats.frees += 1;
+        Ok(())
+    }
+
+    // size of the block starting at addr
+    pub fn block_size(&self, addr: usize) -> Option<usize> {
+        self.blocks.get(&addr).copied()
+    }
+
+    pub fn wants_collection(&self, size: usize) -> bool {
+        self.collect && self.live_bytes + size > self.next_collection
+    }
+
+    pub fn collect(&mut self, memory: &[u8], roots: impl Iterator<Item = i64>) {
+        let mut marked = HashSet::new();
+        let mut pending = vec![];
+        for word in roots {
+            if let Some(addr) = self.block_containing(word)
+                && marked.insert(addr) {
+                pending.push(addr);
+            }
+        }
+
+        while let Some(addr) = pending.pop() {
+            let size = self.blocks[&addr];
+            for word in memory[addr..addr + size].chunks_exact(8) {
+                let word = i64::from_le_bytes(word.try_into().unwrap());
+                if let Some(inner) = self.block_containing(word)
+                    && marked.insert(inner) {
+                    pending.push(inner);
+                }
+            }
+        }
+
+        let unreachable: Vec<(usize, usize)> = self.blocks.iter()
+            .filter(|(addr, _)| !marked.contains(*addr))
+            .map(|(&addr, &size)| (addr, size))
+            .collect();
+        for (addr, size) in unreachable {
+            self.blocks.remove(&addr);
+            self.release(addr, size);
+            self.stats.reclaimed_blocks += 1;
+            self.stats.reclaimed_bytes += size;
+        }
+
+        self.stats.collections += 1;
+        self.next_collection = (self.live_bytes * 2).max(GC_THRESHOLD);
+    }
+
+    fn block_containing(&self, word: i64) -> Option<usize> {
+        let word = usize::try_from(word).ok().filter(|&w| w >= HEAP_START && w < self.top)?;
+        let (&addr, &size) = self.blocks.range(..=word).next_back()?;
+        (word < addr + size).then_some(addr)
+    }
+
+    // gives a block's memory back, merged with any gap right before or after it
+    fn release(&mut self, addr: usize, size: usize) {
+        self.live_bytes -= size;
+
+        let mut start = addr;
+        let mut len = size;
+        if let Some((&prev, &prev_len)) = self.free.range(..addr).next_back()
+            && prev + prev_len == addr {
+            self.free.remove(&prev);
+            start = prev;
+            len += prev_len;
+        }
+        if let Some(next_len) = self.free.remove(&(addr + size)) {
+            len += next_len;
+        }
+
+        if start + len == self.top {
+            self.top = start;
+        } else {
+            self.free.insert(start, len);
+        }
+    }
+}
+
+// the --gc-stats report
+impl fmt::Display for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = &self.stats;
+        writeln!(f, "collector: {}", if self.collect { "on" } else { "off" })?;
+        writeln!(f, "allocations: {} ({} bytes)", stats.allocations, stats.allocated_bytes)?;
+        writeln!(f, "frees: {}", stats.frees)?;
+        writeln!(f, "collections: {}", stats.collections)?;
+        writeln!(f, "reclaimed: {} blocks ({} bytes)", stats.reclaimed_blocks, stats.reclaimed_bytes)?;
+        writeln!(f, "live at exit: {} blocks ({} bytes)", self.blocks.len(), self.live_bytes)?;
+        write!(f, "peak: {} bytes", stats.peak_bytes)
+    }
+}
@@ -1,4 +1,6 @@
+use std::fmt;
 
+use crate::span::Span;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -108,10 +110,126 @@ pub enum Token {
     EOF,
 }
 
+// how a token looks in source, used by the parser's expected/found messages
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::IntLiteral(n) => return write!(f, "integer literal `{}`", n),
+            Token::FloatLiteral(n) => return write!(f, "float literal `{}`", n),
+            Token::CharLiteral(c) => return write!(f, "char literal {:?}", c),
+            Token::StringLiteral(s) => return write!(f, "string literal {:?}", s),
+            Token::BoolLiteral(b) => return write!(f, "`{}`", b),
+            Token::Ident(name) => return write!(f, "identifier `{}`", name),
+            Token::EOF => return write!(f, "end of file"),
+
+            Token::Int => "int",
+            Token::Char => "char",
+            Token::Short => "short",
+            Token::Long => "long",
+            Token::Float => "float",
+            Token::Double => "double",
+            Token::Void => "void",
+            Token::Bool => "bool",
+            Token::Signed => "signed",
+            Token::Unsigned => "unsigned",
+
+            Token::Struct => "struct",
+            Token::Union => "union",
+            Token::Enum => "enum",
+            Token::Typedef => "typedef",
+            Token::Const => "const",
+            Token::Static => "static",
+            Token::Extern => "extern",
+            Token::Sizeof => "sizeof",
+
+            Token::Return => "return",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::Do => "do",
+            Token::For => "for",
+            Token::Switch => "switch",
+            Token::Case => "case",
+            Token::Default => "default",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Goto => "goto",
+            Token::Null => "null",
+
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::PlusPlus => "++",
+            Token::MinusMinus => "--",
+
+            Token::Eq => "==",
+            Token::NotEq => "!=",
+            Token::Lt => "<",
+            Token::Gt => ">",
+            Token::Le => "<=",
+            Token::Ge => ">=",
+
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Not => "!",
+
+            Token::Ampersand => "&",
+            Token::Pipe => "|",
+            Token::Tilde => "~",
+            Token::Caret => "^",
+            Token::LShift => "<<",
+            Token::RShift => ">>",
+
+            Token::Assign => "=",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::StarAssign => "*=",
+            Token::SlashAssign => "/=",
+            Token::PercentAssign => "%=",
+            Token::AndAssign => "&=",
+            Token::OrAssign => "|=",
+            Token::XorAssign => "^=",
+            Token::LShiftAssign => "<<=",
+            Token::RShiftAssign => ">>=",
+
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::Semicolon => ";",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Arrow => "->",
+            Token::Question => "?",
+            Token::Colon => ":",
+        };
+        write!(f, "`{}`", text)
+    }
+}
+
+// token + where it started in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        SpannedToken { token, span }
+    }
+}
+
 // lexer / tokenizer
 pub struct Lexer {
     input: Vec<char>,
     pos: usize,
+    line: usize,
+    col: usize,
 }
 
 impl Lexer {
@@ -119,6 +237,8 @@ impl Lexer {
         Lexer {
             input: input.chars().collect(),
             pos: 0,
+            line: 1,
+            col: 1,
         }
     }
 
@@ -129,10 +249,22 @@ impl Lexer {
     fn advance(&mut self) -> Option<char> {
         let ch = self.peek();
         self.pos += 1;
+
+        // keep line/col in sync so every token knows where it came from
+        if ch == Some('\n') {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
         ch
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    fn current_span(&self) -> Span {
+        Span::new(self.line, self.col, self.pos)
+    }
+
+    pub fn tokenize(&mut self) -> Vec<SpannedToken> {
         let mut tokens = vec![];
         while self.pos < self.input.len() {
             while self.pos < self.input.len() && self.input[self.pos].is_whitespace() {
@@ -143,6 +275,7 @@ impl Lexer {
                 Some(c) => c,
                 None => break,
             };
+            let span = self.current_span();
 
             // check for comments
             if ch == '/' && self.input.get(self.pos + 1) == Some(&'/') {
@@ -183,9 +316,9 @@ impl Lexer {
                         }
                     }
                     if hex.is_empty() {
-                        panic!("Invalid hex literal");
+                        panic!("{}: Invalid hex literal", span);
                     }
-                    tokens.push(Token::IntLiteral(i64::from_str_radix(&hex, 16).unwrap()));
+                    tokens.push(SpannedToken::new(Token::IntLiteral(i64::from_str_radix(&hex, 16).unwrap()), span));
                     continue;
                 }
                 
@@ -198,15 +331,15 @@ impl Lexer {
                             octal.push(c);
                             self.advance();
                         } else if c.is_ascii_digit() {
-                            panic!("Invalid octal digit: {}", c);
+                            panic!("{}: Invalid octal digit: {}", span, c);
                         } else {
                             break;
                         }
                     }
                     if octal.is_empty() {
-                        tokens.push(Token::IntLiteral(0));
+                        tokens.push(SpannedToken::new(Token::IntLiteral(0), span));
                     } else {
-                        tokens.push(Token::IntLiteral(i64::from_str_radix(&octal, 8).unwrap()));
+                        tokens.push(SpannedToken::new(Token::IntLiteral(i64::from_str_radix(&octal, 8).unwrap()), span));
                     }
                     continue;
                 }
@@ -269,9 +402,9 @@ impl Lexer {
                 }
                     
                 if is_float {
-                    tokens.push(Token::FloatLiteral(num.parse().unwrap()));
+                    tokens.push(SpannedToken::new(Token::FloatLiteral(num.parse().unwrap()), span));
                 } else {
-                    tokens.push(Token::IntLiteral(num.parse().unwrap()));
+                    tokens.push(SpannedToken::new(Token::IntLiteral(num.parse().unwrap()), span));
                 }
                 continue;
             }
@@ -318,7 +451,7 @@ impl Lexer {
                                 }
                                 char::from_u32(u32::from_str_radix(&octal, 8).unwrap()).unwrap()
                             },
-                            _ => panic!("Invalid escape sequence"),
+                            _ => panic!("{}: Invalid escape sequence", span),
                         }
                     },
                     Some(c) => {
@@ -326,14 +459,14 @@ impl Lexer {
                         self.advance();
                         ch
                     },
-                    None => panic!("Unterminated character literal"),
+                    None => panic!("{}: Unterminated character literal", span),
                 };
                 
                 if self.peek() != Some('\'') {
-                    panic!("Expected closing ' for character literal");
+                    panic!("{}: Expected closing ' for character literal", span);
                 }
                 self.advance();
-                tokens.push(Token::CharLiteral(c));
+                tokens.push(SpannedToken::new(Token::CharLiteral(c), span));
                 continue;
             }
 
@@ -368,21 +501,21 @@ impl Lexer {
                                     let val = u32::from_str_radix(&hex, 16).unwrap();
                                     s.push(char::from_u32(val).unwrap());
                                 },
-                                _ => panic!("Invalid escape sequence in string"),
+                                _ => panic!("{}: Invalid escape sequence in string", span),
                             }
                         },
                         Some(c) => {
                             s.push(c);
                             self.advance();
                         },
-                        None => panic!("Unterminated string literal"),
+                        None => panic!("{}: Unterminated string literal", span),
                     }
                 }
                 if self.peek() != Some('"') {
-                    panic!("Expected closing \" for string literal");
+                    panic!("{}: Expected closing \" for string literal", span);
                 }
                 self.advance();
-                tokens.push(Token::StringLiteral(s));
+                tokens.push(SpannedToken::new(Token::StringLiteral(s), span));
                 continue;
             }
 
@@ -435,6 +568,7 @@ impl Lexer {
                     "continue" => Token::Continue,
                     "goto"     => Token::Goto,    
                     "null"     => Token::Null,
+                    "NULL"     => Token::Null,
                     
                     // bools
                     "true"     => Token::BoolLiteral(true),
@@ -443,7 +577,7 @@ impl Lexer {
                     _          => Token::Ident(word),
                 };
 
-                tokens.push(token);
+                tokens.push(SpannedToken::new(token, span));
                 continue;
             }
 
@@ -460,7 +594,7 @@ impl Lexer {
                         self.advance();
                         self.advance();
                         self.advance();
-                        tokens.push(tok);
+                        tokens.push(SpannedToken::new(tok, span));
                         continue;
                     }
                 }
@@ -494,7 +628,7 @@ impl Lexer {
                 if let Some(tok) = token {
                     self.advance();
                     self.advance();
-                    tokens.push(tok);
+                    tokens.push(SpannedToken::new(tok, span));
                     continue;
                 }
             }
@@ -526,11 +660,11 @@ impl Lexer {
                 ']' => Token::RBracket,
                 '?' => Token::Question,
                 ':' => Token::Colon,
-                _ => panic!("Unexpected character: {}", ch),
+                _ => panic!("{}: Unexpected character: {}", span, ch),
             };
-            tokens.push(token);
+            tokens.push(SpannedToken::new(token, span));
         }
-        tokens.push(Token::EOF);
+        tokens.push(SpannedToken::new(Token::EOF, self.current_span()));
         tokens
     }
 }
\ No newline at end of file
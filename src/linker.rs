@@ -0,0 +1,148 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Declaration, Program};
+use crate::builtins::Builtin;
+use crate::codegen::CodeGenerator;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::span::Span;
+
+/*
+    Linking (cvm a.c b.c ...):
+        - every file is lexed, parsed and analyzed on its own, it only knows the functions and
+          globals it declares itself
+        - a file scope function or variable that isn't static is external. any file can declare
+          it (a prototype or an extern) and exactly one file defines it
+        - a static one belongs to its file, another file can have its own with the same name
+        - a name a file uses without defining it has to be defined by another file, otherwise
+          it's an undefined symbol. declared and never used is fine, like a real linker
+        - once the names check out codegen places every file's definitions and resolves the
+          other files' declarations to them, see CodeGenerator::gen_units
+*/
+
+// one source file, parsed and analyzed
+pub struct Unit {
+    pub filename: String,
+    pub source: String,
+    pub program: Program,
+
+    /// where each file scope name is first used, from semantic analysis
+    pub uses: HashMap<String, Span>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Function,
+    Variable,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Function => "function",
+            Kind::Variable => "variable",
+        }
+    }
+}
+
+// where a symbol is declared or defined, unit is an index into the units being linked
+#[derive(Clone, Copy)]
+struct Location {
+    unit: usize,
+    span: Span,
+    kind: Kind,
+}
+
+// checks the units' symbols against each other and compiles them into one program.
+// the diagnostics' files are indices into units
+pub fn link(units: &[Unit]) -> Result<CodeGenerator, Vec<Diagnostic>> {
+    let errors = check_symbols(units);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let programs: Vec<&Program> = units.iter().map(|unit| &unit.program).collect();
+    let mut codegen = CodeGenerator::new();
+    codegen.gen_units(&programs)?;
+    Ok(codegen)
+}
+
+fn check_symbols(units: &[Unit]) -> Vec<Diagnostic> {
+    let mut errors = vec![];
+
+    // external definitions, and static ones so an undefined symbol can say where a private one is
+    let mut external: HashMap<&str, Location> = HashMap::new();
+    let mut private: HashMap<&str, Location> = HashMap::new();
+
+    for (unit_idx, unit) in units.iter().enumerate() {
+        let statics = unit.program.static_names();
+        for (name, span, kind) in symbols(&unit.program, true) {
+            let location = Location { unit: unit_idx, span, kind };
+            if statics.contains(name) {
+                private.entry(name).or_insert(location);
+                continue;
+            }
+            match external.get(name) {
+                Some(first) => errors.push(Diagnostic::error(Code::DuplicateSymbol, span, format!("Duplicate definition of '{}'", name))
+                    .in_file(unit_idx)
+                    .with_label_in(first.unit, first.span, "first defined here")),
+                None => {
+                    external.insert(name, location);
+                }
+            }
+        }
+    }
+
+    for (unit_idx, unit) in units.iter().enumerate() {
+        let statics = unit.program.static_names();
+        let defined: HashSet<&str> = symbols(&unit.program, true).into_iter().map(|(name, _, _)| name).collect();
+
+        for (name, decl_span, kind) in symbols(&unit.program, false) {
+            let Some(&use_span) = unit.uses.get(name) else { continue };
+            if defined.contains(name) {
+                continue;
+            }
+
+            let definition = if statics.contains(name) { None } else { external.get(name) };
+            match definition {
+                Some(def) if def.kind == kind => {}
+                Some(def) => errors.push(Diagnostic::error(Code::ConflictingSymbol, decl_span, format!(
+                        "'{}' is declared as a {} but defined as a {}", name, kind.name(), def.kind.name()))
+                    .in_file(unit_idx)
+                    .with_label_in(def.unit, def.span, "defined here")),
+
+                // malloc and friends are defined by the vm
+                None if kind == Kind::Function && Builtin::from_name(name).is_some() => {}
+
+                None => {
+                    let mut err = Diagnostic::error(Code::UndefinedSymbol, use_span, format!("Undefined reference to {} '{}'", kind.name(), name))
+                        .in_file(unit_idx)
+                        .with_label(decl_span, "declared here");
+                    if let Some(other) = private.get(name).filter(|other| other.unit != unit_idx) {
+                        err = err.with_note(format!("'{}' is static in {}, only that file can use it", name, units[other.unit].filename));
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+// the first declaration of each file scope function and variable, or the definition when
+// definitions_only is set
+fn symbols(program: &Program, definitions_only: bool) -> Vec<(&str, Span, Kind)> {
+    let mut seen = HashSet::new();
+    let mut symbols = vec![];
+    for decl in &program.declarations {
+        let (name, kind, is_definition) = match decl {
+            Declaration::Function(func) => (func.name.as_str(), Kind::Function, func.body.is_some()),
+            Declaration::Variable(var) => (var.name.as_str(), Kind::Variable, var.is_definition()),
+            _ => continue,
+        };
+        if (is_definition || !definitions_only) && seen.insert(name) {
+            symbols.push((name, decl.span(), kind));
+        }
+    }
+    symbols
+}
@@ -1,21 +1,33 @@
 mod ast;
+mod builtins;
+mod bytecode;
+mod checker;
+mod span;
+mod diagnostic;
 mod lexer;
 mod parser;
 mod symbol_table;
 mod semantic;
 mod codegen;
+mod disasm;
+mod heap;
+mod linker;
 mod vm;
 
 use vm::VM;
 use lexer::Lexer;
-use parser::Parser;
+use parser::{ParseError, Parser};
 use ast::{Declaration, Program};
-use codegen::CodeGenerator;
+use bytecode::Bytecode;
+use linker::Unit;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::process;
 
+use crate::diagnostic::{Diagnostic, Source, WarningOptions};
 use crate::semantic::SemanticAnalyzer;
+use crate::span::Span;
 
 fn read_file(filename: &str) -> String {
     match fs::read_to_string(filename) {
@@ -27,33 +39,27 @@ fn read_file(filename: &str) -> String {
     }
 }
 
-fn lex(source: &str) -> Vec<lexer::Token> {
+fn lex(source: &str) -> Vec<lexer::SpannedToken> {
     let mut lexer = Lexer::new(source);
     lexer.tokenize()
 }
 
-fn parse(tokens: Vec<lexer::Token>) -> Program {
+fn parse(tokens: Vec<lexer::SpannedToken>, source: &str, filename: &str) -> Program {
     let mut parser = Parser::new(tokens);
-    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        parser.parse_program()
-    })) {
+    match parser.parse_program() {
         Ok(ast) => ast,
-        Err(_) => {
-            eprintln!("\nParsing failed!");
+        Err(errors) => {
+            print_syntax_errors(&errors, source, filename);
             process::exit(1);
         }
     }
 }
 
-fn analyze(ast: &Program) -> Result<(), Vec<String>> {
+// diagnostics, and where the file uses each file scope name for the linker
+fn analyze(ast: &Program, warning_options: &WarningOptions) -> (Vec<Diagnostic>, HashMap<String, Span>) {
     let mut analyzer = SemanticAnalyzer::new();
-    analyzer.analyze(ast)
-}
-
-fn compile(ast: &Program) -> CodeGenerator {
-    let mut codegen = CodeGenerator::new();
-    codegen.gen_program(ast);
-    codegen
+    let diagnostics = warning_options.filter(analyzer.analyze(ast));
+    (diagnostics, analyzer.global_uses().clone())
 }
 
 fn print_ast(ast: &Program) {
@@ -61,22 +67,43 @@ fn print_ast(ast: &Program) {
     println!("{:#?}", ast);
 }
 
-fn print_semantic_results(result: &Result<(), Vec<String>>) {
+fn print_syntax_errors(errors: &[ParseError], source: &str, filename: &str) {
+    println!("\n======== SYNTAX ERRORS ========");
+    println!("Found {} syntax error(s):\n", errors.len());
+    for err in errors {
+        println!("{}", Diagnostic::from(err).render(source, filename));
+    }
+}
+
+fn print_semantic_results(diagnostics: &[Diagnostic], source: &str, filename: &str) {
     println!("\n======== SEMANTIC ANALYSIS ========");
-    match result {
-        Ok(()) => println!("No semantic errors found"),
-        Err(errors) => {
-            println!("Found {} semantic error(s):\n", errors.len());
-            for (i, err) in errors.iter().enumerate() {
-                println!("  {}. {}", i + 1, err);
-            }
-        }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+
+    match (errors, warnings) {
+        (0, 0) => println!("No semantic errors found"),
+        (0, w) => println!("No semantic errors found, {} warning(s):\n", w),
+        (e, 0) => println!("Found {} semantic error(s):\n", e),
+        (e, w) => println!("Found {} semantic error(s) and {} warning(s):\n", e, w),
+    }
+
+    for diag in diagnostics {
+        println!("{}", diag.render(source, filename));
+    }
+}
+
+fn print_link_errors(errors: &[Diagnostic], units: &[Unit]) {
+    println!("\n======== LINK ERRORS ========");
+    println!("Found {} link error(s):\n", errors.len());
+    let sources: Vec<Source> = units.iter().map(|unit| Source { name: &unit.filename, text: &unit.source }).collect();
+    for err in errors {
+        println!("{}", err.render_in(&sources));
     }
 }
 
-fn print_codegen_results(codegen: &CodeGenerator) {
+fn print_codegen_results(program: &Bytecode) {
     println!("\n======== BYTECODE ========");
-    codegen.print_instructions();
+    print!("{}", disasm::disassemble(program, &[]));
 }
 
 fn print_summary(ast: &Program) {
@@ -134,38 +161,215 @@ fn print_summary(ast: &Program) {
     println!("Typedefs:  {}", typedef_count);
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+// flags for every command, each command only looks at the ones it uses
+struct Options {
+    warning_options: WarningOptions,
+    files: Vec<String>,
+    output: Option<String>,
+    gc: bool,
+    gc_stats: bool,
+    checked: bool,
+    source: bool,
+}
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <file.c>", args[0]);
-        process::exit(1);
+fn parse_options(args: &[String]) -> Options {
+    let mut options = Options {
+        warning_options: WarningOptions::new(),
+        files: vec![],
+        output: None,
+        gc: false,
+        gc_stats: false,
+        checked: false,
+        source: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--gc" {
+            options.gc = true;
+        } else if arg == "--gc-stats" {
+            options.gc_stats = true;
+        } else if arg == "--checked" {
+            options.checked = true;
+        } else if arg == "--source" {
+            options.source = true;
+        } else if arg == "-o" {
+            let Some(path) = args.next() else {
+                eprintln!("-o needs a file name");
+                process::exit(1);
+            };
+            options.output = Some(path.clone());
+        } else if arg.starts_with('-') {
+            if let Err(e) = options.warning_options.apply_flag(arg) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        } else {
+            options.files.push(arg.clone());
+        }
+    }
+    options
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} [--gc] [--gc-stats] [--checked] [-w] [-Wall] [-Werror] [-W<warning>] [-Wno-<warning>] <file.c>...", program);
+    eprintln!("       {} compile [-w] [-Wall] [-Werror] [-W<warning>] [-Wno-<warning>] -o <out.cvmb> <file.c>...", program);
+    eprintln!("       {} run [--gc] [--gc-stats] [--checked] <file.cvmb>", program);
+    eprintln!("       {} disasm [--source] <file.c>... | <file.cvmb>", program);
+    process::exit(1);
+}
+
+// compiles and links the files, or prints what's wrong and exits. verbose prints every stage
+// along the way, otherwise only the diagnostics
+fn build(files: &[String], warning_options: &WarningOptions, verbose: bool) -> Bytecode {
+    // each file is compiled on its own, the linker puts them together after
+    let mut units = vec![];
+    let mut has_errors = false;
+    for filename in files {
+        let source = read_file(filename);
+        let tokens = lex(&source);
+        let ast = parse(tokens, &source, filename);
+        let (diagnostics, uses) = analyze(&ast, warning_options);
+
+        if verbose {
+            print_ast(&ast);
+        }
+        if verbose || !diagnostics.is_empty() {
+            print_semantic_results(&diagnostics, &source, filename);
+        }
+
+        has_errors |= diagnostics.iter().any(|d| d.is_error());
+        units.push(Unit { filename: filename.clone(), source, program: ast, uses });
     }
 
-    let filename = &args[1];
+    let print_summaries = |units: &[Unit]| {
+        if verbose {
+            units.iter().for_each(|unit| print_summary(&unit.program));
+        }
+    };
 
-    let source = read_file(filename);
-    let tokens = lex(&source);
-    let ast = parse(tokens);
-    let semantic_result = analyze(&ast);
-    let codegen = compile(&ast);
+    // codegen assumes a well typed program, -Werror turns warnings into errors before we get here
+    if has_errors {
+        print_summaries(&units);
+        process::exit(1);
+    }
 
-    print_ast(&ast);
-    print_semantic_results(&semantic_result);
-    print_codegen_results(&codegen);
-    print_summary(&ast);
+    let program = match linker::link(&units) {
+        Ok(codegen) => Bytecode::new(codegen, files.to_vec()),
+        Err(errors) => {
+            print_link_errors(&errors, &units);
+            print_summaries(&units);
+            process::exit(1);
+        }
+    };
+    if verbose {
+        print_codegen_results(&program);
+    }
+    print_summaries(&units);
 
-    if semantic_result.is_err() {
+    // the same checks a loaded file gets, a program too big for memory only shows up here
+    if let Err(e) = program.validate() {
+        eprintln!("error: {}", e);
         process::exit(1);
     }
+    program
+}
+
+fn load(filename: &str) -> Bytecode {
+    let bytes = fs::read(filename).unwrap_or_else(|e| {
+        eprintln!("error reading file '{}': {}", filename, e);
+        process::exit(1);
+    });
+    Bytecode::from_bytes(&bytes).unwrap_or_else(|e| {
+        eprintln!("error loading '{}': {}", filename, e);
+        process::exit(1);
+    })
+}
 
+fn run(program: Bytecode, options: &Options) {
     let mut vm = VM::new(
-        codegen.functions,
-        codegen.function_map,
+        program.functions,
+        program.function_map,
+        program.data,
+        program.rodata,
     );
 
+    vm.heap.collect = options.gc;
+    if options.checked {
+        vm.enable_checks(program.data_slots, program.literals);
+    }
+
     let result = vm.run();
 
     println!("\n======== VM RESULT ========");
     println!("Program returned: {}", result);
-}
\ No newline at end of file
+
+    if options.gc_stats {
+        println!("\n======== GC STATS ========");
+        println!("{}", vm.heap);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let command = args.get(1).map(String::as_str).filter(|arg| ["compile", "run", "disasm"].contains(arg));
+    let options = parse_options(&args[if command.is_some() { 2 } else { 1 }..]);
+    if options.files.is_empty() {
+        usage(&args[0]);
+    }
+
+    match command {
+        // cvm compile -o out.cvmb a.c b.c, the program is written out instead of run
+        Some("compile") => {
+            let Some(output) = &options.output else { usage(&args[0]) };
+            let program = build(&options.files, &options.warning_options, false);
+            if let Err(e) = fs::write(output, program.to_bytes()) {
+                eprintln!("error writing file '{}': {}", output, e);
+                process::exit(1);
+            }
+        }
+
+        // cvm run out.cvmb, no source needed
+        Some("run") => {
+            let [filename] = options.files.as_slice() else { usage(&args[0]) };
+            let program = load(filename);
+            if !program.function_map.contains_key("main") {
+                eprintln!("error loading '{}': no main function to run", filename);
+                process::exit(1);
+            }
+            run(program, &options);
+        }
+
+        // cvm disasm a.c b.c or cvm disasm out.cvmb, --source puts the source lines in between
+        Some("disasm") => {
+            let is_bytecode = fs::read(&options.files[0]).is_ok_and(|bytes| bytes.starts_with(bytecode::MAGIC));
+            let program = if is_bytecode {
+                let [filename] = options.files.as_slice() else { usage(&args[0]) };
+                load(filename)
+            } else {
+                build(&options.files, &options.warning_options, false)
+            };
+
+            // a bytecode file only has the names of its sources, they're read from wherever they are now
+            let sources: Vec<Option<String>> = if options.source {
+                program.files.iter()
+                    .map(|name| fs::read_to_string(name)
+                        .inspect_err(|e| eprintln!("note: can't read source '{}' ({}), its lines are left out", name, e))
+                        .ok())
+                    .collect()
+            } else {
+                vec![]
+            };
+            print!("{}", disasm::disassemble(&program, &sources));
+        }
+
+        _ => {
+            let program = build(&options.files, &options.warning_options, true);
+
+            // files without main (a library of functions) compile fine, there's just nothing to run
+            if !program.function_map.contains_key("main") {
+                return;
+            }
+            run(program, &options);
+        }
+    }
+}
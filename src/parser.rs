@@ -1,117 +1,274 @@
+use std::fmt;
+
 use crate::ast::*;
-use crate::lexer::Token;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::{SpannedToken, Token};
+use crate::span::Span;
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub expected: String,
+    pub found: Token,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(expected: impl Into<String>, found: Token, span: Span) -> Self {
+        ParseError { expected: expected.into(), found, span }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: expected {}, found {}", self.span, self.expected, self.found)
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Self {
+        Diagnostic::error(Code::SyntaxError, err.span, format!("expected {}, found {}", err.expected, err.found))
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     pos: usize,
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
         Parser {
             tokens,
             pos: 0,
+            errors: vec![],
         }
     }
 
     fn peek(&self) -> &Token {
-        &self.tokens[self.pos]
+        &self.tokens[self.pos].token
     }
 
+    // n tokens ahead, stopping at EOF
+    fn peek_at(&self, n: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.pos + n).min(last)].token
+    }
+
+    // where the next token starts
+    fn span(&self) -> Span {
+        self.tokens[self.pos].span
+    }
+
+    // never moves past EOF so error recovery can't run off the end
     fn advance(&mut self) -> Token {
-        let tok = self.tokens[self.pos].clone();
-        self.pos += 1;
+        let tok = self.tokens[self.pos].token.clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
         tok
     }
 
-    fn expect(&mut self, expected: &Token) -> Token {
-        let token = self.advance();
-        if &token != expected {
-            panic!("Expected {:?}, got {:?}", expected, token);
+    // error pointing at the next token, which is left unconsumed
+    fn error(&self, expected: impl Into<String>) -> ParseError {
+        ParseError::new(expected, self.peek().clone(), self.span())
+    }
+
+    fn expect(&mut self, expected: &Token) -> ParseResult<Token> {
+        if self.peek() != expected {
+            return Err(self.error(expected.to_string()));
+        }
+        Ok(self.advance())
+    }
+
+    fn expect_ident(&mut self, what: &str) -> ParseResult<String> {
+        match self.peek() {
+            Token::Ident(name) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error(what)),
+        }
+    }
+
+    fn expect_int(&mut self, what: &str) -> ParseResult<i64> {
+        match self.peek() {
+            Token::IntLiteral(n) => {
+                let n = *n;
+                self.advance();
+                Ok(n)
+            }
+            _ => Err(self.error(what)),
         }
-        token
     }
 
-    pub fn parse_program(&mut self) -> Program {
+    // panic mode recovery: skip to the end of the broken statement or declaration
+    // a `;` is consumed, a `}` closing the enclosing block is left for the caller
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Token::EOF => return,
+                Token::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    // a nested block just closed, so whatever was broken ended with it
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    // statements up to (not including) a token matching `stop`, recovering from bad ones
+    fn parse_statements_until(&mut self, stop: fn(&Token) -> bool) -> Vec<Statement> {
+        let mut statements = vec![];
+        while !stop(self.peek()) && *self.peek() != Token::EOF {
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+        statements
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, Vec<ParseError>> {
         let mut declarations = vec![];
 
         while *self.peek() != Token::EOF {
-            declarations.push(self.parse_declaration());
+            match self.parse_declaration() {
+                Ok(decl) => declarations.push(decl),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                    // stray `}` at file scope (and the `;` of a struct or enum), nothing is waiting to close it
+                    if *self.peek() == Token::RBrace {
+                        self.advance();
+                        if *self.peek() == Token::Semicolon {
+                            self.advance();
+                        }
+                    }
+                }
+            }
         }
 
-        Program { declarations }
+        if self.errors.is_empty() {
+            Ok(Program { declarations })
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
-    fn parse_declaration(&mut self) -> Declaration {
+    fn parse_declaration(&mut self) -> ParseResult<Declaration> {
+        // struct Point p; and struct Point make(...) use the type, only a body defines it
+        if matches!(self.peek(), Token::Struct | Token::Union | Token::Enum)
+            && matches!(self.peek_at(1), Token::Ident(_))
+            && *self.peek_at(2) != Token::LBrace
+        {
+            return self.parse_function_or_variable();
+        }
+
         match self.peek() {
-            Token::Struct => Declaration::Struct(self.parse_struct()),
-            Token::Union => Declaration::Union(self.parse_union()),
-            Token::Enum => Declaration::Enum(self.parse_enum()),
-            Token::Typedef => Declaration::Typedef(self.parse_typedef()),
+            Token::Struct => Ok(Declaration::Struct(self.parse_struct()?)),
+            Token::Union => Ok(Declaration::Union(self.parse_union()?)),
+            Token::Enum => Ok(Declaration::Enum(self.parse_enum()?)),
+            Token::Typedef => Ok(Declaration::Typedef(self.parse_typedef()?)),
             _ => {
                 self.parse_function_or_variable()
             }
         }
     }
 
-    fn parse_function_or_variable(&mut self) -> Declaration {
+    fn parse_function_or_variable(&mut self) -> ParseResult<Declaration> {
+        let span = self.span();
         let storage_class = self.parse_storage_class();
-        let qualified_type = self.parse_qualified_type();
+        let qualified_type = self.parse_qualified_type()?;
+        let name_span = self.declarator_span();
 
-        let name = match self.advance() {
-            Token::Ident(n) => n,
-            other => panic!("expected identifier, got {:?}", other),
-        };
+        // int (*handler)(int);
+        if self.is_function_pointer() {
+            let (name, typ) = self.parse_declarator(qualified_type, "identifier")?;
+            return self.finish_parse_global(storage_class, typ, name, span, name_span);
+        }
+
+        let name = self.expect_ident("identifier")?;
 
         match self.peek() {
             // function dec
             Token::LParen => {
-                self.finish_parse_function(storage_class, qualified_type, name)
+                self.finish_parse_function(storage_class, qualified_type, name, span, name_span)
             }
 
             // either assignment or global variable dec
             Token::Semicolon | Token::Assign | Token::LBracket => {
-                let mut typ = qualified_type;
-                
                 // int arr[10];
-                while *self.peek() == Token::LBracket {
-                    self.advance();
-                    let size = if *self.peek() == Token::RBracket {
-                        None
-                    } else {
-                        match self.advance() {
-                            Token::IntLiteral(n) => Some(n as usize),
-                            other => panic!("Expected array size, got {:?}", other),
-                        }
-                    };
-                    self.expect(&Token::RBracket);
-                    typ = QualifiedType {
-                        base: Type::Array(Box::new(typ.base), size),
-                        is_const: typ.is_const,
-                    };
-                }
-                
-                let init = if *self.peek() == Token::Assign {
-                    self.advance();
-                    Some(self.parse_expression())
-                } else {
-                    None
-                };
-                self.expect(&Token::Semicolon);
-
-                Declaration::Variable(VarDec {
-                    name,
-                    typ,
-                    init,
-                    storage_class 
-                })
+                let typ = self.parse_array_suffix(qualified_type)?;
+                self.finish_parse_global(storage_class, typ, name, span, name_span)
             }
-            other => panic!("Expected '(' or ';' after identifier, got {:?}", other),
+            _ => Err(self.error("`(`, `=` or `;` after declaration name")),
         }
     }
 
-    fn finish_parse_function(&mut self, storage_class: StorageClass, return_type: QualifiedType, name: String) -> Declaration {
-        self.expect(&Token::LParen);
+    fn finish_parse_global(&mut self, storage_class: StorageClass, typ: QualifiedType, name: String, span: Span, name_span: Span) -> ParseResult<Declaration> {
+        let init = if *self.peek() == Token::Assign {
+            self.advance();
+            Some(self.parse_initializer()?)
+        } else {
+            None
+        };
+        self.expect(&Token::Semicolon)?;
+
+        Ok(Declaration::Variable(VarDec {
+            name,
+            typ,
+            init,
+            storage_class,
+            span,
+            name_span,
+        }))
+    }
+
+    fn finish_parse_function(&mut self, storage_class: StorageClass, return_type: QualifiedType, name: String, span: Span, name_span: Span) -> ParseResult<Declaration> {
+        let params = self.parse_parameters()?;
+
+        let body = if *self.peek() == Token::LBrace {
+            Some(self.parse_block()?)
+        } else {
+            self.expect(&Token::Semicolon)?;
+            None
+        };
+
+        Ok(Declaration::Function(FunctionDec {
+            name,
+            params,
+            return_type,
+            body,
+            storage_class,
+            span,
+            name_span,
+        }))
+    }
+
+    // (int a, int b), (void) and () are both no params
+    fn parse_parameters(&mut self) -> ParseResult<Vec<Param>> {
+        self.expect(&Token::LParen)?;
 
         let mut params = vec![];
 
@@ -122,37 +279,38 @@ impl Parser {
                 self.advance();
             } else {
                 self.pos = checkpoint;
-                params = self.parse_parameter_list();
-                self.expect(&Token::RParen);
+                params = self.parse_parameter_list()?;
+                self.expect(&Token::RParen)?;
             }
         } else if *self.peek() != Token::RParen {
-            params = self.parse_parameter_list();
-            self.expect(&Token::RParen);
+            params = self.parse_parameter_list()?;
+            self.expect(&Token::RParen)?;
         } else {
             self.advance();
         }
 
-        let body = if *self.peek() == Token::LBrace {
-            Some(self.parse_block())
-        } else {
-            self.expect(&Token::Semicolon);
-            None
-        };
-
-        Declaration::Function(FunctionDec {
-            name,
-            params,
-            return_type,
-            body,
-            storage_class,
-        })
+        Ok(params)
     }
 
-    fn parse_parameter_list(&mut self) -> Vec<Param> {
+    fn parse_parameter_list(&mut self) -> ParseResult<Vec<Param>> {
         let mut params = vec![];
 
         loop {
-            let typ = self.parse_qualified_type();
+            let span = self.span();
+            let typ = self.parse_qualified_type()?;
+            let name_span = self.declarator_span();
+
+            // int apply(int (*f)(int), int x)
+            if self.is_function_pointer() {
+                let (name, typ) = self.parse_function_pointer(typ, None)?;
+                params.push(Param { name, typ, span, name_span });
+                if *self.peek() == Token::Comma {
+                    self.advance();
+                    continue;
+                }
+                break;
+            }
+
             let name = match self.peek() {
                 Token::Ident(n) => {
                     let name = n.clone();
@@ -164,7 +322,7 @@ impl Parser {
                 _ => None, 
             };
 
-            params.push(Param { name, typ });
+            params.push(Param { name, typ, span, name_span });
 
             if *self.peek() == Token::Comma {
                 self.advance();
@@ -173,11 +331,12 @@ impl Parser {
             }
         }
 
-        params
+        Ok(params)
     }
 
-    fn parse_struct(&mut self) -> StructDec {
-        self.expect(&Token::Struct);
+    fn parse_struct(&mut self) -> ParseResult<StructDec> {
+        let span = self.span();
+        self.expect(&Token::Struct)?;
 
         let name = match self.peek() {
             Token::Ident(n) => {
@@ -189,30 +348,30 @@ impl Parser {
             _ => None,
         };
 
-        self.expect(&Token::LBrace);
+        self.expect(&Token::LBrace)?;
 
         let mut fields = vec![];
         while *self.peek() != Token::RBrace {
-            let field_type = self.parse_qualified_type();
-            let field_name = match self.advance() {
-                Token::Ident(n) => n,
-                other => panic!("Expected field name, got {:?}", other)
-            };
-            self.expect(&Token::Semicolon);
+            let field_span = self.span();
+            let field_type = self.parse_qualified_type()?;
+            let (field_name, field_type) = self.parse_declarator(field_type, "field name")?;
+            self.expect(&Token::Semicolon)?;
             fields.push(StructField {
                 name: field_name,
                 typ: field_type,
+                span: field_span,
             });
         }
 
-        self.expect(&Token::RBrace);
-        self.expect(&Token::Semicolon);
+        self.expect(&Token::RBrace)?;
+        self.expect(&Token::Semicolon)?;
 
-        StructDec { name, fields }
+        Ok(StructDec { name, fields, span })
     }
 
-    fn parse_enum(&mut self) -> EnumDec {
-        self.expect(&Token::Enum);
+    fn parse_enum(&mut self) -> ParseResult<EnumDec> {
+        let span = self.span();
+        self.expect(&Token::Enum)?;
 
         let name = match self.peek() {
             Token::Ident(n) => {
@@ -224,25 +383,19 @@ impl Parser {
             _ => None,
         };
 
-        self.expect(&Token::LBrace);
+        self.expect(&Token::LBrace)?;
         let mut variants = vec![];
         let mut next_value = 0i64;
 
         while *self.peek() != Token::RBrace {
-            let variant_name = match self.advance() {
-                Token::Ident(n) => n,
-                other => panic!("Expected enum variant name, got {:?}", other),
-            };
+            let variant_span = self.span();
+            let variant_name = self.expect_ident("enum variant name")?;
 
             let value = if *self.peek() == Token::Assign {
                 self.advance();
-                match self.advance() {
-                    Token::IntLiteral(n) => {
-                        next_value = n + 1;
-                        Some(n)
-                    }
-                    other => panic!("Expected integer in enum, got {:?}", other),
-                }
+                let n = self.expect_int("integer value for enum variant")?;
+                next_value = n + 1;
+                Some(n)
             } else {
                 let v = next_value;
                 next_value += 1;
@@ -252,6 +405,7 @@ impl Parser {
             variants.push(EnumVariant {
                 name: variant_name,
                 value,
+                span: variant_span,
             });
 
             if *self.peek() == Token::Comma {
@@ -261,14 +415,15 @@ impl Parser {
             }
         }
 
-        self.expect(&Token::RBrace);
-        self.expect(&Token::Semicolon);
+        self.expect(&Token::RBrace)?;
+        self.expect(&Token::Semicolon)?;
 
-        EnumDec { name, variants }
+        Ok(EnumDec { name, variants, span })
     }
 
-    fn parse_union(&mut self) -> UnionDec {
-        self.expect(&Token::Union);
+    fn parse_union(&mut self) -> ParseResult<UnionDec> {
+        let span = self.span();
+        self.expect(&Token::Union)?;
 
         let name = match self.peek() {
             Token::Ident(n) => {
@@ -279,38 +434,36 @@ impl Parser {
             _ => None,
         };
 
-        self.expect(&Token::LBrace);
+        self.expect(&Token::LBrace)?;
 
         let mut fields = vec![];
         while *self.peek() != Token::RBrace {
-            let field_type = self.parse_qualified_type();
-            let field_name = match self.advance() {
-                Token::Ident(n) => n,
-                other => panic!("Expected field name, got {:?}", other),
-            };
-            self.expect(&Token::Semicolon);
+            let field_span = self.span();
+            let field_type = self.parse_qualified_type()?;
+            let (field_name, field_type) = self.parse_declarator(field_type, "field name")?;
+            self.expect(&Token::Semicolon)?;
             fields.push(StructField {
                 name: field_name,
                 typ: field_type,
+                span: field_span,
             });
         }
 
-        self.expect(&Token::RBrace);
-        self.expect(&Token::Semicolon);
+        self.expect(&Token::RBrace)?;
+        self.expect(&Token::Semicolon)?;
 
-        UnionDec { name, fields }
+        Ok(UnionDec { name, fields, span })
     }
 
-    fn parse_typedef(&mut self) -> TypedefDec {
-        self.expect(&Token::Typedef);
-        let typ = self.parse_qualified_type();
-        let name = match self.advance() {
-            Token::Ident(n) => n,
-            other => panic!("Expected typedef name got {:?}", other),
-        };
-        self.expect(&Token::Semicolon);
+    fn parse_typedef(&mut self) -> ParseResult<TypedefDec> {
+        let span = self.span();
+        self.expect(&Token::Typedef)?;
+        let typ = self.parse_qualified_type()?;
+        let name_span = self.declarator_span();
+        let (name, typ) = self.parse_declarator(typ, "typedef name")?;
+        self.expect(&Token::Semicolon)?;
 
-        TypedefDec { name, typ }
+        Ok(TypedefDec { name, typ, span, name_span })
     }
 
     // just handling static and extern for now
@@ -329,7 +482,7 @@ impl Parser {
     }
 
     // just allowing const for now
-    fn parse_qualified_type(&mut self) -> QualifiedType {
+    fn parse_qualified_type(&mut self) -> ParseResult<QualifiedType> {
         let is_const = if *self.peek() == Token::Const {
             self.advance();
             true
@@ -337,15 +490,15 @@ impl Parser {
             false
         };
 
-        let base = self.parse_pointer_type();
+        let base = self.parse_pointer_type()?;
 
-        QualifiedType { base, is_const }
+        Ok(QualifiedType { base, is_const })
     }
 
     // int* arr[10] array of 10 pts
     // int (*ptr)[10]; 1 pointer to beginning
-    fn parse_pointer_type(&mut self) -> Type {
-        let mut base_type = self.parse_base_type();
+    fn parse_pointer_type(&mut self) -> ParseResult<Type> {
+        let mut base_type = self.parse_base_type()?;
 
         while *self.peek() == Token::Star {
             self.advance();
@@ -357,20 +510,17 @@ impl Parser {
             let size = if *self.peek() == Token::RBracket {
                 None
             } else {
-                match self.advance() {
-                    Token::IntLiteral(n) => Some(n as usize),
-                    other => panic!("Expected array size got {:?}", other),
-                }
+                Some(self.expect_int("array size")? as usize)
             };
 
-            self.expect(&Token::RBracket);
+            self.expect(&Token::RBracket)?;
             base_type = Type::Array(Box::new(base_type), size);
         }
 
-        base_type
+        Ok(base_type)
     }
 
-    fn parse_base_type(&mut self) -> Type {
+    fn parse_base_type(&mut self) -> ParseResult<Type> {
         let is_signed = match self.peek() {
             Token::Signed => {
                 self.advance();
@@ -383,6 +533,7 @@ impl Parser {
             _ => None,
         };
 
+        let start = self.pos;
         let base = match self.advance() {
             Token::Void => Type::Void,
             Token::Char => Type::Char,
@@ -401,55 +552,47 @@ impl Parser {
             Token::Double => Type::Double,
             Token::Bool => Type::Int, // just mapping bool to int rn, TODO: CHANGE
             Token::Struct => {
-                let name = match self.advance() {
-                    Token::Ident(n) => n,
-                    other => panic!("expected struct name, got {:?}", other),
-                };
+                let name = self.expect_ident("struct name")?;
                 Type::StructRef(name)
             }
             Token::Union => {
-                let name = match self.advance() {
-                    Token::Ident(n) => n,
-                    other => panic!("Expected union name, got {:?}", other),
-                };
+                let name = self.expect_ident("union name")?;
                 Type::UnionRef(name)
             }
             Token::Enum => {
-                let name = match self.advance() {
-                    Token::Ident(n) => n,
-                    other => panic!("Expected enum name, got {:?}", other),
-                };
+                let name = self.expect_ident("enum name")?;
                 Type::EnumRef(name)
             }
             // chec for typedef types
             Token::Ident(name) => Type::TypedefRef(name),
-            other => panic!("Expected type, got {:?}", other),
+            _ => {
+                self.pos = start;
+                return Err(self.error("type"));
+            }
         };
 
         if let Some(signed) = is_signed {
             if signed {
-                Type::Signed(Box::new(base))
+                Ok(Type::Signed(Box::new(base)))
             } else {
-                Type::Unsigned(Box::new(base))
+                Ok(Type::Unsigned(Box::new(base)))
             }
         } else {
-            base
+            Ok(base)
         }
     }
 
     // STATEMENT PARSING
 
-    fn parse_block(&mut self) -> Vec<Statement> {
-        self.expect(&Token::LBrace);
-        let mut statements = vec![];
-        while *self.peek() != Token::RBrace {
-            statements.push(self.parse_statement());
-        }
-        self.expect(&Token::RBrace);
-        statements
+    fn parse_block(&mut self) -> ParseResult<Vec<Statement>> {
+        self.expect(&Token::LBrace)?;
+        let statements = self.parse_statements_until(|t| matches!(t, Token::RBrace));
+        self.expect(&Token::RBrace)?;
+        Ok(statements)
     }
 
-    fn parse_statement(&mut self) -> Statement {
+    fn parse_statement(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
         match self.peek() {
 
             // checking for type keyword, this is var dec
@@ -471,15 +614,15 @@ impl Parser {
                     } else {
                         // not a dec
                         self.pos = checkpoint;
-                        let expr = self.parse_expression();
-                        self.expect(&Token::Semicolon);
-                        Statement::ExprStatement(expr)
+                        let expr = self.parse_expression()?;
+                        self.expect(&Token::Semicolon)?;
+                        Ok(Statement::new(StatementKind::ExprStatement(expr), span))
                     }
                 } else {
                     self.pos = checkpoint;
-                    let expr = self.parse_expression();
-                    self.expect(&Token::Semicolon);
-                    Statement::ExprStatement(expr)
+                    let expr = self.parse_expression()?;
+                    self.expect(&Token::Semicolon)?;
+                    Ok(Statement::new(StatementKind::ExprStatement(expr), span))
                 }
             }
 
@@ -493,31 +636,31 @@ impl Parser {
 
             Token::Break => {
                 self.advance();
-                self.expect(&Token::Semicolon);
-                Statement::Break
+                self.expect(&Token::Semicolon)?;
+                Ok(Statement::new(StatementKind::Break, span))
             }
 
             Token::Continue => {
                 self.advance();
-                self.expect(&Token::Semicolon);
-                Statement::Continue
+                self.expect(&Token::Semicolon)?;
+                Ok(Statement::new(StatementKind::Continue, span))
             }
 
-            Token::LBrace => Statement::Block(self.parse_block()),
+            Token::LBrace => Ok(Statement::new(StatementKind::Block(self.parse_block()?), span)),
 
             // labels and exprs and typedefs
             Token::Ident(_) => {
                 let checkpoint = self.pos;
                 let name = match self.advance() {
                     Token::Ident(n) => n,
-                    _ => panic!(),
+                    _ => unreachable!(),
                 };
 
                 if *self.peek() == Token::Colon {
                     // check for label
                     self.advance();
-                    let statement = self.parse_statement();
-                    Statement::Label(name, Box::new(statement))
+                    let statement = self.parse_statement()?;
+                    Ok(Statement::new(StatementKind::Label(name, Box::new(statement)), span))
                 } else if matches!(self.peek(), Token::Ident(_) | Token::Star) {
                     // typedef'd type declaration: myint x = 5; or myint *p;
                     self.pos = checkpoint;
@@ -525,208 +668,291 @@ impl Parser {
                 } else {
                     // expression
                     self.pos = checkpoint;
-                    let expression = self.parse_expression();
-                    self.expect(&Token::Semicolon);
-                    Statement::ExprStatement(expression)
+                    let expression = self.parse_expression()?;
+                    self.expect(&Token::Semicolon)?;
+                    Ok(Statement::new(StatementKind::ExprStatement(expression), span))
                 }
             }
 
             _ => {
-                let expression = self.parse_expression();
-                self.expect(&Token::Semicolon);
-                Statement::ExprStatement(expression)
+                let expression = self.parse_expression()?;
+                self.expect(&Token::Semicolon)?;
+                Ok(Statement::new(StatementKind::ExprStatement(expression), span))
             }
         }
     }
 
-    fn parse_local_var_dec(&mut self) -> Statement {
+    fn parse_local_var_dec(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
         let storage_class = self.parse_storage_class();
-        let qualified_type = self.parse_qualified_type();
+        let qualified_type = self.parse_qualified_type()?;
+
+        // handle int arr[10]; and int (*fp)(int);
+        let name_span = self.declarator_span();
+        let (name, typ) = self.parse_declarator(qualified_type, "variable name")?;
 
-        let name = match self.advance() {
-            Token::Ident(n) => n,
-            other => panic!("Expected name for var but got {:?}", other),
+        let init = if *self.peek() == Token::Assign {
+            self.advance();
+            Some(self.parse_initializer()?)
+        } else {
+            None
         };
 
-        let mut typ = qualified_type;
-        
-        // handle int arr[10];
+        self.expect(&Token::Semicolon)?;
+
+        Ok(Statement::new(StatementKind::VarDec(typ, name, init, storage_class, name_span), span))
+    }
+
+    // the [N] after a declared name, int grid[2][3] is an array of 2 arrays of 3 ints
+    // so the first size ends up outermost
+    fn parse_array_suffix(&mut self, typ: QualifiedType) -> ParseResult<QualifiedType> {
+        let sizes = self.parse_array_sizes()?;
+        let base = sizes.into_iter().rev().fold(typ.base, |inner, size| Type::Array(Box::new(inner), size));
+        Ok(QualifiedType { base, is_const: typ.is_const })
+    }
+
+    fn parse_array_sizes(&mut self) -> ParseResult<Vec<Option<usize>>> {
+        let mut sizes = vec![];
         while *self.peek() == Token::LBracket {
             self.advance();
             let size = if *self.peek() == Token::RBracket {
                 None
             } else {
-                match self.advance() {
-                    Token::IntLiteral(n) => Some(n as usize),
-                    other => panic!("Expected array size, got {:?}", other),
-                }
-            };
-            self.expect(&Token::RBracket);
-            typ = QualifiedType {
-                base: Type::Array(Box::new(typ.base), size),
-                is_const: typ.is_const,
+                Some(self.expect_int("array size")? as usize)
             };
+            self.expect(&Token::RBracket)?;
+            sizes.push(size);
         }
+        Ok(sizes)
+    }
 
-        let init = if *self.peek() == Token::Assign {
+    // the name being declared and its type, x or arr[10] or a function pointer
+    fn parse_declarator(&mut self, typ: QualifiedType, what: &str) -> ParseResult<(String, QualifiedType)> {
+        if self.is_function_pointer() {
+            let (name, typ) = self.parse_function_pointer(typ, Some(what))?;
+            // a name is required when what is given
+            return Ok((name.unwrap_or_default(), typ));
+        }
+        let name = self.expect_ident(what)?;
+        let typ = self.parse_array_suffix(typ)?;
+        Ok((name, typ))
+    }
+
+    fn is_function_pointer(&self) -> bool {
+        *self.peek() == Token::LParen && *self.peek_at(1) == Token::Star
+    }
+
+    // where the name of the declarator starting at the next token is, past the ( and *s of a function pointer
+    fn declarator_span(&self) -> Span {
+        let mut n = 0;
+        if self.is_function_pointer() {
+            n = 1;
+            while *self.peek_at(n) == Token::Star {
+                n += 1;
+            }
+        }
+        self.tokens[(self.pos + n).min(self.tokens.len() - 1)].span
+    }
+
+    // int (*ops[4])(int, int) is an array of 4 pointers to functions taking two ints.
+    // the name sits inside the parens, it's left out in casts and prototypes so what is None there
+    fn parse_function_pointer(&mut self, return_type: QualifiedType, what: Option<&str>) -> ParseResult<(Option<String>, QualifiedType)> {
+        self.expect(&Token::LParen)?;
+        let mut depth = 0;
+        while *self.peek() == Token::Star {
             self.advance();
-            Some(self.parse_expression())
-        } else {
-            None
+            depth += 1;
+        }
+
+        let name = match (self.peek().clone(), what) {
+            (Token::Ident(name), _) => {
+                self.advance();
+                Some(name)
+            }
+            (_, Some(what)) => return Err(self.error(what)),
+            (_, None) => None,
+        };
+        let sizes = self.parse_array_sizes()?;
+        self.expect(&Token::RParen)?;
+
+        let params = self.parse_parameters()?;
+        let function = Type::Function {
+            params: params.into_iter().map(|param| param.typ.base).collect(),
+            return_type: Box::new(return_type.base),
         };
 
-        self.expect(&Token::Semicolon);
+        let pointer = (0..depth).fold(function, |inner, _| Type::Pointer(Box::new(inner)));
+        let base = sizes.into_iter().rev().fold(pointer, |inner, size| Type::Array(Box::new(inner), size));
+        Ok((name, QualifiedType { base, is_const: false }))
+    }
 
-        Statement::VarDec(typ, name, init, storage_class)
+    // = 5 or = {1, 2, {3, 4},}
+    fn parse_initializer(&mut self) -> ParseResult<Expr> {
+        if *self.peek() != Token::LBrace {
+            return self.parse_assignment();
+        }
+
+        let span = self.span();
+        self.advance();
+        let mut items = vec![];
+        while *self.peek() != Token::RBrace {
+            items.push(self.parse_initializer()?);
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(&Token::RBrace)?;
+        Ok(Expr::new(ExprKind::InitList(items), span))
     }
 
-    fn parse_return(&mut self) -> Statement {
-        self.expect(&Token::Return);
+    fn parse_return(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::Return)?;
 
         if *self.peek() == Token::Semicolon {
             self.advance();
-            Statement::ReturnVoid
+            Ok(Statement::new(StatementKind::ReturnVoid, span))
         } else {
-            let expr = self.parse_expression();
-            self.expect(&Token::Semicolon);
-            Statement::Return(expr)
+            let expr = self.parse_expression()?;
+            self.expect(&Token::Semicolon)?;
+            Ok(Statement::new(StatementKind::Return(expr), span))
         }
     }
 
-    fn parse_if(&mut self) -> Statement {
-        self.expect(&Token::If);
-        self.expect(&Token::LParen);
-        let condition = self.parse_expression();
-        self.expect(&Token::RParen);
+    fn parse_if(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::If)?;
+        self.expect(&Token::LParen)?;
+        let condition = self.parse_expression()?;
+        self.expect(&Token::RParen)?;
 
-        let then_block = vec![self.parse_statement()];
+        let then_block = vec![self.parse_statement()?];
 
         let else_block = if *self.peek() == Token::Else {
             self.advance();
-            Some(vec![self.parse_statement()])
+            Some(vec![self.parse_statement()?])
         } else {
             None
         };
 
-        Statement::If(condition, then_block, else_block)
+        Ok(Statement::new(StatementKind::If(condition, then_block, else_block), span))
     }
 
-    fn parse_while(&mut self) -> Statement {
-        self.expect(&Token::While);
-        self.expect(&Token::LParen);
+    fn parse_while(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::While)?;
+        self.expect(&Token::LParen)?;
 
-        let condition = self.parse_expression();
+        let condition = self.parse_expression()?;
 
-        self.expect(&Token::RParen);
+        self.expect(&Token::RParen)?;
 
-        let body = vec![self.parse_statement()];
+        let body = vec![self.parse_statement()?];
 
-        Statement::While(condition, body)
+        Ok(Statement::new(StatementKind::While(condition, body), span))
     }
 
-    fn parse_do_while(&mut self) -> Statement {
-        self.expect(&Token::Do);
+    fn parse_do_while(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::Do)?;
 
-        let body = vec![self.parse_statement()];
+        let body = vec![self.parse_statement()?];
 
-        self.expect(&Token::While);
-        self.expect(&Token::LParen);
+        self.expect(&Token::While)?;
+        self.expect(&Token::LParen)?;
 
-        let condition = self.parse_expression();
+        let condition = self.parse_expression()?;
 
-        self.expect(&Token::RParen);
-        self.expect(&Token::Semicolon);
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::Semicolon)?;
 
-        Statement::DoWhile(DoWhileStmt { body, condition })
+        Ok(Statement::new(StatementKind::DoWhile(DoWhileStmt { body, condition }), span))
     }
 
-    fn parse_for_loop(&mut self) -> Statement {
-        self.expect(&Token::For);
-        self.expect(&Token::LParen);
+    fn parse_for_loop(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::For)?;
+        self.expect(&Token::LParen)?;
 
+        let init_span = self.span();
         let init = if *self.peek() == Token::Semicolon {
             self.advance();
             None
         } else if self.is_type_keyword() {
-            Some(Box::new(self.parse_local_var_dec()))
+            Some(Box::new(self.parse_local_var_dec()?))
         } else {
-            let expr = self.parse_expression();
-            self.expect(&Token::Semicolon);
-            Some(Box::new(Statement::ExprStatement(expr)))
+            let expr = self.parse_expression()?;
+            self.expect(&Token::Semicolon)?;
+            Some(Box::new(Statement::new(StatementKind::ExprStatement(expr), init_span)))
         };
 
         let condition = if *self.peek() == Token::Semicolon {
             None
         } else {
-            Some(self.parse_expression())
+            Some(self.parse_expression()?)
         };
-        self.expect(&Token::Semicolon);
+        self.expect(&Token::Semicolon)?;
 
         let increment = if *self.peek() == Token::RParen {
             None
         } else {
-            Some(self.parse_expression())
+            Some(self.parse_expression()?)
         };
-        self.expect(&Token::RParen);
+        self.expect(&Token::RParen)?;
 
-        let body = vec![self.parse_statement()];
+        let body = vec![self.parse_statement()?];
 
-        Statement::For(init, condition, increment, body)
+        Ok(Statement::new(StatementKind::For(init, condition, increment, body), span))
     }
 
-    fn parse_switch(&mut self) -> Statement {
-        self.expect(&Token::Switch);
-        self.expect(&Token::LParen);
+    fn parse_switch(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::Switch)?;
+        self.expect(&Token::LParen)?;
 
-        let expr = self.parse_expression();
+        let expr = self.parse_expression()?;
 
-        self.expect(&Token::RParen);
-        self.expect(&Token::LBrace);
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::LBrace)?;
 
         let mut cases = vec![];
 
         while *self.peek() != Token::RBrace {
+            let case_span = self.span();
             match self.peek() {
                 Token::Case => {
                     self.advance();
-                    let value = Some(self.parse_expression());
-                    self.expect(&Token::Colon);
+                    let value = Some(self.parse_expression()?);
+                    self.expect(&Token::Colon)?;
 
-                    let mut stmts = vec![];
-                    while !matches!(self.peek(), Token::Case | Token::Default | Token::RBrace) {
-                        stmts.push(self.parse_statement());
-                    }
+                    let stmts = self.parse_statements_until(|t| matches!(t, Token::Case | Token::Default | Token::RBrace));
 
-                    cases.push(Case { value, stmts });
+                    cases.push(Case { value, stmts, span: case_span });
                 }
                 Token::Default => {
                     self.advance();
-                    self.expect(&Token::Colon);
+                    self.expect(&Token::Colon)?;
 
-                    let mut stmts = vec![];
-                    while !matches!(self.peek(), Token::Case | Token::Default | Token::RBrace) {
-                        stmts.push(self.parse_statement());
-                    }
+                    let stmts = self.parse_statements_until(|t| matches!(t, Token::Case | Token::Default | Token::RBrace));
 
-                    cases.push(Case { value: None, stmts });
+                    cases.push(Case { value: None, stmts, span: case_span });
                 }
                 _ => break,
             }
         }
 
-        self.expect(&Token::RBrace);
+        self.expect(&Token::RBrace)?;
 
-        Statement::Switch(SwitchStmt { expr, cases })
+        Ok(Statement::new(StatementKind::Switch(SwitchStmt { expr, cases }), span))
     }
 
-    fn parse_goto(&mut self) -> Statement {
-        self.expect(&Token::Goto);
-        let label = match self.advance() {
-            Token::Ident(n) => n,
-            other => panic!("Expected label after go to, got {:?}", other),
-        };
-        self.expect(&Token::Semicolon);
-        Statement::Goto(label)
+    fn parse_goto(&mut self) -> ParseResult<Statement> {
+        let span = self.span();
+        self.expect(&Token::Goto)?;
+        let label = self.expect_ident("label name")?;
+        self.expect(&Token::Semicolon)?;
+        Ok(Statement::new(StatementKind::Goto(label), span))
     }
 
     fn is_type_keyword(&self) -> bool {
@@ -744,153 +970,155 @@ impl Parser {
     // https://stackoverflow.com/questions/17369090/operator-precedence-table-for-the-c-programming-language
     // recursive decent parsing of expressions in order
 
-    fn parse_expression(&mut self) -> Expr {
+    fn parse_expression(&mut self) -> ParseResult<Expr> {
         self.parse_assignment()
     }
 
-    fn parse_assignment(&mut self) -> Expr {
-        let mut left = self.parse_ternary();
+    fn parse_assignment(&mut self) -> ParseResult<Expr> {
+        let left = self.parse_ternary()?;
+        let span = left.span;
 
         match self.peek() {
             Token::Assign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::Assign(Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::Assign(Box::new(left), Box::new(right)), span))
             }
 
             Token::PlusAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::AddAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::AddAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::MinusAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::SubAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::SubAssign, Box::new(left), Box::new(right)), span))
             }
             
             Token::StarAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::MulAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::MulAssign, Box::new(left), Box::new(right)), span))
             }
             Token::SlashAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::DivAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::DivAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::PercentAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::ModAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::ModAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::AndAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::AndAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::AndAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::OrAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::OrAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::OrAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::XorAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::XorAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::XorAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::LShiftAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::LShiftAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::LShiftAssign, Box::new(left), Box::new(right)), span))
             }
 
             Token::RShiftAssign => {
                 self.advance();
-                let right = self.parse_assignment();
-                Expr::CompoundAssign(CompoundOp::RShiftAssign, Box::new(left), Box::new(right))
+                let right = self.parse_assignment()?;
+                Ok(Expr::new(ExprKind::CompoundAssign(CompoundOp::RShiftAssign, Box::new(left), Box::new(right)), span))
             }
 
-            _ => left,
+            _ => Ok(left),
         }
     }
 
-    fn parse_ternary(&mut self) -> Expr {
-        let mut expr = self.parse_or();
+    fn parse_ternary(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_or()?;
 
         if *self.peek() == Token::Question {
             self.advance();
 
-            let then_expr = self.parse_expression();
+            let then_expr = self.parse_expression()?;
 
-            self.expect(&Token::Colon);
+            self.expect(&Token::Colon)?;
 
-            let else_expr = self.parse_ternary();
+            let else_expr = self.parse_ternary()?;
 
-            expr = Expr::Ternary(Box::new(expr), Box::new(then_expr), Box::new(else_expr));
+            let span = expr.span;
+            expr = Expr::new(ExprKind::Ternary(Box::new(expr), Box::new(then_expr), Box::new(else_expr)), span);
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_or(&mut self) -> Expr {
-        let mut left = self.parse_and();
+    fn parse_or(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_and()?;
         while *self.peek() == Token::Or {
             self.advance();
-            let right = self.parse_and();
-            left = Expr::BinOp(Box::new(left), BinOp::Or, Box::new(right));
+            let right = self.parse_and()?;
+            left = binary(left, BinOp::Or, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_and(&mut self) -> Expr {
-        let mut left = self.parse_bitor();
+    fn parse_and(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_bitor()?;
         while *self.peek() == Token::And {
             self.advance();
-            let right = self.parse_bitor();
-            left = Expr::BinOp(Box::new(left), BinOp::And, Box::new(right));
+            let right = self.parse_bitor()?;
+            left = binary(left, BinOp::And, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_bitor(&mut self) -> Expr {
-        let mut left = self.parse_bitxor();
+    fn parse_bitor(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_bitxor()?;
         while *self.peek() == Token::Pipe {
             self.advance();
-            let right = self.parse_bitxor();
-            left = Expr::BinOp(Box::new(left), BinOp::BitOr, Box::new(right));
+            let right = self.parse_bitxor()?;
+            left = binary(left, BinOp::BitOr, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_bitxor(&mut self) -> Expr {
-        let mut left = self.parse_bitand();
+    fn parse_bitxor(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_bitand()?;
         while *self.peek() == Token::Caret {
             self.advance();
-            let right = self.parse_bitand();
-            left = Expr::BinOp(Box::new(left), BinOp::BitXor, Box::new(right));
+            let right = self.parse_bitand()?;
+            left = binary(left, BinOp::BitXor, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_bitand(&mut self) -> Expr {
-        let mut left = self.parse_equality();
+    fn parse_bitand(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_equality()?;
         while *self.peek() == Token::Ampersand {
             self.advance();
-            let right = self.parse_equality();
-            left = Expr::BinOp(Box::new(left), BinOp::BitAnd, Box::new(right));
+            let right = self.parse_equality()?;
+            left = binary(left, BinOp::BitAnd, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_equality(&mut self) -> Expr {
-        let mut left = self.parse_comparison();
+    fn parse_equality(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_comparison()?;
         loop {
             let op = match self.peek() {
                 Token::Eq => BinOp::Eq,
@@ -898,14 +1126,14 @@ impl Parser {
                 _ => break,
             };
             self.advance();
-            let right = self.parse_comparison();
-            left = Expr::BinOp(Box::new(left), op, Box::new(right));
+            let right = self.parse_comparison()?;
+            left = binary(left, op, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_comparison(&mut self) -> Expr {
-        let mut left = self.parse_bitwise_shift();
+    fn parse_comparison(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_bitwise_shift()?;
         loop {
             let op = match self.peek() {
                 Token::Lt => BinOp::Lt,
@@ -915,14 +1143,14 @@ impl Parser {
                 _ => break,
             };
             self.advance();
-            let right = self.parse_bitwise_shift();
-            left = Expr::BinOp(Box::new(left), op, Box::new(right));
+            let right = self.parse_bitwise_shift()?;
+            left = binary(left, op, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_bitwise_shift(&mut self) -> Expr {
-        let mut left = self.parse_additive();
+    fn parse_bitwise_shift(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_additive()?;
         loop {
             let op = match self.peek() {
                 Token::LShift => BinOp::LShift,
@@ -930,14 +1158,14 @@ impl Parser {
                 _ => break,
             };
             self.advance();
-            let right = self.parse_additive();
-            left = Expr::BinOp(Box::new(left), op, Box::new(right));
+            let right = self.parse_additive()?;
+            left = binary(left, op, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_additive(&mut self) -> Expr {
-        let mut left = self.parse_multiplicative();
+    fn parse_additive(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_multiplicative()?;
         loop {
             let op = match self.peek() {
                 Token::Plus => BinOp::Add,
@@ -945,14 +1173,14 @@ impl Parser {
                 _ => break,
             };
             self.advance();
-            let right = self.parse_multiplicative();
-            left = Expr::BinOp(Box::new(left), op, Box::new(right));
+            let right = self.parse_multiplicative()?;
+            left = binary(left, op, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_multiplicative(&mut self) -> Expr {
-        let mut left = self.parse_cast();
+    fn parse_multiplicative(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_cast()?;
         loop {
             let op = match self.peek() {
                 Token::Star => BinOp::Mul,
@@ -961,13 +1189,13 @@ impl Parser {
                 _ => break,
             };
             self.advance();
-            let right = self.parse_cast();
-            left = Expr::BinOp(Box::new(left), op, Box::new(right));
+            let right = self.parse_cast()?;
+            left = binary(left, op, right);
         }
-        left
+        Ok(left)
     }
 
-    fn parse_cast(&mut self) -> Expr {
+    fn parse_cast(&mut self) -> ParseResult<Expr> {
         // gotta check for the cast first
         // here i try parse and backtrack if not work
         if *self.peek() == Token::LParen {
@@ -975,11 +1203,15 @@ impl Parser {
             self.advance();
 
             if self.is_type_keyword() {
-                let typ = self.parse_qualified_type();
+                let mut typ = self.parse_qualified_type()?;
+                // (int (*)(int)) p
+                if self.is_function_pointer() {
+                    typ = self.parse_function_pointer(typ, None)?.1;
+                }
                 if *self.peek() == Token::RParen {
                     self.advance();
-                    let expr = self.parse_cast();
-                    return Expr::Cast(typ, Box::new(expr));
+                    let expr = self.parse_cast()?;
+                    return Ok(Expr::new(ExprKind::Cast(typ, Box::new(expr)), self.tokens[checkpoint].span));
                 }
             }
 
@@ -989,42 +1221,43 @@ impl Parser {
         self.parse_unary()
     }
 
-    fn parse_unary(&mut self) -> Expr {
+    fn parse_unary(&mut self) -> ParseResult<Expr> {
+        let span = self.span();
         match self.peek() {
             
             Token::Not => {
                 self.advance();
-                Expr::UnaryOp(UnaryOp::Not, Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::UnaryOp(UnaryOp::Not, Box::new(self.parse_cast()?)), span))
             }
 
             Token::Minus => {
                 self.advance();
-                Expr::UnaryOp(UnaryOp::Neg, Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::UnaryOp(UnaryOp::Neg, Box::new(self.parse_cast()?)), span))
             }
 
             Token::Tilde => {
                 self.advance();
-                Expr::UnaryOp(UnaryOp::BitNot, Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::UnaryOp(UnaryOp::BitNot, Box::new(self.parse_cast()?)), span))
             }
 
             Token::PlusPlus => {
                 self.advance();
-                Expr::UnaryOp(UnaryOp::PreInc, Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::UnaryOp(UnaryOp::PreInc, Box::new(self.parse_cast()?)), span))
             }
 
             Token::MinusMinus => {
                 self.advance();
-                Expr::UnaryOp(UnaryOp::PreDec, Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::UnaryOp(UnaryOp::PreDec, Box::new(self.parse_cast()?)), span))
             }
             
             Token::Star => {
                 self.advance();
-                Expr::Deref(Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::Deref(Box::new(self.parse_cast()?)), span))
             }
 
             Token::Ampersand => {
                 self.advance();
-                Expr::AddrOf(Box::new(self.parse_cast()))
+                Ok(Expr::new(ExprKind::AddrOf(Box::new(self.parse_cast()?)), span))
             }
 
             // sizeof(int) or sizeof(expr)
@@ -1036,52 +1269,47 @@ impl Parser {
                     self.advance();
 
                     if self.is_type_keyword() {
-                        let typ = self.parse_qualified_type();
+                        let typ = self.parse_qualified_type()?;
                         if *self.peek() == Token::RParen {
                             self.advance();
-                            return Expr::SizeofType(typ);
+                            return Ok(Expr::new(ExprKind::SizeofType(typ), span));
                         }
                     }
 
                     self.pos = checkpoint;
                 }
                 
-                Expr::SizeofExpr(Box::new(self.parse_unary()))
+                Ok(Expr::new(ExprKind::SizeofExpr(Box::new(self.parse_unary()?)), span))
             }
 
             _ => self.parse_postfix(),
         }
     }
 
-    fn parse_postfix(&mut self) -> Expr {
-        let mut expr = self.parse_primary();
+    fn parse_postfix(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_primary()?;
+        let span = expr.span;
 
         loop {
             match self.peek() {
 
                 Token::Dot => {
                     self.advance();
-                    let field = match self.advance() {
-                        Token::Ident(name) => name,
-                        other => panic!("Expected field after '.', got {:?}", other),
-                    };
-                    expr = Expr::FieldAccess(Box::new(expr), field);
+                    let field = self.expect_ident("field name")?;
+                    expr = Expr::new(ExprKind::FieldAccess(Box::new(expr), field), span);
                 }
 
                 Token::Arrow => {
                     self.advance();
-                    let field = match self.advance() {
-                        Token::Ident(name) => name,
-                        other => panic!("Expected field name after '->', got {:?}", other),
-                    };
-                    expr = Expr::PtrMember(Box::new(expr), field);
+                    let field = self.expect_ident("field name")?;
+                    expr = Expr::new(ExprKind::PtrMember(Box::new(expr), field), span);
                 }
 
                 Token::LBracket => {
                     self.advance();
-                    let index = self.parse_expression();
-                    self.expect(&Token::RBracket);
-                    expr = Expr::ArrayIndex(Box::new(expr), Box::new(index));
+                    let index = self.parse_expression()?;
+                    self.expect(&Token::RBracket)?;
+                    expr = Expr::new(ExprKind::ArrayIndex(Box::new(expr), Box::new(index)), span);
                 }
 
                 Token::LParen => {
@@ -1089,49 +1317,61 @@ impl Parser {
 
                     let mut args = vec![];
                     while *self.peek() != Token::RParen {
-                        args.push(self.parse_expression());
+                        args.push(self.parse_expression()?);
                         if *self.peek() == Token::Comma {
                             self.advance();
                         }
                     }
-                    self.expect(&Token::RParen);
-                    expr = Expr::Call(Box::new(expr), args);
+                    self.expect(&Token::RParen)?;
+                    expr = Expr::new(ExprKind::Call(Box::new(expr), args), span);
                 }
 
                 Token::PlusPlus => {
                     self.advance();
-                    expr = Expr::UnaryOp(UnaryOp::PostInc, Box::new(expr));
+                    expr = Expr::new(ExprKind::UnaryOp(UnaryOp::PostInc, Box::new(expr)), span);
                 }
 
                 Token::MinusMinus => {
                     self.advance();
-                    expr = Expr::UnaryOp(UnaryOp::PostDec, Box::new(expr));
+                    expr = Expr::new(ExprKind::UnaryOp(UnaryOp::PostDec, Box::new(expr)), span);
                 }
 
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Expr {
-        match self.advance() {
-            Token::IntLiteral(n) => Expr::IntLiteral(n),
-            Token::FloatLiteral(f) => Expr::FloatLiteral(f),
-            Token::CharLiteral(c) => Expr::CharLiteral(c),
-            Token::StringLiteral(s) => Expr::StringLiteral(s),
-            Token::BoolLiteral(b) => Expr::BoolLiteral(b),
-            Token::Null => Expr::Null,
-            Token::Ident(name) => Expr::Identifier(name),
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let span = self.span();
+        let start = self.pos;
+        let kind = match self.advance() {
+            Token::IntLiteral(n) => ExprKind::IntLiteral(n),
+            Token::FloatLiteral(f) => ExprKind::FloatLiteral(f),
+            Token::CharLiteral(c) => ExprKind::CharLiteral(c),
+            Token::StringLiteral(s) => ExprKind::StringLiteral(s),
+            Token::BoolLiteral(b) => ExprKind::BoolLiteral(b),
+            Token::Null => ExprKind::Null,
+            Token::Ident(name) => ExprKind::Identifier(name),
 
             Token::LParen => {
-                let expr = self.parse_expression();
-                self.expect(&Token::RParen);
-                expr
+                let expr = self.parse_expression()?;
+                self.expect(&Token::RParen)?;
+                return Ok(expr);
             }
 
-            other => panic!("Unexpected token in primary expression: {:?}", other),
-        }
+            _ => {
+                self.pos = start;
+                return Err(self.error("expression"));
+            }
+        };
+        Ok(Expr::new(kind, span))
     }
+}
+
+// binary exprs start where their left operand starts
+fn binary(left: Expr, op: BinOp, right: Expr) -> Expr {
+    let span = left.span;
+    Expr::new(ExprKind::BinOp(Box::new(left), op, Box::new(right)), span)
 }
\ No newline at end of file
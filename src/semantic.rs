@@ -1,18 +1,47 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 
-use crate::{ast::{BinOp, CompoundOp, Declaration, Expr, Program, Statement, StorageClass, Type, UnaryOp}, symbol_table::{SymbolTable}};
+use crate::{builtins::Builtin, codegen::{convert_constant, fold_binop, is_truthy, Constant}, ast::{BinOp, CompoundOp, Declaration, Expr, ExprKind, Param, Program, Statement, StatementKind, StorageClass, Type, UnaryOp}, diagnostic::{Code, Diagnostic}, span::Span, symbol_table::{SymbolTable}};
 
 // TODO:
 // handle constants
-// goto label validations
-// warnings
+
+const LVALUE_NOTE: &str = "only variables, dereferenced pointers, array elements and fields can be assigned to";
+
+// labels are function scoped, so they're gathered up front and gotos are checked against them afterwards
+// in_scope holds indices into initialized_locals for every initialized variable visible at that point
+struct LabelInfo {
+    span: Span,
+    in_scope: Vec<usize>,
+    used: bool,
+}
+
+struct GotoInfo {
+    label: String,
+    span: Span,
+    in_scope: Vec<usize>,
+}
 
 pub struct SemanticAnalyzer {
     sym_table: SymbolTable,
     current_function_return_type: Option<Type>,
     loop_depth: usize,
     switch_depth: usize,
-    labels: HashSet<String>,
+    labels: HashMap<String, LabelInfo>,
+    gotos: Vec<GotoInfo>,
+    initialized_locals: Vec<(String, Span)>,
+    warnings: Vec<Diagnostic>,
+
+    // errors that don't stop the rest of a function from being checked, like a bad goto
+    errors: Vec<Diagnostic>,
+
+    // file scope functions with a body and variables with an initializer, only one of each is allowed
+    definitions: HashMap<String, Span>,
+
+    // where each enumerator was declared
+    enumerators: HashMap<String, Span>,
+
+    // where each file scope name is first used, the linker reports an undefined symbol there
+    global_uses: HashMap<String, Span>,
 }
 
 impl SemanticAnalyzer {
@@ -22,11 +51,23 @@ impl SemanticAnalyzer {
             current_function_return_type: None, 
             loop_depth: 0,
             switch_depth: 0,
-            labels: HashSet::new(),
+            labels: HashMap::new(),
+            gotos: vec![],
+            initialized_locals: vec![],
+            warnings: vec![],
+            errors: vec![],
+            definitions: HashMap::new(),
+            enumerators: HashMap::new(),
+            global_uses: HashMap::new(),
         }
     }
 
-    pub fn analyze(&mut self, program: &Program) -> Result<(), Vec<String>> {
+    pub fn global_uses(&self) -> &HashMap<String, Span> {
+        &self.global_uses
+    }
+
+    // errors and warnings together, in source order
+    pub fn analyze(&mut self, program: &Program) -> Vec<Diagnostic> {
         let mut errors = vec![];
 
         // collect declarations
@@ -36,6 +77,17 @@ impl SemanticAnalyzer {
             }
         }
 
+        // the builtins are always there, a program that writes its own prototype for one just gets that instead
+        for builtin in Builtin::ALL {
+            if self.sym_table.lookup_in_current_scope(builtin.name()).is_none() {
+                let func_type = Type::Function {
+                    params: builtin.params(),
+                    return_type: Box::new(builtin.return_type()),
+                };
+                let _ = self.sym_table.declare_in_scope(builtin.name(), func_type, StorageClass::Extern, false, Span::default());
+            }
+        }
+
         // validate usages
         for decl in &program.declarations {
             if let Err(e) = self.validate_declaration(decl) {
@@ -43,22 +95,34 @@ impl SemanticAnalyzer {
             }
         }
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors)
-        }
+        errors.append(&mut self.errors);
+        errors.append(&mut self.warnings);
+        errors.sort_by_key(|d| (d.span.line, d.span.col));
+        errors
     }
 
-    fn declare_declaration(&mut self, decl: &Declaration) -> Result<(), Vec<String>> {
+    fn declare_declaration(&mut self, decl: &Declaration) -> Result<(), Vec<Diagnostic>> {
         let mut errors = vec![];
 
+        let span = decl.span();
+
         // shadowing is allowed in c, so we just check current scope for repeating symbols
         match decl {
             Declaration::Enum(enum_decl) => {
+                // enumerators share one namespace across every enum in the file
+                for variant in &enum_decl.variants {
+                    match self.enumerators.get(&variant.name) {
+                        Some(&prev) => errors.push(Diagnostic::error(Code::Redeclaration, variant.span, format!("Duplicate enumerator '{}'", variant.name))
+                            .with_label(prev, "previously declared here")),
+                        None => {
+                            self.enumerators.insert(variant.name.clone(), variant.span);
+                        }
+                    }
+                }
+
                 if let Some(name) = &enum_decl.name {
                     if self.sym_table.lookup_in_current_scope(name).is_some() {
-                        errors.push(format!("Redeclaration of enum '{}'", name));
+                        errors.push(self.redeclared("enum", name, span));
                     } else {
                         let variants: Vec<(String, Option<i64>)> = enum_decl.variants
                             .iter()
@@ -75,8 +139,9 @@ impl SemanticAnalyzer {
                             enum_type,
                             StorageClass::None,
                             false,
+                            span,
                         ) {
-                            errors.push(e);
+                            errors.push(Diagnostic::error(Code::Redeclaration, span, e));
                         }
                     }
                 }
@@ -84,27 +149,34 @@ impl SemanticAnalyzer {
 
             Declaration::Function(func_decl) => {
                 let name = &func_decl.name;
+                let param_types: Vec<Type> = func_decl.params
+                    .iter()
+                    .map(|p| p.typ.base.clone())
+                    .collect();
+
+                let func_type = Type::Function { 
+                    params: param_types, 
+                    return_type: Box::new(func_decl.return_type.base.clone()) 
+                };
 
                 if self.sym_table.lookup_in_current_scope(name).is_some() {
-                    errors.push(format!("Redec of func {:?}", name))
+                    let definition = func_decl.body.is_some().then_some(span);
+                    if let Err(e) = self.redeclare(name, func_type, &func_decl.storage_class, definition, func_decl.name_span) {
+                        errors.push(e);
+                    }
                 } else {
-                    let param_types: Vec<Type> = func_decl.params
-                        .iter()
-                        .map(|p| p.typ.base.clone())
-                        .collect();
-                    
-                    let func_type = Type::Function { 
-                        params: param_types, 
-                        return_type: Box::new(func_decl.return_type.base.clone()) 
-                    };
+                    if func_decl.body.is_some() {
+                        self.definitions.insert(name.clone(), span);
+                    }
 
                     if let Err(e) = self.sym_table.declare_in_scope(
                         name,
                         func_type,
                         func_decl.storage_class.clone(),
                         false,
+                        func_decl.name_span,
                     ) {
-                        errors.push(e);
+                        errors.push(Diagnostic::error(Code::Redeclaration, func_decl.name_span, e));
                     }
                 }
             }
@@ -112,7 +184,7 @@ impl SemanticAnalyzer {
             Declaration::Struct(struct_dec) => {
                 if let Some(name) = &struct_dec.name {
                     if self.sym_table.lookup_in_current_scope(name).is_some() {
-                        errors.push(format!("Redec of struct '{}'", name));
+                        errors.push(self.redeclared("struct", name, span));
                     } else {
                         let fields: Vec<(String, Type)> = struct_dec.fields
                             .iter()
@@ -128,8 +200,9 @@ impl SemanticAnalyzer {
                             struct_type,
                             StorageClass::None,
                             false,
+                            span,
                         ) {
-                            errors.push(e);
+                            errors.push(Diagnostic::error(Code::Redeclaration, span, e));
                         }
                     }
                 }
@@ -138,7 +211,7 @@ impl SemanticAnalyzer {
             Declaration::Union(union_dec) => {
                 if let Some(name) = &union_dec.name {
                     if self.sym_table.lookup_in_current_scope(name).is_some() {
-                        errors.push(format!("redec of union {}", name));
+                        errors.push(self.redeclared("union", name, span));
                     } else {
                         let fields: Vec<(String, Type)> = union_dec.fields.iter()
                             .map(|f| (f.name.clone(), f.typ.base.clone()))
@@ -152,8 +225,9 @@ impl SemanticAnalyzer {
                             union_type,
                             StorageClass::None,
                             false,
+                            span,
                         ) {
-                            errors.push(e);
+                            errors.push(Diagnostic::error(Code::Redeclaration, span, e));
                         }
                     }
                 }
@@ -161,20 +235,30 @@ impl SemanticAnalyzer {
 
             Declaration::Variable(var_dec) => {
                 if self.sym_table.lookup_in_current_scope(&var_dec.name).is_some() {
-                    errors.push(format!("Redec of var {:?}", var_dec.name));
-                } else if let Err(e) = self.sym_table.declare_in_scope(
-                    &var_dec.name,
-                    var_dec.typ.base.clone(),
-                    var_dec.storage_class.clone(),
-                    var_dec.typ.is_const,
-                ) {
-                    errors.push(e);
+                    let definition = var_dec.init.is_some().then_some(span);
+                    if let Err(e) = self.redeclare(&var_dec.name, var_dec.typ.base.clone(), &var_dec.storage_class, definition, var_dec.name_span) {
+                        errors.push(e);
+                    }
+                } else {
+                    if var_dec.init.is_some() {
+                        self.definitions.insert(var_dec.name.clone(), span);
+                    }
+
+                    if let Err(e) = self.sym_table.declare_in_scope(
+                        &var_dec.name,
+                        var_dec.typ.base.clone(),
+                        var_dec.storage_class.clone(),
+                        var_dec.typ.is_const,
+                        var_dec.name_span,
+                    ) {
+                        errors.push(Diagnostic::error(Code::Redeclaration, var_dec.name_span, e));
+                    }
                 }
             }
 
             Declaration::Typedef(typedef_dec) => {
                 if self.sym_table.lookup_in_current_scope(&typedef_dec.name).is_some() {
-                    errors.push(format!("Redec of typedef {:?}", typedef_dec.name));
+                    errors.push(self.redeclared("typedef", &typedef_dec.name, typedef_dec.name_span));
                 } else {
                     let typedef_type = Type::Typedef {
                         name: typedef_dec.name.clone(),
@@ -185,8 +269,9 @@ impl SemanticAnalyzer {
                         typedef_type,
                         StorageClass::None,
                         typedef_dec.typ.is_const,
+                        typedef_dec.name_span,
                     ) {
-                        errors.push(e);
+                        errors.push(Diagnostic::error(Code::Redeclaration, typedef_dec.name_span, e));
                     }
                 }
             }
@@ -199,12 +284,14 @@ impl SemanticAnalyzer {
         }
     }
 
-    fn validate_declaration(&mut self, decl: &Declaration) -> Result<(), String> {
+    fn validate_declaration(&mut self, decl: &Declaration) -> Result<(), Diagnostic> {
         match decl {
             Declaration::Function(func_decl) => {
                 if let Some(body) = &func_decl.body {
                     self.current_function_return_type = Some(func_decl.return_type.base.clone());
                     self.labels.clear();
+                    self.gotos.clear();
+                    self.initialized_locals.clear();
 
                     // create new scope for function body
                     self.sym_table.push_scope();
@@ -216,16 +303,31 @@ impl SemanticAnalyzer {
                                 param.typ.base.clone(),
                                 StorageClass::None,
                                 param.typ.is_const,
-                            )?;
+                                param.name_span,
+                            ).map_err(|_| self.redeclared("parameter", param_name, param.name_span))?;
                         }
                     }
 
+                    self.collect_labels(body, &mut vec![]);
+                    self.check_gotos();
+
                     for stmt in body {
                         self.validate_statement(stmt)?;
                     }
+                    self.check_unreachable(body);
+
+                    // main is allowed to fall off the end, it returns 0 like in c99
+                    let returns_value = self.resolve_type(&func_decl.return_type.base) != Type::Void;
+                    if returns_value && func_decl.name != "main" && self.list_falls_through(body) {
+                        self.warnings.push(Diagnostic::warning(
+                            Code::MissingReturn,
+                            func_decl.span,
+                            format!("control reaches end of non-void function '{}'", func_decl.name),
+                        ));
+                    }
 
                     // "recursive like" stack popping
-                    self.sym_table.pop_scope();
+                    self.pop_scope(&func_decl.params);
                 }
 
                 Ok(())
@@ -234,13 +336,8 @@ impl SemanticAnalyzer {
             // check type initialization and variable
             Declaration::Variable(var_dec) => {
                 if let Some(init_expr) = &var_dec.init {
-                    let init_type = self.check_expression(init_expr)?;
-                    if !self.types_compatible(&var_dec.typ.base, &init_type) {
-                        return Err(format!(
-                            "Type mismatch: expected {:?}, got {:?}",
-                            var_dec.typ.base, init_type
-                        ));
-                    }
+                    self.check_initializer(&var_dec.typ.base, init_expr, var_dec.span)?;
+                    self.check_constant_initializer(init_expr)?;
                 }
                 Ok(())
             }
@@ -249,7 +346,8 @@ impl SemanticAnalyzer {
             Declaration::Struct(struct_dec) => {
                 if let Some(_name) = &struct_dec.name {
                     for field in &struct_dec.fields {
-                        self.validate_type(&field.typ.base)?;
+                        self.validate_type(&field.typ.base)
+                            .map_err(|e| Diagnostic::error(Code::UnknownType, field.span, e))?;
                     }
                 }
                 Ok(())
@@ -259,7 +357,8 @@ impl SemanticAnalyzer {
             Declaration::Union(union_dec) => {
                 if let Some(_name) = &union_dec.name {
                     for field in &union_dec.fields {
-                        self.validate_type(&field.typ.base)?;
+                        self.validate_type(&field.typ.base)
+                            .map_err(|e| Diagnostic::error(Code::UnknownType, field.span, e))?;
                     }
                 }
                 Ok(())
@@ -272,76 +371,79 @@ impl SemanticAnalyzer {
 
             // just validate alised type exists
             Declaration::Typedef(typedef_dec) => {
-                self.validate_type(&typedef_dec.typ.base)?;
+                self.validate_type(&typedef_dec.typ.base)
+                    .map_err(|e| Diagnostic::error(Code::UnknownType, typedef_dec.span, e))?;
                 Ok(())
             }
         }
     }
 
-    fn validate_statement(&mut self, stmt: &Statement) -> Result<(), String> {
-        match stmt {
-            Statement::VarDec(typ, name, init, storage_class) => {
-                self.sym_table.declare_in_scope(name, typ.base.clone(), storage_class.clone(), typ.is_const)?;
+    fn validate_statement(&mut self, stmt: &Statement) -> Result<(), Diagnostic> {
+        let span = stmt.span;
+        match &stmt.kind {
+            StatementKind::VarDec(typ, name, init, storage_class, name_span) => {
+                self.sym_table.declare_in_scope(name, typ.base.clone(), storage_class.clone(), typ.is_const, *name_span)
+                    .map_err(|_| self.redeclared("variable", name, *name_span))?;
                 
                 // checking variable initializer types are good
                 if let Some(expr) = init {
-                    let init_type = self.check_expression(expr)?;
-                    if !self.types_compatible(&typ.base, &init_type) {
-                        return Err(format!(
-                            "Type mismatch: expected {:?}, got {:?}",
-                            typ.base, init_type
-                        ));
+                    self.check_initializer(&typ.base, expr, span)?;
+
+                    // a static is initialized once before the program runs, so there's nothing to evaluate it with
+                    if *storage_class == StorageClass::Static {
+                        self.check_constant_initializer(expr)?;
                     }
                 }
                 Ok(())
             }
 
             // validate left and right expressions then check type assignment
-            Statement::Assign(lhs, rhs) => {
+            StatementKind::Assign(lhs, rhs) => {
                 let rhs_type = self.check_expression(rhs)?;
                 let lhs_type = self.check_expression(lhs)?;
 
                 if !self.is_lvalue(lhs) {
-                    return Err("Left side of assignment must be an lvalue".to_string());
+                    return Err(Diagnostic::error(Code::NotAnLvalue, span, "Left side of assignment must be an lvalue").with_note(LVALUE_NOTE));
                 }
+                self.check_not_array(&lhs_type, span)?;
                 
-                if !self.types_compatible(&lhs_type, &rhs_type) {
-                    return Err(format!(
+                if !self.types_compatible(&lhs_type, &rhs_type) && !self.is_null_pointer(&lhs_type, rhs) {
+                    return Err(Diagnostic::error(Code::TypeMismatch, span, format!(
                         "Type mismatch: expected {:?}, got {:?}",
                         lhs_type, rhs_type
-                    ));
+                    )));
                 }
                 Ok(())
             }
 
             // check return expr then check if the return type matches expected
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 let expr_type = self.check_expression(expr)?;
                 
                 if let Some(expected_type) = &self.current_function_return_type {
-                    if !self.types_compatible(expected_type, &expr_type) {
-                        return Err(format!(
+                    if !self.types_compatible(expected_type, &expr_type) && !self.is_null_pointer(expected_type, expr) {
+                        return Err(Diagnostic::error(Code::ReturnMismatch, span, format!(
                             "Return type mismatch: expected {:?}, got {:?}",
                             expected_type, expr_type
-                        ));
+                        )));
                     }
                 }
                 Ok(())
             }
 
             // self ex
-            Statement::ReturnVoid => {
+            StatementKind::ReturnVoid => {
                 if let Some(expected_type) = &self.current_function_return_type {
                     if expected_type != &Type::Void {
-                        return Err(format!("Expected return value of type {:?}", expected_type));
+                        return Err(Diagnostic::error(Code::ReturnMismatch, span, format!("Expected return value of type {:?}", expected_type)));
                     }
                 }
                 Ok(())
             }
 
             // validate condition then validate stmts in body
-            Statement::If(cond, then_body, else_body) => {
-                self.check_expression(cond)?;
+            StatementKind::If(cond, then_body, else_body) => {
+                self.check_condition(cond)?;
                 
                 for stmt in then_body {
                     self.validate_statement(stmt)?;
@@ -355,8 +457,8 @@ impl SemanticAnalyzer {
                 Ok(())
             }
 
-            Statement::While(cond, body) => {
-                self.check_expression(cond)?;
+            StatementKind::While(cond, body) => {
+                self.check_condition(cond)?;
                 
                 self.loop_depth += 1;
                 for stmt in body {
@@ -366,7 +468,7 @@ impl SemanticAnalyzer {
                 Ok(())
             }
 
-            Statement::For(init, cond, inc, body) => {
+            StatementKind::For(init, cond, inc, body) => {
                 self.sym_table.push_scope();
                 
                 if let Some(init_stmt) = init {
@@ -374,7 +476,7 @@ impl SemanticAnalyzer {
                 }
                 
                 if let Some(cond_expr) = cond {
-                    let _cond_type = self.check_expression(cond_expr)?;
+                    let _cond_type = self.check_condition(cond_expr)?;
                 }
                 
                 if let Some(inc_expr) = inc {
@@ -387,26 +489,26 @@ impl SemanticAnalyzer {
                 }
                 self.loop_depth -= 1;
                 
-                self.sym_table.pop_scope();
+                self.pop_scope(&[]);
                 Ok(())
             }
 
-            Statement::Break => {
+            StatementKind::Break => {
                 if self.loop_depth == 0 && self.switch_depth == 0 {
-                    return Err("break statement outside of loop or switch".to_string());
+                    return Err(Diagnostic::error(Code::MisplacedJump, span, "break statement outside of loop or switch"));
                 }
                 Ok(())
             }
 
-            Statement::Continue => {
+            StatementKind::Continue => {
                 if self.loop_depth == 0 {
-                    return Err("continue statement outside of loop".to_string());
+                    return Err(Diagnostic::error(Code::MisplacedJump, span, "continue statement outside of loop"));
                 }
                 Ok(())
             }
 
-            Statement::DoWhile(do_while_stmt) => {
-                self.check_expression(&do_while_stmt.condition)?;
+            StatementKind::DoWhile(do_while_stmt) => {
+                self.check_condition(&do_while_stmt.condition)?;
                 
                 self.loop_depth += 1;
                 for stmt in &do_while_stmt.body {
@@ -416,52 +518,55 @@ impl SemanticAnalyzer {
                 Ok(())
             }
 
-            Statement::Switch(switch_stmt) => {
+            StatementKind::Switch(switch_stmt) => {
                 let expr_type = self.check_expression(&switch_stmt.expr)?;
                 if !self.is_integer_type(&expr_type) {
-                    return Err(format!("Switch expression must be integer type, got {:?}", expr_type));
+                    return Err(Diagnostic::error(Code::InvalidSwitch, span, format!("Switch expression must be integer type, got {:?}", expr_type)));
                 }
                 
-                let mut seen_default = false;
+                let mut seen_default: Option<Span> = None;
                 
                 for case in &switch_stmt.cases {
                     if let Some(case_val) = &case.value {
                         let case_type = self.check_expression(case_val)?;
                         if !self.is_integer_type(&case_type) {
-                            return Err(format!("Case value must be integer type, got {:?}", case_type));
+                            return Err(Diagnostic::error(Code::InvalidSwitch, case_val.span, format!("Case value must be integer type, got {:?}", case_type)));
                         }
                     } else {
-                        if seen_default {
-                            return Err("Multiple default cases in switch".to_string());
+                        if let Some(first) = seen_default {
+                            return Err(Diagnostic::error(Code::InvalidSwitch, case.span, "Multiple default cases in switch")
+                                .with_label(first, "first default here"));
                         }
-                        seen_default = true;
+                        seen_default = Some(case.span);
                     }
                     
                     self.switch_depth += 1;
                     for stmt in &case.stmts {
                         self.validate_statement(stmt)?;
                     }
+                    self.check_unreachable(&case.stmts);
                     self.switch_depth -= 1;
                 }
                 
                 Ok(())
             }
 
-            Statement::ExprStatement(expr) => {
+            StatementKind::ExprStatement(expr) => {
                 self.check_expression(expr)?;
                 Ok(())
             }
 
-            Statement::Block(stmts) => {
+            StatementKind::Block(stmts) => {
                 self.sym_table.push_scope();
                 for stmt in stmts {
                     self.validate_statement(stmt)?;
                 }
-                self.sym_table.pop_scope();
+                self.check_unreachable(stmts);
+                self.pop_scope(&[]);
                 Ok(())
             }
 
-            Statement::CompoundAssign(op, lhs, rhs) => {
+            StatementKind::CompoundAssign(op, lhs, rhs) => {
                 let lhs_type = self.check_expression(lhs)?;
                 let rhs_type = self.check_expression(rhs)?;
                 
@@ -471,104 +576,447 @@ impl SemanticAnalyzer {
                         if matches!(lhs_type, Type::Pointer(_)) && self.is_integer_type(&rhs_type) {
                             
                         } else if !self.is_numeric_type(&lhs_type) || !self.is_numeric_type(&rhs_type) {
-                            return Err(format!(
+                            return Err(Diagnostic::error(Code::InvalidOperands, span, format!(
                                 "Invalid types for {:?}: {:?} and {:?}",
                                 op, lhs_type, rhs_type
-                            ));
+                            )));
                         }
                     }
                     CompoundOp::MulAssign | CompoundOp::DivAssign => {
                         if !self.is_numeric_type(&lhs_type) || !self.is_numeric_type(&rhs_type) {
-                            return Err(format!(
+                            return Err(Diagnostic::error(Code::InvalidOperands, span, format!(
                                 "Invalid types for {:?}: {:?} and {:?}",
                                 op, lhs_type, rhs_type
-                            ));
+                            )));
                         }
                     }
                     CompoundOp::ModAssign | CompoundOp::AndAssign | CompoundOp::OrAssign |
                     CompoundOp::XorAssign | CompoundOp::LShiftAssign | CompoundOp::RShiftAssign => {
                         if !self.is_integer_type(&lhs_type) || !self.is_integer_type(&rhs_type) {
-                            return Err(format!(
+                            return Err(Diagnostic::error(Code::InvalidOperands, span, format!(
                                 "{:?} requires integer types, got {:?} and {:?}",
                                 op, lhs_type, rhs_type
-                            ));
+                            )));
                         }
                     }
                 }
                 
                 if !self.is_lvalue(lhs) {
-                    return Err("Left side of compound assignment must be an lvalue".to_string());
+                    return Err(Diagnostic::error(Code::NotAnLvalue, span, "Left side of compound assignment must be an lvalue").with_note(LVALUE_NOTE));
                 }
                 
                 Ok(())
             }
 
-            // TOOD: handle goto semantics
-            Statement::Goto(_) => {
-                // nothing fo rnow
+            // already checked by check_gotos, which runs once every label in the function is known
+            StatementKind::Goto(_) => Ok(()),
+
+            StatementKind::Label(_, stmt) => {
+                self.validate_statement(stmt)?;
                 Ok(())
             }
+        }
+    }
 
-            Statement::Label(label, stmt) => {
-                if self.labels.contains(label) {
-                    return Err(format!("Duplicate label '{}'", label));
+    // points back at the earlier declaration when there is one in the current scope
+    fn redeclared(&self, what: &str, name: &str, span: Span) -> Diagnostic {
+        let diag = Diagnostic::error(Code::Redeclaration, span, format!("Duplicate declaration of {} '{}'", what, name));
+        match self.sym_table.lookup_in_current_scope(name) {
+            Some(prev) => diag.with_label(prev.span, "previously declared here"),
+            None => diag,
+        }
+    }
+
+    // a file scope function or variable can be declared again, a prototype before its body or an
+    // extern before the definition, as long as the types agree. it can only be defined once.
+    // definition is the whole declaration's span when it is one, span is the name's
+    fn redeclare(&mut self, name: &str, typ: Type, storage_class: &StorageClass, definition: Option<Span>, span: Span) -> Result<(), Diagnostic> {
+        let Some(prev) = self.sym_table.lookup_in_current_scope(name) else {
+            return Ok(());
+        };
+        let prev_span = prev.span;
+        let prev_static = prev.storage_class == StorageClass::Static;
+
+        if !matches!(prev.typ, Type::Function { .. }) && !self.is_object_type(&prev.typ) {
+            return Err(self.redeclared(if matches!(typ, Type::Function { .. }) { "function" } else { "variable" }, name, span));
+        }
+        if !self.declarations_agree(&prev.typ, &typ) {
+            return Err(Diagnostic::error(Code::Redeclaration, span, format!("Conflicting types for '{}'", name))
+                .with_label(prev_span, "previously declared here"));
+        }
+        if *storage_class == StorageClass::Static && !prev_static {
+            return Err(Diagnostic::error(Code::Redeclaration, span, format!("Static declaration of '{}' follows non-static declaration", name))
+                .with_label(prev_span, "previously declared here"));
+        }
+        if let Some(def_span) = definition {
+            if let Some(&prev_def) = self.definitions.get(name) {
+                return Err(Diagnostic::error(Code::Redeclaration, span, format!("Redefinition of '{}'", name))
+                    .with_label(prev_def, "previously defined here"));
+            }
+            self.definitions.insert(name.to_string(), def_span);
+        }
+
+        // int a[]; then int a[10]; the later one knows the size
+        if let Some(prev) = self.sym_table.lookup_mut(name)
+            && matches!(prev.typ, Type::Array(_, None)) {
+            prev.typ = typ;
+        }
+        Ok(())
+    }
+
+    // a variable's type, anything but a function or a struct/union/enum/typedef name
+    fn is_object_type(&self, typ: &Type) -> bool {
+        !matches!(typ, Type::Function { .. } | Type::Struct { .. } | Type::Union { .. } | Type::Enum { .. } | Type::Typedef { .. })
+    }
+
+    // same type, except an array's size can be left out in one of them
+    fn declarations_agree(&self, a: &Type, b: &Type) -> bool {
+        match (self.resolve_type(a), self.resolve_type(b)) {
+            (Type::Array(a, None), Type::Array(b, _)) | (Type::Array(a, _), Type::Array(b, None)) => self.declarations_agree(&a, &b),
+            (Type::Function { params: a_params, return_type: a_ret }, Type::Function { params: b_params, return_type: b_ret }) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(&b_params).all(|(a, b)| self.declarations_agree(a, b))
+                    && self.declarations_agree(&a_ret, &b_ret)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    // pops a scope and warns about anything declared in it that was never referenced
+    // pass the function's params when closing its outermost scope so they get their own warning
+    fn pop_scope(&mut self, params: &[Param]) {
+        let Some(scope) = self.sym_table.pop_scope() else {
+            return;
+        };
+
+        for sym in scope.symbols() {
+            if sym.used || sym.storage_class == StorageClass::Extern {
+                continue;
+            }
+            let name = self.sym_table.get_name(sym.name_id);
+            let is_param = params.iter().any(|p| p.name.as_deref() == Some(name));
+            let (code, what) = if is_param {
+                (Code::UnusedParameter, "parameter")
+            } else {
+                (Code::UnusedVariable, "variable")
+            };
+            self.warnings.push(Diagnostic::warning(code, sym.span, format!("unused {} '{}'", what, name)));
+        }
+    }
+
+    // first pass over a function body: find every label and goto, and which initialized locals each one can see
+    fn collect_labels(&mut self, stmts: &[Statement], in_scope: &mut Vec<usize>) {
+        let depth = in_scope.len();
+        for stmt in stmts {
+            self.collect_labels_in(stmt, in_scope);
+        }
+        in_scope.truncate(depth);
+    }
+
+    fn collect_labels_in(&mut self, stmt: &Statement, in_scope: &mut Vec<usize>) {
+        match &stmt.kind {
+            // a static is initialized before the program starts, jumping past it skips nothing
+            StatementKind::VarDec(_, name, Some(_), storage_class, _) if *storage_class != StorageClass::Static => {
+                in_scope.push(self.initialized_locals.len());
+                self.initialized_locals.push((name.clone(), stmt.span));
+            }
+
+            StatementKind::Label(label, inner) => {
+                if let Some(prev) = self.labels.get(label) {
+                    self.errors.push(Diagnostic::error(Code::DuplicateLabel, stmt.span, format!("Duplicate label '{}'", label))
+                        .with_label(prev.span, "first defined here"));
+                } else {
+                    self.labels.insert(label.clone(), LabelInfo {
+                        span: stmt.span,
+                        in_scope: in_scope.clone(),
+                        used: false,
+                    });
                 }
-                self.labels.insert(label.clone());
-                self.validate_statement(stmt)?;
-                Ok(())
+                self.collect_labels_in(inner, in_scope);
+            }
+
+            StatementKind::Goto(label) => {
+                self.gotos.push(GotoInfo {
+                    label: label.clone(),
+                    span: stmt.span,
+                    in_scope: in_scope.clone(),
+                });
+            }
+
+            StatementKind::Block(stmts) => self.collect_labels(stmts, in_scope),
+            StatementKind::If(_, then_body, else_body) => {
+                self.collect_labels(then_body, in_scope);
+                if let Some(else_body) = else_body {
+                    self.collect_labels(else_body, in_scope);
+                }
+            }
+            StatementKind::While(_, body) => self.collect_labels(body, in_scope),
+            StatementKind::DoWhile(do_while_stmt) => self.collect_labels(&do_while_stmt.body, in_scope),
+
+            // the init clause is scoped to the loop
+            StatementKind::For(init, _, _, body) => {
+                let depth = in_scope.len();
+                if let Some(init) = init {
+                    self.collect_labels_in(init, in_scope);
+                }
+                self.collect_labels(body, in_scope);
+                in_scope.truncate(depth);
+            }
+
+            // every case shares the one switch body scope
+            StatementKind::Switch(switch_stmt) => {
+                let depth = in_scope.len();
+                for case in &switch_stmt.cases {
+                    for stmt in &case.stmts {
+                        self.collect_labels_in(stmt, in_scope);
+                    }
+                }
+                in_scope.truncate(depth);
+            }
+
+            _ => {}
+        }
+    }
+
+    // second pass: every goto needs a label, and must not land inside the scope of a variable
+    // whose initializer it jumped over
+    fn check_gotos(&mut self) {
+        for goto in &self.gotos {
+            let Some(target) = self.labels.get_mut(&goto.label) else {
+                self.errors.push(Diagnostic::error(Code::UndefinedLabel, goto.span, format!("undefined label '{}'", goto.label)));
+                continue;
+            };
+            target.used = true;
+
+            if let Some(&skipped) = target.in_scope.iter().find(|i| !goto.in_scope.contains(i)) {
+                let (name, decl_span) = &self.initialized_locals[skipped];
+                self.errors.push(Diagnostic::error(
+                    Code::JumpPastInitialization,
+                    goto.span,
+                    format!("goto '{}' jumps over the initialization of '{}'", goto.label, name),
+                )
+                .with_label(*decl_span, format!("'{}' is initialized here", name))
+                .with_label(target.span, "and is still in scope at the label"));
+            }
+        }
+
+        for (name, info) in &self.labels {
+            if !info.used {
+                self.warnings.push(Diagnostic::warning(Code::UnusedLabel, info.span, format!("unused label '{}'", name)));
+            }
+        }
+    }
+
+    // if (x = 0) is almost always a typo for ==
+    fn check_condition(&mut self, cond: &Expr) -> Result<Type, Diagnostic> {
+        if let ExprKind::Assign(_, _) = &cond.kind {
+            self.warnings.push(
+                Diagnostic::warning(Code::AssignInCondition, cond.span, "assignment used as a condition")
+                    .with_note("use '==' to compare, or compare the assignment against 0 if it is intended"),
+            );
+        }
+        self.check_expression(cond)
+    }
+
+    // only the first dead statement of each list is reported, the rest are dead for the same reason
+    fn check_unreachable(&mut self, stmts: &[Statement]) {
+        for pair in stmts.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+
+            // labels can still be reached with a goto
+            if !self.falls_through(prev) && !matches!(next.kind, StatementKind::Label(..)) {
+                self.warnings.push(
+                    Diagnostic::warning(Code::Unreachable, next.span, "unreachable code")
+                        .with_label(prev.span, "any code following this statement is unreachable"),
+                );
+                return;
+            }
+        }
+    }
+
+    // can control run off the end of this statement into the next one
+    fn falls_through(&self, stmt: &Statement) -> bool {
+        match &stmt.kind {
+            StatementKind::Return(_) | StatementKind::ReturnVoid | StatementKind::Break
+            | StatementKind::Continue | StatementKind::Goto(_) => false,
+
+            StatementKind::Block(stmts) => self.list_falls_through(stmts),
+            StatementKind::Label(_, inner) => self.falls_through(inner),
+
+            StatementKind::If(_, then_body, Some(else_body)) => {
+                self.list_falls_through(then_body) || self.list_falls_through(else_body)
+            }
+
+            // infinite loops can only be left through a break
+            StatementKind::While(cond, body) => !is_always_true(cond) || self.contains_break(body),
+            // the condition is only reached by finishing the body or a continue
+            StatementKind::DoWhile(do_while_stmt) => {
+                let body = &do_while_stmt.body;
+                let reaches_condition = self.list_falls_through(body) || self.contains_continue(body);
+                (!is_always_true(&do_while_stmt.condition) && reaches_condition) || self.contains_break(body)
+            }
+            StatementKind::For(_, cond, _, body) => {
+                cond.as_ref().is_some_and(|c| !is_always_true(c)) || self.contains_break(body)
+            }
+
+            // without a default some value skips every case, otherwise cases fall into each other
+            // so only a break or the last case finishing gets us out
+            StatementKind::Switch(switch_stmt) => {
+                !switch_stmt.cases.iter().any(|c| c.value.is_none())
+                    || switch_stmt.cases.iter().any(|c| self.contains_break(&c.stmts))
+                    || switch_stmt.cases.last().is_none_or(|c| self.list_falls_through(&c.stmts))
             }
+
+            _ => true,
+        }
+    }
+
+    fn list_falls_through(&self, stmts: &[Statement]) -> bool {
+        let mut reachable = true;
+        for stmt in stmts {
+            // a label makes code after a return reachable again
+            if reachable || matches!(stmt.kind, StatementKind::Label(..)) {
+                reachable = self.falls_through(stmt);
+            }
+        }
+        reachable
+    }
+
+    // breaks that leave the enclosing loop or switch, nested loops and switches own theirs
+    fn contains_break(&self, stmts: &[Statement]) -> bool {
+        stmts.iter().any(|stmt| match &stmt.kind {
+            StatementKind::Break => true,
+            StatementKind::Block(inner) => self.contains_break(inner),
+            StatementKind::Label(_, inner) => self.contains_break(std::slice::from_ref(inner.as_ref())),
+            StatementKind::If(_, then_body, else_body) => {
+                self.contains_break(then_body) || else_body.as_ref().is_some_and(|e| self.contains_break(e))
+            }
+            _ => false,
+        })
+    }
+
+    // continues that go to the enclosing loop, a switch doesn't own them but nested loops do
+    fn contains_continue(&self, stmts: &[Statement]) -> bool {
+        stmts.iter().any(|stmt| match &stmt.kind {
+            StatementKind::Continue => true,
+            StatementKind::Block(inner) => self.contains_continue(inner),
+            StatementKind::Label(_, inner) => self.contains_continue(std::slice::from_ref(inner.as_ref())),
+            StatementKind::If(_, then_body, else_body) => {
+                self.contains_continue(then_body) || else_body.as_ref().is_some_and(|e| self.contains_continue(e))
+            }
+            StatementKind::Switch(switch_stmt) => switch_stmt.cases.iter().any(|c| self.contains_continue(&c.stmts)),
+            _ => false,
+        })
+    }
+
+    // x == x, 1 < 2, unsigned < 0 and friends
+    fn check_constant_comparison(&mut self, op: &BinOp, lhs: &Expr, rhs: &Expr, lhs_type: &Type, rhs_type: &Type, span: Span) {
+        let is_unsigned = |t: &Type| matches!(self.resolve_type(t), Type::Unsigned(_));
+
+        let (result, note) = match (literal_value(lhs), literal_value(rhs)) {
+            (Some(l), Some(r)) => (compare_values(op, l, r), None),
+
+            (None, Some(0)) if is_unsigned(lhs_type) => match op {
+                BinOp::Lt => (Some(false), Some("unsigned values are never negative")),
+                BinOp::Ge => (Some(true), Some("unsigned values are never negative")),
+                _ => (None, None),
+            },
+            (Some(0), None) if is_unsigned(rhs_type) => match op {
+                BinOp::Gt => (Some(false), Some("unsigned values are never negative")),
+                BinOp::Le => (Some(true), Some("unsigned values are never negative")),
+                _ => (None, None),
+            },
+
+            // same variable on both sides, only for integers since NaN != NaN
+            _ => match (&lhs.kind, &rhs.kind) {
+                (ExprKind::Identifier(a), ExprKind::Identifier(b)) if a == b && self.is_integer_type(lhs_type) => {
+                    (compare_values(op, 0, 0), None)
+                }
+                _ => (None, None),
+            },
+        };
+
+        if let Some(result) = result {
+            let mut diag = Diagnostic::warning(Code::ConstantComparison, span, format!("comparison is always {}", result));
+            if let Some(note) = note {
+                diag = diag.with_note(note);
+            }
+            self.warnings.push(diag);
         }
     }
 
     // make sure it is left valuw (something that identifies a mem loc)
     fn is_lvalue(&self, expr: &Expr) -> bool {
-        match expr {
-            Expr::Identifier(_) => true,
-            Expr::Deref(_) => true,
-            Expr::ArrayIndex(_, _) => true,
-            Expr::FieldAccess(_, _) => true,
-            Expr::PtrMember(_, _) => true,
+        match &expr.kind {
+            ExprKind::Identifier(_) => true,
+            ExprKind::Deref(_) => true,
+            ExprKind::ArrayIndex(_, _) => true,
+            ExprKind::FieldAccess(_, _) => true,
+            ExprKind::PtrMember(_, _) => true,
             _ => false,
         }
     }
 
-    fn check_expression(&mut self, expr: &Expr) -> Result<Type, String> {
-        match expr {
-            Expr::IntLiteral(_) => Ok(Type::Int),
-            Expr::BoolLiteral(_) => Ok(Type::Int),
-            Expr::FloatLiteral(_) => Ok(Type::Double),
-            Expr::CharLiteral(_) => Ok(Type::Char),
-            Expr::StringLiteral(_) => Ok(Type::Pointer(Box::new(Type::Char))),
-            Expr::Null => Ok(Type::Pointer(Box::new(Type::Void))),
+    fn check_expression(&mut self, expr: &Expr) -> Result<Type, Diagnostic> {
+        let span = expr.span;
+        match &expr.kind {
+            // too big for an int makes it a long, like C
+            ExprKind::IntLiteral(val) => Ok(if i32::try_from(*val).is_ok() { Type::Int } else { Type::Long }),
+            ExprKind::BoolLiteral(_) => Ok(Type::Int),
+            ExprKind::FloatLiteral(_) => Ok(Type::Double),
+            ExprKind::CharLiteral(_) => Ok(Type::Char),
+            // char[N] counting the nul, it decays to char * like any other array
+            ExprKind::StringLiteral(text) => Ok(Type::Array(Box::new(Type::Char), Some(text.len() + 1))),
+            ExprKind::Null => Ok(Type::Pointer(Box::new(Type::Void))),
 
             // check if it's declared in symtabe
-            Expr::Identifier(name) => {
-                self.sym_table.lookup(name)
-                    .map(|sym| sym.typ.clone())
-                    .ok_or_else(|| format!("Undeclared identifier '{}'", name))
+            ExprKind::Identifier(name) => {
+                let sym = self.sym_table.lookup_mut(name)
+                    .ok_or_else(|| Diagnostic::error(Code::UndeclaredIdentifier, span, format!("Undeclared identifier '{}'", name)))?;
+                sym.used = true;
+                let typ = sym.typ.clone();
+                if sym.scope_level == 0 {
+                    self.global_uses.entry(name.clone()).or_insert(span);
+                }
+                Ok(typ)
             }
 
             // lhs and rhs
-            Expr::BinOp(lhs, op, rhs) => {
-                let lhs_type = self.check_expression(lhs)?;
-                let rhs_type = self.check_expression(rhs)?;
-                self.check_binary_op(op, &lhs_type, &rhs_type)
+            // arrays used in arithmetic or comparisons are pointers to their first element
+            ExprKind::BinOp(lhs, op, rhs) => {
+                let mut lhs_type = decay(self.check_expression(lhs)?);
+                let mut rhs_type = decay(self.check_expression(rhs)?);
+
+                // comparing a pointer with 0 compares it with null
+                if matches!(op, BinOp::Eq | BinOp::NotEq) {
+                    if self.is_null_pointer(&lhs_type, rhs) {
+                        rhs_type = lhs_type.clone();
+                    } else if self.is_null_pointer(&rhs_type, lhs) {
+                        lhs_type = rhs_type.clone();
+                    }
+                }
+                let result = self.check_binary_op(op, &lhs_type, &rhs_type)
+                    .map_err(|e| Diagnostic::error(Code::InvalidOperands, span, e))?;
+                self.check_constant_comparison(op, lhs, rhs, &lhs_type, &rhs_type, span);
+                Ok(result)
             }
 
             // just expr
-            Expr::UnaryOp(op, operand) => {
+            ExprKind::UnaryOp(op, operand) => {
                 if matches!(op, UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec) {
                     if !self.is_lvalue(operand) {
-                        return Err("Increment/decrement requires an lvalue".to_string());
+                        return Err(Diagnostic::error(Code::NotAnLvalue, span, "Increment/decrement requires an lvalue").with_note(LVALUE_NOTE));
                     }
                 }
 
                 let operand_type = self.check_expression(operand)?;
                 self.check_unary_op(op, &operand_type)
+                    .map_err(|e| Diagnostic::error(Code::InvalidOperands, span, e))
             }
 
-            Expr::Call(callee, args) => {
+            ExprKind::Call(callee, args) => {
                 let callee_type = self.check_expression(callee)?;
+                let callee_type = self.resolve_type(&callee_type);
 
                 // handling .method() and ->method()
                 let (params, return_type) = match &callee_type {
@@ -579,27 +1027,27 @@ impl SemanticAnalyzer {
                         if let Type::Function { params, return_type } = inner.as_ref() {
                             (params.clone(), *return_type.clone())
                         } else {
-                            return Err(format!("Call on non func type {:?}", callee_type));
+                            return Err(Diagnostic::error(Code::InvalidCall, span, format!("Call on non func type {:?}", callee_type)));
                         }
                     }
-                    _ => return Err(format!("Call on non function type {:?}", callee_type)),
+                    _ => return Err(Diagnostic::error(Code::InvalidCall, span, format!("Call on non function type {:?}", callee_type))),
                 };
 
                 if args.len() != params.len() {
-                    return Err(format!(
+                    return Err(Diagnostic::error(Code::InvalidCall, span, format!(
                         "Expected {} arguments, got {}",
                         params.len(), args.len()
-                    ));
+                    )));
                 }
 
                 // check arguments against parameters
                 for (arg, param_type) in args.iter().zip(params.iter()) {
                     let arg_type = self.check_expression(arg)?;
-                    if !self.types_compatible(param_type, &arg_type) {
-                        return Err(format!(
+                    if !self.types_compatible(param_type, &arg_type) && !self.is_null_pointer(param_type, arg) {
+                        return Err(Diagnostic::error(Code::TypeMismatch, span, format!(
                             "Argument type mismatch: expected {:?}, got {:?}",
                             param_type, arg_type
-                        ));
+                        )));
                     }
                 }
 
@@ -607,134 +1055,143 @@ impl SemanticAnalyzer {
             }
 
             // expr.field
-            Expr::FieldAccess(obj, field) => {
+            ExprKind::FieldAccess(obj, field) => {
                 let obj_type = self.check_expression(obj)?;
                 self.get_field_type(&obj_type, field)
+                    .map_err(|e| Diagnostic::error(Code::InvalidMemberAccess, span, e))
             }
 
             // expr->feild
-            Expr::PtrMember(ptr, field) => {
+            ExprKind::PtrMember(ptr, field) => {
                 let ptr_type = self.check_expression(ptr)?;
                 match ptr_type {
-                    Type::Pointer(inner) => self.get_field_type(&inner, field),
-                    _ => Err(format!("Cannot use -> on non-pointer type {:?}", ptr_type)),
+                    Type::Pointer(inner) => self.get_field_type(&inner, field)
+                        .map_err(|e| Diagnostic::error(Code::InvalidMemberAccess, span, e)),
+                    _ => Err(Diagnostic::error(Code::InvalidMemberAccess, span, format!("Cannot use -> on non-pointer type {:?}", ptr_type))),
                 }
             }
 
             // arr[idx]
-            Expr::ArrayIndex(arr, idx) => {
+            ExprKind::ArrayIndex(arr, idx) => {
                 let arr_type = self.check_expression(arr)?;
                 let idx_type = self.check_expression(idx)?;
 
                 if !self.is_integer_type(&idx_type) {
-                    return Err("Array index must be an integer type".to_string());
+                    return Err(Diagnostic::error(Code::InvalidIndex, span, "Array index must be an integer type"));
                 }
 
                 match arr_type {
                     Type::Array(elem_type, _) => Ok(*elem_type),
                     Type::Pointer(elem_type) => Ok(*elem_type),
-                    _ => Err(format!("Cannot index into {:?}", arr_type)),
+                    _ => Err(Diagnostic::error(Code::InvalidIndex, span, format!("Cannot index into {:?}", arr_type))),
                 }
             }
 
             // *var
-            Expr::Deref(expr) => {
+            ExprKind::Deref(expr) => {
                 let expr_type = self.check_expression(expr)?;
-                match expr_type {
+                match self.resolve_type(&expr_type) {
                     Type::Pointer(inner) => Ok(*inner),
                     Type::Array(inner, _) => Ok(*inner),
-                    _ => Err(format!("Cannot dereference non pointer type {:?}", expr_type)),
+                    // *f of a function is the function again, so (*fp)(x) calls it
+                    function @ Type::Function { .. } => Ok(function),
+                    _ => Err(Diagnostic::error(Code::InvalidDeref, span, format!("Cannot dereference non pointer type {:?}", expr_type))),
                 }
             }
 
             // &var just checking expressoin for now
-            Expr::AddrOf(expr) => {
+            ExprKind::AddrOf(expr) => {
                 if !self.is_lvalue(expr) {
-                    return Err("Cannot take address of non-lvalue".to_string());
+                    return Err(Diagnostic::error(Code::NotAnLvalue, span, "Cannot take address of non-lvalue").with_note(LVALUE_NOTE));
                 }
                 let expr_type = self.check_expression(expr)?;
                 Ok(Type::Pointer(Box::new(expr_type)))
             }
 
             // cond ? then : else
-            Expr::Ternary(cond, then_expr, else_expr) => {
-                self.check_expression(cond)?;
-                let then_type = self.check_expression(then_expr)?;
-                let else_type = self.check_expression(else_expr)?;
-
-                if self.types_compatible(&then_type, &else_type) {
-                    Ok(self.common_type(&then_type, &else_type))
+            ExprKind::Ternary(cond, then_expr, else_expr) => {
+                self.check_condition(cond)?;
+                let then_type = decay(self.check_expression(then_expr)?);
+                let else_type = decay(self.check_expression(else_expr)?);
+
+                if matches!(then_type, Type::Pointer(_)) && self.types_compatible(&then_type, &else_type) {
+                    Ok(then_type)
+                } else if self.types_compatible(&then_type, &else_type) {
+                    Ok(common_type(&then_type, &else_type))
                 } else {
-                    Err(format!(
+                    Err(Diagnostic::error(Code::TypeMismatch, span, format!(
                         "Ternary branches have incompatible types: {:?} and {:?}",
                         then_type, else_type
-                    ))
+                    )))
                 }
             }
 
             // (int)var
-            Expr::Cast(target_type, expr) => {
+            ExprKind::Cast(target_type, expr) => {
                 let _expr_type = self.check_expression(expr)?;
                 Ok(target_type.base.clone())
             }
 
-            Expr::SizeofType(_) => Ok(Type::Unsigned(Box::new(Type::Long))),
-            Expr::SizeofExpr(expr) => {
+            ExprKind::InitList(_) => Err(Diagnostic::error(Code::TypeMismatch, span, "A brace-enclosed list can only be used as an initializer")),
+
+            ExprKind::SizeofType(_) => Ok(Type::Unsigned(Box::new(Type::Long))),
+            ExprKind::SizeofExpr(expr) => {
                 let _expr_type = self.check_expression(expr)?;
                 Ok(Type::Unsigned(Box::new(Type::Long)))
             }
 
-            Expr::Assign(lhs, rhs) => {
+            ExprKind::Assign(lhs, rhs) => {
                 let lhs_type = self.check_expression(lhs)?;
                 let rhs_type = self.check_expression(rhs)?;
 
                 if !self.is_lvalue(lhs) {
-                    return Err("Left side of assignment must be an lvalue".to_string());
+                    return Err(Diagnostic::error(Code::NotAnLvalue, span, "Left side of assignment must be an lvalue").with_note(LVALUE_NOTE));
                 }
+                self.check_not_array(&lhs_type, span)?;
 
-                if !self.types_compatible(&lhs_type, &rhs_type) {
-                    return Err(format!(
+                if !self.types_compatible(&lhs_type, &rhs_type) && !self.is_null_pointer(&lhs_type, rhs) {
+                    return Err(Diagnostic::error(Code::TypeMismatch, span, format!(
                         "Assignment type mismatch: {:?} = {:?}",
                         lhs_type, rhs_type
-                    ));
+                    )));
                 }
 
                 Ok(lhs_type)
             }
 
-            Expr::CompoundAssign(op, lhs, rhs) => {
+            ExprKind::CompoundAssign(op, lhs, rhs) => {
                 let lhs_type = self.check_expression(lhs)?;
                 let rhs_type = self.check_expression(rhs)?;
 
                 if !self.is_lvalue(lhs) {
-                    return Err("Compound assignment requires an lvalue".to_string());
+                    return Err(Diagnostic::error(Code::NotAnLvalue, span, "Compound assignment requires an lvalue").with_note(LVALUE_NOTE));
                 }
 
                 match op {
                     CompoundOp::AddAssign | CompoundOp::SubAssign => {
                         if matches!(lhs_type, Type::Pointer(_)) && self.is_integer_type(&rhs_type) {
                         } else if !self.is_numeric_type(&lhs_type) || !self.is_numeric_type(&rhs_type) {
-                            return Err(format!(
+                            return Err(Diagnostic::error(Code::InvalidOperands, span, format!(
                                 "Invalid types for {:?}: {:?} and {:?}",
                                 op, lhs_type, rhs_type
-                            ));
+                            )));
                         }
                     }
                     CompoundOp::MulAssign | CompoundOp::DivAssign => {
                         if !self.is_numeric_type(&lhs_type) || !self.is_numeric_type(&rhs_type) {
-                            return Err(format!(
+                            return Err(Diagnostic::error(Code::InvalidOperands, span, format!(
                                 "Invalid types for {:?}: {:?} and {:?}",
                                 op, lhs_type, rhs_type
-                            ));
+                            )));
                         }
                     }
                     CompoundOp::ModAssign | CompoundOp::AndAssign | CompoundOp::OrAssign |
                     CompoundOp::XorAssign | CompoundOp::LShiftAssign | CompoundOp::RShiftAssign => {
                         if !self.is_integer_type(&lhs_type) || !self.is_integer_type(&rhs_type) {
-                            return Err(format!(
+                            return Err(Diagnostic::error(Code::InvalidOperands, span, format!(
                                 "{:?} requires integer types, got {:?} and {:?}",
                                 op, lhs_type, rhs_type
-                            ));
+                            )));
                         }
                     }
                 }
@@ -744,6 +1201,168 @@ impl SemanticAnalyzer {
         }
     }
 
+    // a brace list goes with an array (nested lists for nested arrays), anything else is a plain expression
+    fn check_initializer(&mut self, typ: &Type, init: &Expr, span: Span) -> Result<(), Diagnostic> {
+        match (self.resolve_type(typ), &init.kind) {
+            (Type::Array(elem, size), ExprKind::InitList(items)) => {
+                if let Some(extra) = size.and_then(|size| items.get(size)) {
+                    return Err(Diagnostic::error(Code::TypeMismatch, extra.span, format!(
+                        "Excess elements in array initializer, the array holds {}",
+                        size.unwrap_or_default()
+                    )));
+                }
+                for item in items {
+                    self.check_initializer(&elem, item, item.span)?;
+                }
+                Ok(())
+            }
+            // char s[] = "hi", the terminating nul is dropped if the array is exactly as long as the text
+            (Type::Array(elem, size), ExprKind::StringLiteral(text)) if self.is_char_type(&elem) => {
+                match size {
+                    Some(size) if text.len() > size => Err(Diagnostic::error(Code::TypeMismatch, init.span, format!(
+                        "Initializer string is too long, the array holds {}",
+                        size
+                    ))),
+                    _ => Ok(()),
+                }
+            }
+            (Type::Array(..), _) => Err(Diagnostic::error(Code::TypeMismatch, init.span, "Array must be initialized with a brace-enclosed list")),
+
+            // fields are filled in order, a union's list only sets its first member
+            (Type::Struct { name, fields }, ExprKind::InitList(items)) => self.check_field_initializers(&name, &fields, items),
+            (Type::Union { name, fields }, ExprKind::InitList(items)) => {
+                self.check_field_initializers(&name, &fields[..fields.len().min(1)], items)
+            }
+            (_, ExprKind::InitList(_)) => Err(Diagnostic::error(Code::TypeMismatch, init.span, format!("Cannot initialize {:?} with a brace-enclosed list", typ))),
+            _ => {
+                let init_type = self.check_expression(init)?;
+                if !self.types_compatible(typ, &init_type) && !self.is_null_pointer(typ, init) {
+                    return Err(Diagnostic::error(Code::TypeMismatch, span, format!(
+                        "Type mismatch: expected {:?}, got {:?}",
+                        typ, init_type
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn check_field_initializers(&mut self, name: &str, fields: &[(String, Type)], items: &[Expr]) -> Result<(), Diagnostic> {
+        if let Some(extra) = items.get(fields.len()) {
+            return Err(Diagnostic::error(Code::TypeMismatch, extra.span, format!(
+                "Excess elements in initializer for {}, it has {} field(s) to initialize",
+                name, fields.len()
+            )));
+        }
+        for (item, (_, field_type)) in items.iter().zip(fields) {
+            self.check_initializer(field_type, item, item.span)?;
+        }
+        Ok(())
+    }
+
+    // globals and statics are written into the data segment at compile time
+    fn check_constant_initializer(&self, init: &Expr) -> Result<(), Diagnostic> {
+        match &init.kind {
+            ExprKind::InitList(items) => items.iter().try_for_each(|item| self.check_constant_initializer(item)),
+            _ if self.is_constant_expr(init) => self.fold_constant(init).map(|_| ()),
+            _ => Err(Diagnostic::error(Code::NonConstantInitializer, init.span, "Initializer element is not a compile-time constant")
+                .with_note("globals and static locals can only be initialized with constant expressions or addresses of other globals")),
+        }
+    }
+
+    // folds a constant expression the same way codegen will write it out (see ConstScope::value).
+    // Ok(None) for sizeof and addresses, which aren't known until codegen lays out memory.
+    // a ternary only folds the branch it picks, both when the condition isn't known
+    fn fold_constant(&self, expr: &Expr) -> Result<Option<Constant>, Diagnostic> {
+        let value = match &expr.kind {
+            ExprKind::IntLiteral(val) => Constant::Int(*val),
+            ExprKind::CharLiteral(c) => Constant::Int(*c as i64),
+            ExprKind::BoolLiteral(b) => Constant::Int(*b as i64),
+            ExprKind::Null => Constant::Int(0),
+            ExprKind::FloatLiteral(val) => Constant::Float(*val),
+            ExprKind::UnaryOp(op, inner) => match (op, self.fold_constant(inner)?) {
+                (_, None) => return Ok(None),
+                (UnaryOp::Neg, Some(Constant::Int(val))) => Constant::Int(val.wrapping_neg()),
+                (UnaryOp::Neg, Some(Constant::Float(val))) => Constant::Float(-val),
+                (UnaryOp::Not, Some(value)) => Constant::Int(!is_truthy(value) as i64),
+                (UnaryOp::BitNot, Some(Constant::Int(val))) => Constant::Int(!val),
+                _ => return Err(Diagnostic::error(Code::NonConstantInitializer, expr.span, "Initializer element is not a compile-time constant")),
+            },
+            ExprKind::BinOp(lhs, op, rhs) => {
+                let (Some(l), Some(r)) = (self.fold_constant(lhs)?, self.fold_constant(rhs)?) else { return Ok(None) };
+                match fold_binop(op, l, r) {
+                    Some(value) => value,
+                    None if matches!(r, Constant::Int(0)) => {
+                        return Err(Diagnostic::error(Code::NonConstantInitializer, expr.span, "Division by zero in constant expression"));
+                    }
+                    None => return Err(Diagnostic::error(Code::NonConstantInitializer, expr.span, "Integer overflow in constant expression")),
+                }
+            }
+            ExprKind::Ternary(cond, then_expr, else_expr) => match self.fold_constant(cond)? {
+                Some(cond) if is_truthy(cond) => return self.fold_constant(then_expr),
+                Some(_) => return self.fold_constant(else_expr),
+                None => {
+                    self.fold_constant(then_expr)?;
+                    self.fold_constant(else_expr)?;
+                    return Ok(None);
+                }
+            },
+            ExprKind::Cast(typ, inner) => match self.fold_constant(inner)? {
+                Some(value) => convert_constant(value, &self.resolve_type(&typ.base)),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    // what codegen can fold: literals, operators and casts on them, sizeof, and addresses of
+    // globals, statics, string literals and functions
+    fn is_constant_expr(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::IntLiteral(_) | ExprKind::FloatLiteral(_) | ExprKind::CharLiteral(_) | ExprKind::BoolLiteral(_) | ExprKind::Null => true,
+            ExprKind::StringLiteral(_) => true,
+            ExprKind::UnaryOp(UnaryOp::Neg | UnaryOp::Not | UnaryOp::BitNot, inner) | ExprKind::Cast(_, inner) => self.is_constant_expr(inner),
+            ExprKind::BinOp(lhs, _, rhs) => self.is_constant_expr(lhs) && self.is_constant_expr(rhs),
+            ExprKind::Ternary(cond, then_expr, else_expr) => {
+                self.is_constant_expr(cond) && self.is_constant_expr(then_expr) && self.is_constant_expr(else_expr)
+            }
+            ExprKind::SizeofType(_) => true,
+            ExprKind::SizeofExpr(inner) => match inner.kind {
+                ExprKind::Identifier(_) => self.constant_place(inner).is_some(),
+                ExprKind::StringLiteral(_) => true,
+                _ => false,
+            },
+            ExprKind::AddrOf(inner) => self.constant_place(inner).is_some(),
+            ExprKind::Identifier(_) => matches!(self.constant_place(expr), Some(Type::Array(..) | Type::Function { .. })),
+            _ => false,
+        }
+    }
+
+    // type of a global or static, or of an element or field of one at a constant index.
+    // None when its address isn't known until runtime
+    fn constant_place(&self, expr: &Expr) -> Option<Type> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => self.sym_table.lookup(name)
+                .filter(|sym| sym.scope_level == 0 || sym.storage_class == StorageClass::Static)
+                .map(|sym| self.resolve_type(&sym.typ)),
+            ExprKind::ArrayIndex(arr, index) => match self.constant_place(arr)? {
+                Type::Array(elem, _) if self.is_constant_expr(index) => Some(self.resolve_type(&elem)),
+                _ => None,
+            },
+            ExprKind::FieldAccess(obj, field) => self.get_field_type(&self.constant_place(obj)?, field).ok(),
+            _ => None,
+        }
+    }
+
+    // an array names storage but can't be assigned as a whole
+    fn check_not_array(&self, lhs_type: &Type, span: Span) -> Result<(), Diagnostic> {
+        if matches!(self.resolve_type(lhs_type), Type::Array(..)) {
+            return Err(Diagnostic::error(Code::NotAnLvalue, span, format!("Cannot assign to array type {:?}", lhs_type)));
+        }
+        Ok(())
+    }
+
     fn check_binary_op(&self, op: &BinOp, lhs: &Type, rhs: &Type) -> Result<Type, String> {
         match op {
 
@@ -768,7 +1387,7 @@ impl SemanticAnalyzer {
 
                 // non pointer ariths
                 if self.is_numeric_type(lhs) && self.is_numeric_type(rhs) {
-                    Ok(self.common_type(lhs, rhs))
+                    Ok(common_type(lhs, rhs))
                 } else {
                     Err(format!("Invalid operands to {:?}: {:?} and {:?}", op, lhs, rhs))
                 }
@@ -776,7 +1395,7 @@ impl SemanticAnalyzer {
 
             BinOp::Mod => {
                 if self.is_integer_type(lhs) && self.is_integer_type(rhs) {
-                    Ok(self.common_type(lhs, rhs))
+                    Ok(common_type(lhs, rhs))
                 } else {
                     Err(format!("Modulo requires integer types, got {:?} and {:?}", lhs, rhs))
                 }
@@ -804,7 +1423,7 @@ impl SemanticAnalyzer {
             // bitwise
             BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => {
                 if self.is_integer_type(lhs) && self.is_integer_type(rhs) {
-                    Ok(self.common_type(lhs, rhs))
+                    Ok(common_type(lhs, rhs))
                 } else {
                     Err(format!("Bitwise operators require integer types, got {:?} and {:?}", lhs, rhs))
                 }
@@ -921,6 +1540,11 @@ impl SemanticAnalyzer {
 
     // checking compatible types
     // https://pebble.gitbooks.io/learning-c-with-pebble/content/appendixa.html
+    // a literal 0 converts to any pointer type
+    fn is_null_pointer(&self, expected: &Type, expr: &Expr) -> bool {
+        matches!(self.resolve_type(expected), Type::Pointer(_)) && matches!(expr.kind, ExprKind::IntLiteral(0))
+    }
+
     fn types_compatible(&self, expected: &Type, actual: &Type) -> bool {
         let expected = self.resolve_type(expected);
         let actual = self.resolve_type(actual);
@@ -955,10 +1579,15 @@ impl SemanticAnalyzer {
         // array type decays down to pointer
         if let Type::Pointer(ref ptr_inner) = expected {
             if let Type::Array(ref arr_inner, _) = actual {
-                return self.types_compatible(ptr_inner.as_ref(), arr_inner.as_ref());
+                return **ptr_inner == Type::Void || self.types_compatible(ptr_inner.as_ref(), arr_inner.as_ref());
             }
         }
 
+        // so does a function name, but only to a pointer with the same signature
+        if let (Type::Pointer(ptr_inner), Type::Function { .. }) = (&expected, &actual) {
+            return self.resolve_type(ptr_inner) == actual;
+        }
+
         false
     }
 
@@ -975,6 +1604,15 @@ impl SemanticAnalyzer {
         )
     }
 
+    // char, signed char or unsigned char, what a string literal can initialize
+    fn is_char_type(&self, typ: &Type) -> bool {
+        match self.resolve_type(typ) {
+            Type::Char => true,
+            Type::Signed(inner) | Type::Unsigned(inner) => *inner == Type::Char,
+            _ => false,
+        }
+    }
+
     fn is_numeric_type(&self, typ: &Type) -> bool {
         let typ = self.resolve_type(typ);
         self.is_integer_type(&typ) || matches!(typ, Type::Float | Type::Double)
@@ -986,22 +1624,6 @@ impl SemanticAnalyzer {
         self.is_numeric_type(&typ) || matches!(typ, Type::Pointer(_))
     }
 
-    fn common_type(&self, a: &Type, b: &Type) -> Type {
-        if matches!(a, Type::Double) || matches!(b, Type::Double) {
-            return Type::Double;
-        }
-        if matches!(a, Type::Float) || matches!(b, Type::Float) {
-            return Type::Float;
-        }
-        if matches!(a, Type::LongLong) || matches!(b, Type::LongLong) {
-            return Type::LongLong;
-        }
-        if matches!(a, Type::Long) || matches!(b, Type::Long) {
-            return Type::Long;
-        }
-        Type::Int
-    }
-
     fn validate_type(&self, typ: &Type) -> Result<(), String> {
         match typ {
             Type::StructRef(name) => {
@@ -1041,4 +1663,81 @@ impl SemanticAnalyzer {
             _ => Ok(()),
         }
     }
-}
\ No newline at end of file
+}
+
+// int[4] in an expression is an int* to its first element, a function is a pointer to it
+fn decay(typ: Type) -> Type {
+    match typ {
+        Type::Array(elem, _) => Type::Pointer(elem),
+        function @ Type::Function { .. } => Type::Pointer(Box::new(function)),
+        other => other,
+    }
+}
+
+// value of an integer constant written directly in the source
+fn literal_value(expr: &Expr) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::IntLiteral(n) => Some(*n),
+        ExprKind::CharLiteral(c) => Some(*c as i64),
+        ExprKind::BoolLiteral(b) => Some(*b as i64),
+        ExprKind::UnaryOp(UnaryOp::Neg, inner) => literal_value(inner).map(i64::wrapping_neg),
+        _ => None,
+    }
+}
+
+fn is_always_true(cond: &Expr) -> bool {
+    literal_value(cond).is_some_and(|v| v != 0)
+}
+
+// None when op isn't a comparison
+fn compare_values(op: &BinOp, l: i64, r: i64) -> Option<bool> {
+    Some(match op {
+        BinOp::Eq => l == r,
+        BinOp::NotEq => l != r,
+        BinOp::Lt => l < r,
+        BinOp::Gt => l > r,
+        BinOp::Le => l <= r,
+        BinOp::Ge => l >= r,
+        _ => return None,
+    })
+}
+
+// usual arithmetic conversions, codegen uses this too to pick int or float opcodes
+// (and signed or unsigned ones)
+pub fn common_type(a: &Type, b: &Type) -> Type {
+    if matches!(a, Type::Double) || matches!(b, Type::Double) {
+        return Type::Double;
+    }
+    if matches!(a, Type::Float) || matches!(b, Type::Float) {
+        return Type::Float;
+    }
+
+    let (a, b) = (integer_promotion(a), integer_promotion(b));
+    let is_unsigned = |t: &Type| matches!(t, Type::Unsigned(_));
+    let is_wide = |t: &Type| matches!(t, Type::Long | Type::LongLong)
+        || matches!(t, Type::Unsigned(inner) if matches!(inner.as_ref(), Type::Long | Type::LongLong));
+    let is_long_long = |t: &Type| matches!(t, Type::LongLong)
+        || matches!(t, Type::Unsigned(inner) if matches!(inner.as_ref(), Type::LongLong));
+
+    if is_wide(&a) || is_wide(&b) {
+        let base = if is_long_long(&a) || is_long_long(&b) { Type::LongLong } else { Type::Long };
+        // long holds every unsigned int, so only an unsigned 64 bit operand makes the result unsigned
+        let unsigned = (is_wide(&a) && is_unsigned(&a)) || (is_wide(&b) && is_unsigned(&b));
+        return if unsigned { Type::Unsigned(Box::new(base)) } else { base };
+    }
+
+    if is_unsigned(&a) || is_unsigned(&b) {
+        return Type::Unsigned(Box::new(Type::Int));
+    }
+    Type::Int
+}
+
+// anything narrower than int is computed as an int
+pub fn integer_promotion(typ: &Type) -> Type {
+    match typ {
+        Type::Char | Type::Short | Type::EnumRef(_) | Type::Enum { .. } => Type::Int,
+        Type::Signed(inner) => integer_promotion(inner),
+        Type::Unsigned(inner) if matches!(inner.as_ref(), Type::Char | Type::Short) => Type::Int,
+        other => other.clone(),
+    }
+}
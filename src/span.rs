@@ -0,0 +1,22 @@
+use std::fmt;
+
+// source location of a token or ast node
+// line and col are 1 based (like every editor), offset is the char index into the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+    pub offset: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, col: usize, offset: usize) -> Self {
+        Span { line, col, offset }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
@@ -1,9 +1,9 @@
 /*
 table structure:
 
-------------------------------------------------------------------------
-| name_id | typ | scope_level | storage_class | is_const | line_number |
-------------------------------------------------------------------------
+-----------------------------------------------------------------
+| name_id | typ | scope_level | storage_class | is_const | span |
+-----------------------------------------------------------------
 
 
 stack of scopes uses 1 map per scope:
@@ -12,6 +12,7 @@ stack of scopes uses 1 map per scope:
 */
 use std::collections::HashMap;
 use crate::ast::{Type, StorageClass};
+use crate::span::Span;
 
 // https://www.reddit.com/r/Compilers/comments/1dy9722/symbol_table_design/
 // going to just do stack of hash tables as my DS and then string interneing frot he lookups
@@ -54,6 +55,8 @@ pub struct Symbol {
     pub scope_level: usize,
     pub storage_class: StorageClass,
     pub is_const: bool,
+    pub span: Span, // where it was declared
+    pub used: bool, // referenced anywhere after its declaration, for unused warnings
 }
 
 pub struct Scope {
@@ -79,6 +82,14 @@ impl Scope {
     pub fn lookup(&self, name_id: usize) -> Option<&Symbol> {
         self.symbols.get(&name_id)
     }
+
+    pub fn lookup_mut(&mut self, name_id: usize) -> Option<&mut Symbol> {
+        self.symbols.get_mut(&name_id)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values()
+    }
 }
 
 pub struct SymbolTable {
@@ -102,7 +113,7 @@ impl SymbolTable {
     }
 
     // declaring symbol in curr scope
-    pub fn declare_in_scope(&mut self, name: &str, typ: Type, storage: StorageClass, is_const: bool) -> Result<(), String> {
+    pub fn declare_in_scope(&mut self, name: &str, typ: Type, storage: StorageClass, is_const: bool, span: Span) -> Result<(), String> {
         let name_id = self.intern(name);
         let symbol = Symbol {
             name_id,
@@ -110,6 +121,8 @@ impl SymbolTable {
             scope_level: self.scope_level,
             storage_class: storage,
             is_const,
+            span,
+            used: false,
         };
         self.scopes[self.scope_level].declare(name_id, symbol)
     }
@@ -127,6 +140,17 @@ impl SymbolTable {
         None
     }
 
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        let name_id = self.interner.get_id(name)?;
+
+        for i in (0..=self.scope_level).rev() {
+            if self.scopes[i].lookup(name_id).is_some() {
+                return self.scopes[i].lookup_mut(name_id);
+            }
+        }
+        None
+    }
+
     pub fn lookup_in_current_scope(&self, name: &str) -> Option<&Symbol> {
         let name_id = self.interner.get_id(name)?;
         self.scopes[self.scope_level].lookup(name_id)
@@ -1,6 +1,11 @@
 use std::collections::HashMap;
+use std::ops::Range;
+use std::process;
 
-use crate::codegen::{FunctionChunk, Instruction, OpCode};
+use crate::builtins::Builtin;
+use crate::checker::{Access, Checker};
+use crate::codegen::{arg_a, arg_b, arg_bx, arg_c, arg_sbx, op, FunctionChunk, Instruction, OpCode, Slot, Width};
+use crate::heap::{Heap, HEAP_START};
 
 /* 
     The VM >:D
@@ -12,11 +17,16 @@ use crate::codegen::{FunctionChunk, Instruction, OpCode};
         frames:         Vec<CallFrame>, each fram tracks one active func
         functions:      Vec<FunctionChunk>, the bytecode from codegen
         functionMap:    Hashmap<String, usize>, maps the function name to indices in Functions
+        memory:         Vec<u8>, byte addressable memory, pointers are offsets into it. starts at
+                        1 MiB and grows when the heap needs more
+        mem_sp:         first free byte of memory, the stack frames grow up from the end of
+                        the data segment
     
     CallFrame has:
         function_idx:   the function chunk that's the vm is currently running
         pc:             program counter
         base:           offset into global stack, -- register window start 
+        frame_addr:     where this call's stack frame starts in memory
     
     Initializing:
         - find "main" in function map and push a callframe to frames
@@ -25,9 +35,10 @@ use crate::codegen::{FunctionChunk, Instruction, OpCode};
     
     Iteration Loop:
         - grab current frame (last elem on frames stack)
-        - read the instruction at frame[top].pc from that func's bytecode
+        - read the instruction word at frame[top].pc from that func's bytecode
         - increment pc **BEFORE** executing (because of JUMP)
-        - match on instruction and do the thing
+        - match on the word's opcode and do the thing, its operands are shifted out of the
+          word (see Instruction::encode for where each one sits)
         ** every register access is stack[base + register_number]
            so r0 = stack[base + 0], r3 = stack[base + 3]
 
@@ -50,17 +61,71 @@ use crate::codegen::{FunctionChunk, Instruction, OpCode};
         - pc is incremented BEFORE execution so the offset is relative to the instruciton AFTER JMP
     
     CLOSURE:
-        - CLOSE rA, Fx just stores the function index in the stack
-        - stack[base + a] = function_index
-        - func index is just a number and CALL reads it later
+        - CLOSE rA, Fx just stores a reference to the function in the stack
+        - stack[base + a] = function_index + 1, so a null function pointer (0) never names a function
+        - func ref is just a number and CALL reads it later
         - kinda like storing a func pointer but instead of mem address its an index into the funcitons array
+        - the same number is what a function pointer holds, in a register or in memory
     
     CALL:
         - CALL rA, B, C
-            - step 1: read function index from stack[base + a] (CLOSURE put it there)
-            - step 2: calc the new base = current_base + a + 1
-            - step 3: push new CallFrame
+            - step 1: read the function ref from stack[base + a] (CLOSURE put it there)
+            - step 2: check it names a function and that B - 1 args is what that function takes,
+                      a bad function pointer stops the program here instead of jumping anywhere
+            - step 3: calc the new base = current_base + a + 1
+            - step 4: push new CallFrame
     
+    Integer widths:
+        - every register is 64 bits and arithmetic wraps (never panics on overflow)
+        - codegen follows ADD/SUB/MUL/DIV/SHL on narrower types with SEXT/ZEXT to wrap them at
+          their own width, so char, short, int and their unsigned versions overflow like gcc
+        - DIVU/MODU/SHRU/LTU/LEU/GTU/GEU read their operands as u64, used for unsigned types
+
+    Floats:
+        - a float/double register holds the f64's bits, the stack is still just i64s
+        - LOADK of a float constant loads those bits
+        - FADD/FSUB/FMUL/FDIV/FUNM read both operands back as f64 and store the bits of the result
+        - FEQ/FNE/FLT/FLE/FGT/FGE give a plain 0 or 1 like the int comparisons
+
+    Conversions:
+        - I2F/U2F rA, rB: int in rB (signed or unsigned) to the bits of an f64
+        - F2I/F2U rA, rB: f64 to int, truncating toward zero (out of range saturates)
+        - F2S rA, rB: round a double to float precision, still stored as an f64
+        - SEXT/ZEXT rA, rB, C: keep the low C bits of rB, sign or zero extended back to 64
+          so a char/short/int register always holds a value in range for its type
+
+    Memory:
+        - address 0 is null, so the first 8 bytes are never handed out
+        - the data segment (globals and static locals) is copied in at address 8, GADDR rA, Bx gives the address
+          of byte Bx in it
+        - string literals (rodata) go right after it at the next multiple of 8, SADDR rA, Bx gives the
+          address of byte Bx in them. a STORE or COPY that touches rodata panics
+        - locals that have their address taken live in the function's stack frame instead of a
+          register, CALL reserves frame_size bytes (8 aligned) past mem_sp and RETURN gives them back
+        - ADDR rA, Bx  -->  stack[base + a] = frame_addr + Bx
+        - LOAD rA, rB, C  -->  stack[base + a] = memory[stack[base + b]], C is the Width code
+          and says how many bytes to read and whether to sign or zero extend them
+        - STORE rA, rB, C  -->  memory[stack[base + a]] = stack[base + b], truncated to the width
+        - COPY rA, rB, rC  -->  copies stack[base + c] bytes from address stack[base + b] to
+          address stack[base + a], struct values are copied with it
+        - everything is little endian, an f32 width converts to/from the f64 bits in the register
+
+    Checked mode (--checked):
+        - before each instruction the checker (checker.rs) looks at the memory it's about to touch,
+          bad accesses stop the program with what went wrong and the pc of every active call
+        - catches null dereferences, out of bounds accesses, use after free or return, double
+          and invalid frees, and reads of memory nothing has written yet
+
+    CALLN:
+        - CALLN rA, B, C runs builtin C (malloc/calloc/realloc/free) right here in rust
+        - args are stack[base + a + 1..base + a + b], the result goes in stack[base + a]
+        - the heap starts at 1 MiB, above the stack frames, see heap.rs
+
+    JMPTAB:
+        - JMPTAB rA, Bx is followed by Bx + 1 JMPs, the first one is the default
+        - if 0 <= stack[base + a] < Bx, pc += stack[base + a] + 1 to land on that entry
+        - otherwise fall through into the default JMP
+
     RETURN:
         -RETURN rA, B
             - step1: if B == 2, grab the return value
@@ -81,6 +146,9 @@ struct CallFrame {
 
     /// offset into the global register array
     base: usize,
+
+    /// start of this call's stack frame in memory
+    frame_addr: usize,
 }
 
 pub struct VM {
@@ -95,25 +163,298 @@ pub struct VM {
 
     /// 
     function_map: HashMap<String, usize>,
+
+    /// byte addressable memory that pointers point into
+    memory: Vec<u8>,
+
+    /// top of the memory stack, next frame starts here
+    mem_sp: usize,
+
+    /// where the string literals sit in memory, stores into here are rejected
+    rodata: Range<usize>,
+
+    /// malloc'd blocks, above the stack frames
+    pub heap: Heap,
+
+    /// bounds, liveness and initialization tracking, only in checked mode
+    checker: Option<Checker>,
 }
 
+// leave address 0 alone so a null pointer never points at a real variable,
+// the data segment starts right after it
+pub const NULL_GUARD: usize = 8;
+
 impl VM {
-    pub fn new(functions: Vec<FunctionChunk>, function_map: HashMap<String, usize>) -> Self {
+    pub fn new(functions: Vec<FunctionChunk>, function_map: HashMap<String, usize>, data: Vec<u8>, rodata: Vec<u8>) -> Self {
+        let mut memory = vec![0u8; HEAP_START];
+        memory[NULL_GUARD..NULL_GUARD + data.len()].copy_from_slice(&data);
+
+        // same spot codegen assumed when it wrote string addresses into the data segment
+        let rodata_start = NULL_GUARD + data.len().next_multiple_of(8);
+        let rodata_range = rodata_start..rodata_start + rodata.len();
+        memory[rodata_range.clone()].copy_from_slice(&rodata);
+
         VM {
             stack: vec![0i64; 8192],
             frames: vec![],
             functions,
             function_map,
+            memory,
+            mem_sp: rodata_range.end,
+            rodata: rodata_range,
+            heap: Heap::new(),
+            checker: None,
         }
     }
 
-    pub fn run(&mut self) -> i64 {
-        let main_idx = *self.function_map.get("main").expect("No main function found");
+    // checked mode, every memory access is checked against the object it points into.
+    // globals are the data segment's slots and literals rodata's
+    pub fn enable_checks(&mut self, globals: Vec<Slot>, literals: Vec<Slot>) {
+        self.checker = Some(Checker::new(globals, literals, &self.functions, self.rodata.clone(), self.stack.len(), self.memory.len()));
+    }
+
+    // reserves a stack frame in memory for a call into func_idx
+    fn push_frame(&mut self, func_idx: usize, base: usize) {
+        let frame_addr = self.mem_sp.next_multiple_of(8);
+        self.mem_sp = frame_addr + self.functions[func_idx].frame_size as usize;
+        if self.mem_sp > HEAP_START {
+            self.runtime_error("stack overflow: out of frame memory");
+        }
+        if base + self.functions[func_idx].max_registers as usize >= self.stack.len() {
+            self.runtime_error("stack overflow: out of registers");
+        }
+        if let Some(checker) = &mut self.checker {
+            checker.push_frame(func_idx, &self.functions[func_idx], frame_addr);
+        }
         self.frames.push(CallFrame {
-            function_idx: main_idx,
+            function_idx: func_idx,
             pc: 0,
-            base: 0,
+            base,
+            frame_addr,
         });
+    }
+
+    // checks the address and gives back the bytes an access of this width touches
+    fn mem_range(&self, addr: i64, len: usize) -> Range<usize> {
+        match usize::try_from(addr) {
+            Ok(start) if start.checked_add(len).is_some_and(|end| end <= self.memory.len()) => start..start + len,
+            _ => self.runtime_error(&format!("memory access out of bounds at address {}", addr)),
+        }
+    }
+
+    fn load(&self, addr: i64, width: Width) -> i64 {
+        let bytes = &self.memory[self.mem_range(addr, width.bytes())];
+        match width {
+            Width::I8 => bytes[0] as i8 as i64,
+            Width::U8 => bytes[0] as i64,
+            Width::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+            Width::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+            Width::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            Width::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            Width::I64 => i64::from_le_bytes(bytes.try_into().unwrap()),
+            Width::F32 => float_bits(f32::from_le_bytes(bytes.try_into().unwrap()) as f64),
+        }
+    }
+
+    fn store(&mut self, addr: i64, val: i64, width: Width) {
+        let le = match width {
+            Width::F32 => (f64::from_bits(val as u64) as f32).to_le_bytes().to_vec(),
+            _ => val.to_le_bytes()[..width.bytes()].to_vec(),
+        };
+        let range = self.writable_range(addr, width.bytes());
+        self.memory[range].copy_from_slice(&le);
+    }
+
+    // same as mem_range but string literals are off limits
+    fn writable_range(&self, addr: i64, len: usize) -> Range<usize> {
+        let range = self.mem_range(addr, len);
+        if range.start < self.rodata.end && self.rodata.start < range.end {
+            self.runtime_error(&format!("write to read-only memory at address {}", addr));
+        }
+        range
+    }
+
+    fn call_builtin(&mut self, builtin: Builtin, args: &[i64]) -> i64 {
+        match builtin {
+            Builtin::Malloc => self.malloc(args[0] as usize),
+            Builtin::Calloc => {
+                let Some(size) = (args[0] as usize).checked_mul(args[1] as usize) else { return 0 };
+                let addr = self.malloc(size);
+                if addr != 0 {
+                    let start = addr as usize;
+                    self.memory[start..start + size].fill(0);
+                    if let Some(checker) = &mut self.checker {
+                        checker.initialize(start..start + size);
+                    }
+                }
+                addr
+            }
+            Builtin::Realloc => {
+                let (ptr, size) = (args[0], args[1] as usize);
+                if ptr == 0 {
+                    return self.malloc(size);
+                }
+                if let Some(Err(e)) = self.checker.as_ref().map(|checker| checker.freeable(ptr as usize, "realloc")) {
+                    self.memory_error(&e);
+                }
+                let old_size = self.heap.block_size(ptr as usize)
+                    .unwrap_or_else(|| self.memory_error(&format!("realloc of address {} which is not an allocated block", ptr)));
+                if size == 0 {
+                    self.free(ptr);
+                    return 0;
+                }
+                let addr = self.malloc(size);
+                if addr != 0 {
+                    let old = ptr as usize..ptr as usize + old_size.min(size);
+                    if let Some(checker) = &mut self.checker {
+                        checker.copied(addr as usize, old.start, old.len());
+                    }
+                    self.memory.copy_within(old, addr as usize);
+                    self.free(ptr);
+                }
+                addr
+            }
+            Builtin::Free => {
+                if args[0] != 0 {
+                    self.free(args[0]);
+                }
+                0
+            }
+        }
+    }
+
+    // 0 (NULL) when the heap is full, like C
+    fn malloc(&mut self, size: usize) -> i64 {
+        if self.heap.wants_collection(size) {
+            self.collect_garbage();
+        }
+        let mut addr = self.heap.alloc(&mut self.memory, size);
+        if addr.is_none() && self.heap.collect {
+            self.collect_garbage();
+            addr = self.heap.alloc(&mut self.memory, size);
+        }
+        let Some(addr) = addr else { return 0 };
+
+        if let Some(checker) = &mut self.checker {
+            checker.allocated(addr, size, self.memory.len());
+        }
+        addr as i64
+    }
+
+    fn free(&mut self, ptr: i64) {
+        if let Some(Err(e)) = self.checker.as_mut().map(|checker| checker.free(ptr as usize)) {
+            self.memory_error(&e);
+        }
+        if let Err(e) = self.heap.free(ptr as usize) {
+            self.memory_error(&e);
+        }
+    }
+
+    // the roots are every register in use, the data segment and the stack frames.
+    // rodata only holds characters so it's skipped
+    fn collect_garbage(&mut self) {
+        let frame = self.frames.last().unwrap();
+        let registers_end = frame.base + self.functions[frame.function_idx].max_registers as usize + 1;
+        let words = |range: Range<usize>| {
+            self.memory[range].chunks_exact(8).map(|word| i64::from_le_bytes(word.try_into().unwrap()))
+        };
+
+        let registers = self.stack[..registers_end].iter().copied();
+        let data = words(NULL_GUARD..self.rodata.start);
+        let frames = words(self.rodata.end.next_multiple_of(8)..self.mem_sp);
+        self.heap.collect(&self.memory, registers.chain(data).chain(frames));
+
+        if let Some(checker) = &mut self.checker {
+            checker.collected(|addr| self.heap.block_size(addr).is_some());
+        }
+    }
+
+    // checked mode, runs before each instruction. checks the memory it touches and keeps track
+    // of which object each register points into
+    fn check_instruction(&mut self, instr: &Instruction, base: usize) -> Result<(), String> {
+        let frame_addr = self.frames.last().unwrap().frame_addr;
+        let checker = self.checker.as_mut().unwrap();
+        let reg = |r: u16| base + r as usize;
+
+        // which register the instruction writes and what it points into afterwards
+        let result = match *instr {
+            Instruction::ABx { opcode: OpCode::LOADK | OpCode::CLOSURE, a, .. } => Some((a, None)),
+            Instruction::ABx { opcode: OpCode::ADDR, a, bx } => Some((a, checker.local_at(frame_addr + bx as usize))),
+            Instruction::ABx { opcode: OpCode::GADDR, a, bx } => Some((a, checker.static_at(NULL_GUARD + bx as usize))),
+            Instruction::ABx { opcode: OpCode::SADDR, a, bx } => Some((a, checker.static_at(self.rodata.start + bx as usize))),
+            Instruction::ABx { .. } | Instruction::AsBx { .. } => None,
+
+            Instruction::ABC { opcode, a, b, c } => match opcode {
+                OpCode::MOV => Some((a, checker.register(reg(b)))),
+                OpCode::ADD | OpCode::SUB => Some((a, checker.register(reg(b)).or(checker.register(reg(c))))),
+                OpCode::LOAD => {
+                    let len = Width::from_code(c).expect("bad LOAD width").bytes();
+                    let addr = self.stack[reg(b)];
+                    checker.check(addr, len, checker.register(reg(b)), Access::Read)?;
+                    Some((a, checker.loaded(addr as usize, len)))
+                }
+                OpCode::STORE => {
+                    let len = Width::from_code(c).expect("bad STORE width").bytes();
+                    let addr = self.stack[reg(a as u16)];
+                    checker.check(addr, len, checker.register(reg(a as u16)), Access::Write)?;
+                    checker.stored(addr as usize, len, checker.register(reg(b)));
+                    None
+                }
+                OpCode::COPY => {
+                    let len = self.stack[reg(c)] as usize;
+                    let (dest, src) = (self.stack[reg(a as u16)], self.stack[reg(b)]);
+                    checker.check(src, len, checker.register(reg(b)), Access::Copy)?;
+                    checker.check(dest, len, checker.register(reg(a as u16)), Access::Write)?;
+                    checker.copied(dest as usize, src as usize, len);
+                    None
+                }
+                OpCode::TEST | OpCode::CALL | OpCode::RETURN => None,
+                _ => Some((a, None)),
+            },
+        };
+
+        if let Some((a, object)) = result {
+            checker.set_register(base + a as usize, object);
+        }
+        Ok(())
+    }
+
+    // the function a CALL's ref names, a function pointer can hold anything so it's checked first
+    fn callee(&self, func_ref: i64, args: usize) -> usize {
+        if func_ref == 0 {
+            self.runtime_error("call through a null function pointer");
+        }
+        let Some(func) = usize::try_from(func_ref - 1).ok().and_then(|idx| self.functions.get(idx)) else {
+            self.runtime_error(&format!("call through an invalid function pointer {}", func_ref));
+        };
+        if func.arity as usize != args {
+            self.runtime_error(&format!("call to '{}' with {} args, it takes {}", func.name, args, func.arity));
+        }
+        func_ref as usize - 1
+    }
+
+    fn memory_error(&self, msg: &str) -> ! {
+        self.runtime_error(&format!("memory error: {}", msg));
+    }
+
+    // stops the program with the call stack, like a sanitizer would. every fault in the
+    // program being run ends up here, a panic is only for a bug in the vm itself
+    fn runtime_error(&self, msg: &str) -> ! {
+        let trace: Vec<String> = self.frames.iter().rev().enumerate()
+            .map(|(i, frame)| format!("    #{} pc {} in {}", i, frame.pc.saturating_sub(1), self.functions[frame.function_idx].name))
+            .collect();
+        eprintln!("runtime error: {}\n{}", msg, trace.join("\n"));
+        process::exit(1);
+    }
+
+    // read a register as the f64 it holds
+    fn float(&self, slot: usize) -> f64 {
+        f64::from_bits(self.stack[slot] as u64)
+    }
+
+    pub fn run(&mut self) -> i64 {
+        let main_idx = *self.function_map.get("main").expect("No main function found");
+        self.push_frame(main_idx, 0);
 
         loop {
             let frame = self.frames.last().unwrap();
@@ -121,157 +462,308 @@ impl VM {
             let pc = frame.pc;
             let base = frame.base;
 
-            let func = &self.functions[func_idx];
-            let instr = &func.instructions[pc];
+            let word = self.functions[func_idx].instructions[pc];
 
             self.frames.last_mut().unwrap().pc += 1;
 
-            match instr {
-                Instruction::ABx { opcode, a, bx } => {
-                    match opcode {
-                        OpCode::LOADK => {
-                            let constant = self.functions[func_idx].constants[*bx as usize];
-                            self.stack[base + *a as usize] = constant;
-                        }
-                        OpCode::CLOSURE => {
-                            self.stack[base + *a as usize] = *bx as i64;
-                        }
-                        _ => panic!("Unknown ABx opcode"),
+            if self.checker.is_some()
+                && let Some(instr) = Instruction::decode(word)
+                && let Err(e) = self.check_instruction(&instr, base) {
+                self.memory_error(&e);
+            }
+
+            // every operand is pulled out up front, an opcode just ignores the ones it doesn't have.
+            // ra, rb and rc are the stack slots when the operand is a register
+            let (a, b, c) = (arg_a(word), arg_b(word), arg_c(word));
+            let (ra, rb, rc) = (base + a, base + b, base + c);
+
+            match op(word) {
+                OpCode::LOADK => {
+                    let constant = self.functions[func_idx].constants[arg_bx(word)];
+                    self.stack[ra] = constant.raw();
+                }
+                OpCode::CLOSURE => {
+                    self.stack[ra] = arg_bx(word) as i64 + 1;
+                }
+                OpCode::ADDR => {
+                    let frame_addr = self.frames.last().unwrap().frame_addr;
+                    self.stack[ra] = (frame_addr + arg_bx(word)) as i64;
+                }
+                OpCode::GADDR => {
+                    self.stack[ra] = (NULL_GUARD + arg_bx(word)) as i64;
+                }
+                OpCode::SADDR => {
+                    self.stack[ra] = (self.rodata.start + arg_bx(word)) as i64;
+                }
+                OpCode::JMPTAB => {
+                    // the default JMP is next, entry i is i + 1 past it
+                    let index = self.stack[ra];
+                    if index >= 0 && index < arg_bx(word) as i64 {
+                        self.frames.last_mut().unwrap().pc += index as usize + 1;
                     }
                 }
 
-                Instruction::ABC { opcode, a, b, c } => {
-                    match opcode {
-                        OpCode::ADD => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] + self.stack[base + *c as usize];
-                        }
-                        OpCode::MOV => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize];
-                        }
-                        OpCode::RETURN => {
-                            let return_val = if *b == 2 {
-                                self.stack[base + *a as usize]
-                            } else {
-                                0
-                            };
-
-                            self.frames.pop();
-
-                            if self.frames.is_empty() {
-                                return return_val;
-                            }
-
-                            let caller = self.frames.last().unwrap();
-                            let caller_func = &self.functions[caller.function_idx];
-                            let call_instr = &caller_func.instructions[caller.pc - 1];
-
-                            if let Instruction::ABC { a: call_a, .. } = call_instr {
-                                self.stack[caller.base + *call_a as usize] = return_val;
-                            }
-                        }
-
-                        OpCode::CALL => {
-                            let func_idx = self.stack[base + *a as usize] as usize;
-                            let new_base = base + *a as usize + 1;
-                            self.frames.push(CallFrame {
-                                function_idx: func_idx,
-                                pc: 0,
-                                base: new_base,
-                            });
-                        }
-
-                        OpCode::SUB => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] - self.stack[base + *c as usize];
-                        }
-
-                        OpCode::MUL => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] * self.stack[base + *c as usize];
-                        }
-
-                        OpCode::DIV => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] / self.stack[base + *c as usize];
-                        }
-
-                        OpCode::MOD => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] % self.stack[base + *c as usize];
-                        }
-
-                        OpCode::EQ => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] == self.stack[base + *c as usize]) as i64;
-                        }
-
-                        OpCode::NE => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] != self.stack[base + *c as usize]) as i64;
-                        }
-
-                        OpCode::LT => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] < self.stack[base + *c as usize]) as i64;
-                        }
-                        
-                        OpCode::LE => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] <= self.stack[base + *c as usize]) as i64;
-                        }
-
-                        OpCode::GT => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] > self.stack[base + *c as usize]) as i64;
-                        }
-
-                        OpCode::GE => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] >= self.stack[base + *c as usize]) as i64;
-                        }
-
-                        OpCode::BAND => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] & self.stack[base + *c as usize];
-                        }
-                        
-                        OpCode::BOR => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] | self.stack[base + *c as usize];
-                        }
-
-                        OpCode::BXOR => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] ^ self.stack[base + *c as usize];
-                        }
-
-                        OpCode::SHL => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] << self.stack[base + *c as usize];
-                        }
-
-                        OpCode::SHR => {
-                            self.stack[base + *a as usize] = self.stack[base + *b as usize] >> self.stack[base + *c as usize];
-                        }
-
-                        OpCode::UNM => {
-                            self.stack[base + *a as usize] = -self.stack[base + *b as usize];
-                        }
-
-                        OpCode::NOT => {
-                            self.stack[base + *a as usize] = (self.stack[base + *b as usize] == 0) as i64;
-                        }
-
-                        OpCode::BNOT => {
-                            self.stack[base + *a as usize] = !self.stack[base + *b as usize];
-                        }
-
-                        OpCode::TEST => {
-                            if self.stack[base + *a as usize] != 0 {
-                                self.frames.last_mut().unwrap().pc += 1;
-                            }
-                        }
-
-                        _ => panic!("unknown iABC"),
+                OpCode::ADD => {
+                    self.stack[ra] = self.stack[rb].wrapping_add(self.stack[rc]);
+                }
+                OpCode::MOV => {
+                    self.stack[ra] = self.stack[rb];
+                }
+                OpCode::RETURN => {
+                    let return_val = if b == 2 {
+                        self.stack[ra]
+                    } else {
+                        0
+                    };
+
+                    let frame = self.frames.pop().unwrap();
+                    self.mem_sp = frame.frame_addr;
+                    if let Some(checker) = &mut self.checker {
+                        checker.pop_frame();
+                    }
+
+                    if self.frames.is_empty() {
+                        return return_val;
+                    }
+
+                    // the result goes where the caller's CALL had the function ref
+                    let caller = self.frames.last().unwrap();
+                    let call_word = self.functions[caller.function_idx].instructions[caller.pc - 1];
+                    let result_slot = caller.base + arg_a(call_word);
+                    self.stack[result_slot] = return_val;
+
+                    // a returned pointer still points where it did
+                    if let Some(checker) = &mut self.checker {
+                        let object = if b == 2 { checker.register(ra) } else { None };
+                        checker.set_register(result_slot, object);
                     }
                 }
 
-                Instruction::AsBx { opcode, offset } => {
-                    match opcode {
-                        OpCode::JMP => {
-                            let current_pc = self.frames.last().unwrap().pc as i32;
-                            self.frames.last_mut().unwrap().pc = (current_pc + offset) as usize;
-                        }
-                        _ => panic!("Unknown AsBx opcode"),
+                OpCode::CALL => {
+                    let func_idx = self.callee(self.stack[ra], b - 1);
+                    self.push_frame(func_idx, ra + 1);
+                }
+
+                // builtins run right here, no frame
+                OpCode::CALLN => {
+                    let builtin = Builtin::from_code(c as u16).expect("bad builtin code");
+                    let args = self.stack[ra + 1..ra + b].to_vec();
+                    self.stack[ra] = self.call_builtin(builtin, &args);
+                    if let Some(checker) = &mut self.checker {
+                        checker.set_register(ra, checker.block(self.stack[ra] as usize));
                     }
                 }
+
+                OpCode::SUB => {
+                    self.stack[ra] = self.stack[rb].wrapping_sub(self.stack[rc]);
+                }
+
+                OpCode::MUL => {
+                    self.stack[ra] = self.stack[rb].wrapping_mul(self.stack[rc]);
+                }
+
+                OpCode::DIV => {
+                    self.stack[ra] = self.stack[rb].wrapping_div(self.stack[rc]);
+                }
+
+                OpCode::MOD => {
+                    self.stack[ra] = self.stack[rb].wrapping_rem(self.stack[rc]);
+                }
+
+                OpCode::EQ => {
+                    self.stack[ra] = (self.stack[rb] == self.stack[rc]) as i64;
+                }
+
+                OpCode::NE => {
+                    self.stack[ra] = (self.stack[rb] != self.stack[rc]) as i64;
+                }
+
+                OpCode::LT => {
+                    self.stack[ra] = (self.stack[rb] < self.stack[rc]) as i64;
+                }
+
+                OpCode::LE => {
+                    self.stack[ra] = (self.stack[rb] <= self.stack[rc]) as i64;
+                }
+
+                OpCode::GT => {
+                    self.stack[ra] = (self.stack[rb] > self.stack[rc]) as i64;
+                }
+
+                OpCode::GE => {
+                    self.stack[ra] = (self.stack[rb] >= self.stack[rc]) as i64;
+                }
+
+                OpCode::BAND => {
+                    self.stack[ra] = self.stack[rb] & self.stack[rc];
+                }
+
+                OpCode::BOR => {
+                    self.stack[ra] = self.stack[rb] | self.stack[rc];
+                }
+
+                OpCode::BXOR => {
+                    self.stack[ra] = self.stack[rb] ^ self.stack[rc];
+                }
+
+                OpCode::SHL => {
+                    self.stack[ra] = self.stack[rb].wrapping_shl(self.stack[rc] as u32);
+                }
+
+                OpCode::SHR => {
+                    self.stack[ra] = self.stack[rb].wrapping_shr(self.stack[rc] as u32);
+                }
+
+                OpCode::UNM => {
+                    self.stack[ra] = self.stack[rb].wrapping_neg();
+                }
+
+                OpCode::NOT => {
+                    self.stack[ra] = (self.stack[rb] == 0) as i64;
+                }
+
+                OpCode::BNOT => {
+                    self.stack[ra] = !self.stack[rb];
+                }
+
+                OpCode::LOAD => {
+                    let width = Width::from_code(c as u16).expect("bad LOAD width");
+                    self.stack[ra] = self.load(self.stack[rb], width);
+                }
+
+                OpCode::STORE => {
+                    let width = Width::from_code(c as u16).expect("bad STORE width");
+                    self.store(self.stack[ra], self.stack[rb], width);
+                }
+
+                OpCode::COPY => {
+                    let len = self.stack[rc] as usize;
+                    let dest = self.writable_range(self.stack[ra], len);
+                    let src = self.mem_range(self.stack[rb], len);
+                    self.memory.copy_within(src, dest.start);
+                }
+
+                OpCode::DIVU => {
+                    self.stack[ra] = (unsigned(self.stack[rb]) / unsigned(self.stack[rc])) as i64;
+                }
+
+                OpCode::MODU => {
+                    self.stack[ra] = (unsigned(self.stack[rb]) % unsigned(self.stack[rc])) as i64;
+                }
+
+                OpCode::SHRU => {
+                    self.stack[ra] = unsigned(self.stack[rb]).wrapping_shr(self.stack[rc] as u32) as i64;
+                }
+
+                OpCode::LTU => {
+                    self.stack[ra] = (unsigned(self.stack[rb]) < unsigned(self.stack[rc])) as i64;
+                }
+
+                OpCode::LEU => {
+                    self.stack[ra] = (unsigned(self.stack[rb]) <= unsigned(self.stack[rc])) as i64;
+                }
+
+                OpCode::GTU => {
+                    self.stack[ra] = (unsigned(self.stack[rb]) > unsigned(self.stack[rc])) as i64;
+                }
+
+                OpCode::GEU => {
+                    self.stack[ra] = (unsigned(self.stack[rb]) >= unsigned(self.stack[rc])) as i64;
+                }
+
+                OpCode::FADD => {
+                    self.stack[ra] = float_bits(self.float(rb) + self.float(rc));
+                }
+
+                OpCode::FSUB => {
+                    self.stack[ra] = float_bits(self.float(rb) - self.float(rc));
+                }
+
+                OpCode::FMUL => {
+                    self.stack[ra] = float_bits(self.float(rb) * self.float(rc));
+                }
+
+                OpCode::FDIV => {
+                    self.stack[ra] = float_bits(self.float(rb) / self.float(rc));
+                }
+
+                OpCode::FUNM => {
+                    self.stack[ra] = float_bits(-self.float(rb));
+                }
+
+                OpCode::FEQ => {
+                    self.stack[ra] = (self.float(rb) == self.float(rc)) as i64;
+                }
+
+                OpCode::FNE => {
+                    self.stack[ra] = (self.float(rb) != self.float(rc)) as i64;
+                }
+
+                OpCode::FLT => {
+                    self.stack[ra] = (self.float(rb) < self.float(rc)) as i64;
+                }
+
+                OpCode::FLE => {
+                    self.stack[ra] = (self.float(rb) <= self.float(rc)) as i64;
+                }
+
+                OpCode::FGT => {
+                    self.stack[ra] = (self.float(rb) > self.float(rc)) as i64;
+                }
+
+                OpCode::FGE => {
+                    self.stack[ra] = (self.float(rb) >= self.float(rc)) as i64;
+                }
+
+                OpCode::I2F => {
+                    self.stack[ra] = float_bits(self.stack[rb] as f64);
+                }
+
+                OpCode::U2F => {
+                    self.stack[ra] = float_bits(self.stack[rb] as u64 as f64);
+                }
+
+                OpCode::F2I => {
+                    self.stack[ra] = self.float(rb) as i64;
+                }
+
+                OpCode::F2U => {
+                    self.stack[ra] = self.float(rb) as u64 as i64;
+                }
+
+                OpCode::F2S => {
+                    self.stack[ra] = float_bits(self.float(rb) as f32 as f64);
+                }
+
+                OpCode::SEXT => {
+                    let shift = 64 - c as u32;
+                    self.stack[ra] = (self.stack[rb] << shift) >> shift;
+                }
+
+                OpCode::ZEXT => {
+                    let mask = (1i64 << c) - 1;
+                    self.stack[ra] = self.stack[rb] & mask;
+                }
+
+                OpCode::TEST => {
+                    if self.stack[ra] != 0 {
+                        self.frames.last_mut().unwrap().pc += 1;
+                    }
+                }
+
+                OpCode::JMP => {
+                    let current_pc = self.frames.last().unwrap().pc as i32;
+                    self.frames.last_mut().unwrap().pc = (current_pc + arg_sbx(word)) as usize;
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn float_bits(val: f64) -> i64 {
+    val.to_bits() as i64
+}
+
+fn unsigned(val: i64) -> u64 {
+    val as u64
+}
@@ -0,0 +1,288 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn cvm(args: &[&str]) -> (bool, String) {
+    Command::new("cargo")
+        .args(["build", "--quiet"])
+        .status()
+        .unwrap();
+
+    let output = Command::new("./target/debug/cvm")
+        .args(args)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    (output.status.success(), stdout + &stderr)
+}
+
+// writes the source out and compiles it to a .cvmb, gives back its path
+fn compile(code: &str) -> String {
+    let id = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let source = format!("/tmp/test_bytecode_{}.c", id);
+    let output = format!("/tmp/test_bytecode_{}.cvmb", id);
+    std::fs::write(&source, code).unwrap();
+
+    let (success, log) = cvm(&["compile", "-o", &output, &source]);
+    assert!(success, "compile failed: {}", log);
+    output
+}
+
+const PROGRAM: &str = r#"
+struct Pair { int a; double b; };
+
+int counts[4] = {1, 2, 3, 4};
+char *names[] = {"zero", "one"};
+
+int twice(int x) { return x * 2; }
+int (*op)(int) = twice;
+
+int classify(int n) {
+    switch (n) {
+        case 0: return 10;
+        case 1: return 20;
+        case 2: return 30;
+        default: return -1;
+    }
+}
+
+int main(void) {
+    struct Pair p;
+    p.a = 3;
+    p.b = 1.5;
+    int total = 0;
+    for (int i = 0; i < 4; i++) {
+        total += counts[i];
+    }
+    total += classify(2);
+    total += op(p.a);
+    total += (int)(p.b * 4.0);
+    total += names[1][0];
+    return total;
+}
+"#;
+
+#[test]
+fn test_compiled_file_runs_like_the_source() {
+    let source = "/tmp/test_bytecode_direct.c";
+    std::fs::write(source, PROGRAM).unwrap();
+    let (success, direct) = cvm(&[source]);
+    assert!(success, "output: {}", direct);
+    // 10 + 30 + 6 + 6 + 'o'
+    assert!(direct.contains("Program returned: 163"), "output: {}", direct);
+
+    let file = compile(PROGRAM);
+    let (success, output) = cvm(&["run", &file]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 163"), "output: {}", output);
+    assert!(!output.contains("======== AST ========"), "output: {}", output);
+}
+
+#[test]
+fn test_compile_is_deterministic() {
+    let source = "/tmp/test_bytecode_same.c";
+    std::fs::write(source, PROGRAM).unwrap();
+    let mut outputs = vec![];
+    for output in ["/tmp/test_bytecode_same_1.cvmb", "/tmp/test_bytecode_same_2.cvmb"] {
+        let (success, log) = cvm(&["compile", "-o", output, source]);
+        assert!(success, "output: {}", log);
+        outputs.push(std::fs::read(output).unwrap());
+    }
+    assert_eq!(&outputs[0][..4], b"CVMB");
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+#[test]
+fn test_checked_run_of_compiled_file() {
+    let file = compile("int table[4];\nint main(void) { int i = 4; return table[i]; }");
+    let (success, output) = cvm(&["run", "--checked", &file]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("out of bounds read of 4 bytes at offset 16 of global 'table'"), "output: {}", output);
+}
+
+#[test]
+fn test_compile_errors_write_nothing() {
+    let source = "/tmp/test_bytecode_bad.c";
+    let output = "/tmp/test_bytecode_bad.cvmb";
+    std::fs::write(source, "int main(void) { return y; }").unwrap();
+    let _ = std::fs::remove_file(output);
+
+    let (success, log) = cvm(&["compile", "-o", output, source]);
+    assert!(!success, "output: {}", log);
+    assert!(log.contains("Undeclared"), "output: {}", log);
+    assert!(!std::path::Path::new(output).exists());
+}
+
+// ============ REJECTED FILES ============
+
+fn run_modified(modify: impl Fn(&mut Vec<u8>)) -> (bool, String) {
+    let file = compile("int main(void) { return 7; }");
+    let mut bytes = std::fs::read(&file).unwrap();
+    modify(&mut bytes);
+    std::fs::write(&file, bytes).unwrap();
+    cvm(&["run", &file])
+}
+
+#[test]
+fn test_unmodified_file_runs() {
+    let (success, output) = run_modified(|_| {});
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 7"), "output: {}", output);
+}
+
+#[test]
+fn test_bad_magic() {
+    let (success, output) = run_modified(|bytes| bytes[0] = b'X');
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("not a cvm bytecode file"), "output: {}", output);
+}
+
+#[test]
+fn test_unsupported_version() {
+    let (success, output) = run_modified(|bytes| bytes[4] = 99);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("bytecode version 99 is not supported"), "output: {}", output);
+}
+
+#[test]
+fn test_corrupted_body() {
+    let (success, output) = run_modified(|bytes| {
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+    });
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("checksum mismatch"), "output: {}", output);
+}
+
+#[test]
+fn test_truncated_file() {
+    let (success, output) = run_modified(|bytes| bytes.truncate(bytes.len() - 3));
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("the header says"), "output: {}", output);
+}
+
+#[test]
+fn test_run_without_main() {
+    let file = compile("int helper(void) { return 1; }");
+    let (success, output) = cvm(&["run", &file]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("no main function to run"), "output: {}", output);
+}
+
+// ============ REJECTED PROGRAMS ============
+// files with a good checksum whose contents would take the vm somewhere they don't describe
+
+// opcode numbers, OpCode's declaration order
+const RETURN: u32 = 12;
+const LOADK: u32 = 51;
+const CLOSURE: u32 = 53;
+const JMP: u32 = 58;
+
+// crc-32 the same way the loader does, so a changed body still gets past the checksum
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// fixes up the header's length and checksum after the body changed
+fn seal(bytes: &mut [u8]) {
+    let length = bytes.len() as u32 - 16;
+    let checksum = crc32(&bytes[16..]);
+    bytes[8..12].copy_from_slice(&length.to_le_bytes());
+    bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> usize {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize
+}
+
+// return 7 compiles to LOADK r0, K0 then two RETURNs, this swaps one of them for word
+fn run_with_instruction(pc: usize, word: u32) -> (bool, String) {
+    run_modified(|bytes| {
+        // header, the file count and name, the function count, then main's name,
+        // max_registers, arity, frame_size, file and the instruction count
+        let main = 16 + 4 + 4 + u32_at(bytes, 20) + 4;
+        let code = main + 4 + u32_at(bytes, main) + 1 + 1 + 4 + 4 + 4;
+        assert_eq!(u32_at(bytes, code) as u32 & 0x3f, LOADK);
+        let pos = code + pc * 4;
+        bytes[pos..pos + 4].copy_from_slice(&word.to_le_bytes());
+        seal(bytes);
+    })
+}
+
+#[test]
+fn test_sealed_file_runs() {
+    let (success, output) = run_with_instruction(1, RETURN | 2 << 14);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 7"), "output: {}", output);
+}
+
+#[test]
+fn test_jump_outside_the_function() {
+    let (success, output) = run_with_instruction(1, JMP | 100 << 14);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("jump to 102, outside the function's 3 instructions at pc 1 in 'main'"), "output: {}", output);
+
+    let (success, output) = run_with_instruction(0, JMP | ((-2i32 as u32) & 0x3ffff) << 14);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("jump to -1"), "output: {}", output);
+}
+
+#[test]
+fn test_missing_constant() {
+    let (success, output) = run_with_instruction(0, LOADK | 5 << 14);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("constant 5 named, there are only 1 at pc 0 in 'main'"), "output: {}", output);
+}
+
+#[test]
+fn test_missing_function() {
+    let (success, output) = run_with_instruction(0, CLOSURE | 9 << 14);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("function 9 named, there are only 1 at pc 0 in 'main'"), "output: {}", output);
+}
+
+#[test]
+fn test_register_past_the_function() {
+    let (success, output) = run_with_instruction(0, LOADK | 200 << 6);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("register r200 past the function's last register r0 at pc 0 in 'main'"), "output: {}", output);
+
+    let (success, output) = run_with_instruction(1, RETURN | 7 << 6 | 2 << 14);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("register r7"), "output: {}", output);
+}
+
+#[test]
+fn test_data_past_the_heap() {
+    // the file ends with the data and rodata lengths and bytes, then two empty slot lists
+    let (success, output) = run_modified(|bytes| {
+        let tail = bytes.len() - 16;
+        assert_eq!(&bytes[tail..], &[0u8; 16]);
+        bytes.truncate(tail + 4);
+        bytes.extend((1u32 << 20).to_le_bytes());
+        bytes.extend(vec![0u8; 1 << 20]);
+        bytes.extend([0u8; 8]);
+        seal(bytes);
+    });
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("data and rodata end at address 1048584, past the heap at 1048576"), "output: {}", output);
+    assert!(!output.contains("panicked"), "output: {}", output);
+
+    // compiling it is stopped the same way
+    let source = "/tmp/test_bytecode_huge.c";
+    std::fs::write(source, "char big[2000000];\nint main(void) { return 7; }").unwrap();
+    let (success, output) = cvm(&[source]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("error: data and rodata end at address 2000008"), "output: {}", output);
+    assert!(!output.contains("panicked"), "output: {}", output);
+}
@@ -4,6 +4,10 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 fn run_compiler(code: &str) -> (bool, String) {
+    run_compiler_with_flags(code, &[])
+}
+
+fn run_compiler_with_flags(code: &str, flags: &[&str]) -> (bool, String) {
     let id = COUNTER.fetch_add(1, Ordering::SeqCst);
     let path = format!("/tmp/test_codegen_{}.c", id);
     
@@ -15,14 +19,17 @@ fn run_compiler(code: &str) -> (bool, String) {
         .unwrap();
     
     let output = Command::new("./target/debug/cvm")
+        .args(flags)
         .arg(&path)
         .output()
         .unwrap();
     
     let _ = std::fs::remove_file(&path);
     
+    // runtime errors (checked mode's reports) end up on stderr
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    (output.status.success(), stdout)
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    (output.status.success(), stdout + &stderr)
 }
 
 #[test]
@@ -68,4 +75,1320 @@ int main() {
     assert!(success);
     assert!(output.contains("ADD"));
     assert!(output.contains("MUL"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_goto_backward_loop() {
+    let code = r#"
+int main() {
+    int total = 0;
+    int i = 0;
+top:
+    if (i >= 5) goto done;
+    total = total + i;
+    i = i + 1;
+    goto top;
+done:
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 10"), "output: {}", output);
+}
+
+#[test]
+fn test_goto_cleanup_block() {
+    let code = r#"
+int work(int fail) {
+    int status = 1;
+    if (fail) goto cleanup;
+    status = 0;
+cleanup:
+    return status;
+}
+
+int main() {
+    return work(1) * 10 + work(0);
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 10"), "output: {}", output);
+}
+
+#[test]
+fn test_switch_dense_uses_jump_table() {
+    let code = r#"
+int main() {
+    int x = 2;
+    int r = 0;
+    switch (x) {
+        case 1: r = 10; break;
+        case 2: r = 20;
+        case 3: r = r + 3; break;
+        case 4: r = 40; break;
+        default: r = -1;
+    }
+    return r;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("JMPTAB"), "output: {}", output);
+    assert!(output.contains("Program returned: 23"), "output: {}", output);
+}
+
+#[test]
+fn test_switch_out_of_range_goes_to_default() {
+    let code = r#"
+int main() {
+    int x = 9;
+    int r = 0;
+    switch (x) {
+        case 0: r = 1; break;
+        case 1: r = 2; break;
+        case 2: r = 3; break;
+        case 3: r = 4; break;
+        default: r = 7;
+    }
+    return r;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 7"), "output: {}", output);
+}
+
+#[test]
+fn test_switch_sparse_compare_chain() {
+    let code = r#"
+int main() {
+    int x = 1000;
+    int r = 0;
+    switch (x) {
+        case -5: r = 1; break;
+        case 1000: r = 2; break;
+        case 70000: r = 3; break;
+    }
+    return r;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("JMPTAB"), "output: {}", output);
+    assert!(output.contains("Program returned: 2"), "output: {}", output);
+}
+
+#[test]
+fn test_switch_in_loop_break_and_continue() {
+    let code = r#"
+int main() {
+    int i = 0;
+    int total = 0;
+    while (i < 6) {
+        i = i + 1;
+        switch (i) {
+            case 2: continue;
+            case 4: break;
+            default: total = total + i;
+        }
+        total = total + 100;
+    }
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 515"), "output: {}", output);
+}
+
+#[test]
+fn test_do_while_runs_body_once() {
+    let code = r#"
+int main() {
+    int n = 0;
+    do {
+        n = n + 1;
+    } while (n > 5);
+    return n;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 1"), "output: {}", output);
+}
+
+#[test]
+fn test_do_while_break_and_continue() {
+    let code = r#"
+int main() {
+    int i = 0;
+    int total = 0;
+    do {
+        i = i + 1;
+        if (i == 3) continue;
+        if (i == 6) break;
+        total = total + i;
+    } while (i < 10);
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 12"), "output: {}", output);
+}
+
+#[test]
+fn test_for_infinite_with_break() {
+    let code = r#"
+int main() {
+    int n = 0;
+    for (;;) {
+        n = n + 1;
+        if (n == 7) break;
+    }
+    return n;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    // only the if gets a TEST, the loop itself has none
+    assert_eq!(output.matches(": TEST").count(), 1, "output: {}", output);
+    assert!(output.contains("Program returned: 7"), "output: {}", output);
+}
+
+#[test]
+fn test_for_missing_clauses() {
+    let code = r#"
+int main() {
+    int i = 0;
+    int total = 0;
+    for (; i < 4;) {
+        total = total + i;
+        i = i + 1;
+    }
+    for (int j = 0;; j++) {
+        if (j > 2) break;
+        total = total + 10;
+    }
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 36"), "output: {}", output);
+}
+
+#[test]
+fn test_for_continue_runs_increment() {
+    let code = r#"
+int main() {
+    int total = 0;
+    for (int i = 0; i < 5; i++) {
+        if (i == 2) continue;
+        total = total + i;
+    }
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 8"), "output: {}", output);
+}
+
+#[test]
+fn test_logical_ops_short_circuit() {
+    let code = r#"
+int main() {
+    int calls = 0;
+    int x = 0;
+    if (x != 0 && (calls = calls + 1)) x = 5;
+    if (x == 0 || (calls = calls + 10)) x = 1;
+    return calls;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 0"), "output: {}", output);
+}
+
+#[test]
+fn test_logical_ops_value_is_zero_or_one() {
+    let code = r#"
+int main() {
+    int a = 7;
+    int b = 3;
+    int both = a && b;
+    int either = 0 || b;
+    int neither = 0 || 0;
+    return both * 100 + either * 10 + neither;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 110"), "output: {}", output);
+}
+
+#[test]
+fn test_logical_ops_in_loop_conditions() {
+    let code = r#"
+int main() {
+    int i = 0;
+    while (i < 10 && !(i == 5 || i == 7)) i++;
+    do { i = i + 1; } while (i < 20 && i != 12);
+    return i;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 12"), "output: {}", output);
+}
+
+#[test]
+fn test_float_constants_and_ops() {
+    let code = r#"
+int main() {
+    double a = 1.5;
+    double b = a * 4.0 - 0.5;
+    if (b == 5.5) return 1;
+    return 0;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("K0: 1.5"), "output: {}", output);
+    assert!(output.contains("FMUL"), "output: {}", output);
+    assert!(output.contains("FSUB"), "output: {}", output);
+    assert!(output.contains("FEQ"), "output: {}", output);
+    assert!(output.contains("Program returned: 1"), "output: {}", output);
+}
+
+#[test]
+fn test_float_function_and_comparisons() {
+    let code = r#"
+double half(double x) {
+    return x / 2.0;
+}
+
+int main() {
+    double c = half(-7.0);
+    int r = 0;
+    if (c < -3.0 && c > -4.0) r = r + 1;
+    if (-0.0) r = r + 10;
+    if (c) r = r + 100;
+    return r;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("FDIV"), "output: {}", output);
+    assert!(output.contains("Program returned: 101"), "output: {}", output);
+}
+
+#[test]
+fn test_implicit_int_float_conversions() {
+    let code = r#"
+int truncate(int x) {
+    return x;
+}
+
+double average(int a, int b) {
+    return (a + b) / 2.0;
+}
+
+int main() {
+    double d = 7;
+    int i = d / 2;
+    int t = truncate(9.99);
+    int neg = -2.7;
+    return i * 100 + t * 10 + neg + average(3, 4) * 1000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("I2F"), "output: {}", output);
+    assert!(output.contains("F2I"), "output: {}", output);
+    assert!(output.contains("Program returned: 3888"), "output: {}", output);
+}
+
+#[test]
+fn test_narrowing_conversions() {
+    let code = r#"
+int main() {
+    char c = 300;
+    unsigned char u = -1;
+    short s = (short)70000;
+    return c + u * 100 + s * 100000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("SEXT"), "output: {}", output);
+    assert!(output.contains("ZEXT"), "output: {}", output);
+    assert!(output.contains("Program returned: 446425544"), "output: {}", output);
+}
+
+#[test]
+fn test_float_rounds_to_single_precision() {
+    let code = r#"
+int main() {
+    float f = 0.1;
+    double d = 0.1;
+    return f == d;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("F2S"), "output: {}", output);
+    assert!(output.contains("Program returned: 0"), "output: {}", output);
+}
+
+#[test]
+fn test_unsigned_int_hash_matches_gcc() {
+    let code = r#"
+unsigned int fnv(int a, int b, int c) {
+    unsigned int h = 2166136261;
+    h = h ^ a;
+    h = h * 16777619;
+    h = h ^ b;
+    h = h * 16777619;
+    h = h ^ c;
+    h = h * 16777619;
+    return h;
+}
+
+int main() {
+    unsigned int h = fnv(97, 98, 99);
+    return h % 100000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    // fnv-1a of "abc" is 440920331
+    assert!(output.contains("Program returned: 20331"), "output: {}", output);
+}
+
+#[test]
+fn test_signed_overflow_wraps_at_width() {
+    let code = r#"
+int main() {
+    int big = 2147483647;
+    big = big + 1;
+    char ch = 127;
+    ch++;
+    short s = -32768;
+    s = s - 1;
+    return (big == -2147483648) + (ch == -128) * 10 + (s == 32767) * 100;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 111"), "output: {}", output);
+}
+
+#[test]
+fn test_unsigned_compare_shift_and_divide() {
+    let code = r#"
+int main() {
+    unsigned int u = 0;
+    u = u - 1;
+    unsigned int sh = u >> 28;
+    unsigned long ul = 0;
+    ul = ul - 1;
+    unsigned long q = ul / 3;
+    return (u > 5) + sh * 10 + (ul > 1) * 1000 + (q == 6148914691236517205) * 10000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("GTU"), "output: {}", output);
+    assert!(output.contains("DIVU"), "output: {}", output);
+    assert!(output.contains("Program returned: 11151"), "output: {}", output);
+}
+
+#[test]
+fn test_store_through_pointer() {
+    let code = r#"
+int main() {
+    int x = 1;
+    int *p = &x;
+    *p = 3;
+    return x;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("ADDR"), "output: {}", output);
+    assert!(output.contains("STORE"), "output: {}", output);
+    assert!(output.contains("Program returned: 3"), "output: {}", output);
+}
+
+#[test]
+fn test_swap_through_pointers() {
+    let code = r#"
+void swap(int *a, int *b) {
+    int t = *a;
+    *a = *b;
+    *b = t;
+}
+
+int bump(int n) {
+    int *p = &n;
+    (*p)++;
+    ++*p;
+    return n;
+}
+
+int main() {
+    int x = 1;
+    int y = 40;
+    swap(&x, &y);
+    return x * 100 + y + bump(5) * 10000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 74001"), "output: {}", output);
+}
+
+#[test]
+fn test_memory_widths() {
+    let code = r#"
+int main() {
+    char c = 127;
+    char *cp = &c;
+    *cp = *cp + 1;
+    short s = 0;
+    short *sp = &s;
+    *sp = 70000;
+    unsigned char u = 0;
+    unsigned char *up = &u;
+    *up = 300;
+    return c + s + u;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    // -128 + 4464 + 44
+    assert!(output.contains("Program returned: 4380"), "output: {}", output);
+}
+
+#[test]
+fn test_local_array_index_and_initializer() {
+    let code = r#"
+int sum(int *xs, int n) {
+    int total = 0;
+    for (int i = 0; i < n; i++) {
+        total += xs[i];
+    }
+    return total;
+}
+
+int main() {
+    int a[5] = {1, 2, 3};
+    a[3] = 4;
+    int i = 4;
+    a[i] = a[0] + a[1];
+    return sum(a, 5);
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("LOAD"), "output: {}", output);
+    assert!(output.contains("Program returned: 13"), "output: {}", output);
+}
+
+#[test]
+fn test_array_zero_fill_and_nested() {
+    let code = r#"
+int main() {
+    int grid[2][3] = {{1, 2, 3}, {4}};
+    char s[] = {'h', 'i', 0};
+    return grid[1][0] * 100 + grid[1][2] * 10 + grid[0][2] + (s[1] == 'i') * 1000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 1403"), "output: {}", output);
+}
+
+#[test]
+fn test_pointer_arithmetic_scales() {
+    let code = r#"
+int main() {
+    long a[4] = {10, 20, 30, 40};
+    long *p = a;
+    p++;
+    p += 1;
+    long *end = a + 4;
+    long n = end - p;
+    short sh[3] = {1, 2, 3};
+    short *q = &sh[0];
+    ++q;
+    return *p + n * 100 + *q * 1000 + *(a + 3) * 10000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 402230"), "output: {}", output);
+}
+
+#[test]
+fn test_sizeof_arrays_and_types() {
+    let code = r#"
+int main() {
+    int a[10];
+    double d[] = {1.0, 2.0, 3.0};
+    char grid[4][5];
+    return sizeof(a) + sizeof(d) * 100 + sizeof(grid) * 10000 + sizeof(long) * 1000000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 8202440"), "output: {}", output);
+}
+
+#[test]
+fn test_global_arrays() {
+    let code = r#"
+int primes[6] = {2, 3, 5, 7, 11};
+double scale[] = {0.5, -1.5};
+
+int main() {
+    primes[5] = 13;
+    int total = 0;
+    for (int i = 0; i < 6; i++) {
+        total += primes[i];
+    }
+    return total + (scale[1] < 0) * 100 + sizeof(scale) * 1000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("GADDR"), "output: {}", output);
+    assert!(output.contains("Program returned: 16141"), "output: {}", output);
+}
+
+#[test]
+fn test_struct_layout_and_sizeof() {
+    let code = r#"
+struct Mixed { char a; int b; char c; };
+struct Packed { char a; char b; short c; };
+struct Wide { char a; double d; };
+union Either { int i; char bytes[6]; };
+
+int main() {
+    return sizeof(struct Mixed) + sizeof(struct Packed) * 100 + sizeof(struct Wide) * 1000
+        + sizeof(union Either) * 100000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 816412"), "output: {}", output);
+}
+
+#[test]
+fn test_struct_member_access() {
+    let code = r#"
+struct Point { int x; int y; };
+struct Rect { struct Point min; struct Point max; char tags[3]; };
+
+int main() {
+    struct Rect r = {{1, 2}, {5, 9}};
+    struct Point *p = &r.max;
+    p->x = p->x * 2;
+    r.tags[2] = 7;
+    r.min.y += 1;
+    return (r.max.x - r.min.x) * 100 + (r.max.y - r.min.y) * 10 + r.tags[2] + r.tags[0];
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 967"), "output: {}", output);
+}
+
+#[test]
+fn test_union_shares_storage() {
+    let code = r#"
+union Word { int value; unsigned char bytes[4]; };
+
+int main() {
+    union Word w;
+    w.value = 0x01020304;
+    w.bytes[0] = 9;
+    return w.value - 0x01020300;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 9"), "output: {}", output);
+}
+
+#[test]
+fn test_struct_assignment_copies() {
+    let code = r#"
+struct Pair { long a; long b; };
+
+int main() {
+    struct Pair p = {1, 2};
+    struct Pair q;
+    q = p;
+    q.a = 10;
+    return p.a + q.a * 10 + q.b * 100;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("COPY"), "output: {}", output);
+    assert!(output.contains("Program returned: 301"), "output: {}", output);
+}
+
+#[test]
+fn test_struct_params_and_returns() {
+    let code = r#"
+struct Point { int x; int y; };
+
+struct Point make(int x, int y) {
+    struct Point p = {x, y};
+    return p;
+}
+
+int sum(struct Point p) {
+    int total = p.x + p.y;
+    p.x = 100;
+    return total;
+}
+
+int manhattan(struct Point a, struct Point b) {
+    return (b.x - a.x) + (b.y - a.y);
+}
+
+int main() {
+    struct Point p = make(3, 4);
+    int s = sum(p);
+    return s + p.x * 10 + manhattan(make(1, 1), make(4, 5)) * 100 + make(8, 9).y * 1000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 9737"), "output: {}", output);
+}
+
+#[test]
+fn test_linked_structs_and_global_struct() {
+    let code = r#"
+struct Node { int value; struct Node *next; };
+struct Node tail = {30};
+
+int total(struct Node *n) {
+    int sum = 0;
+    while (n) {
+        sum += n->value;
+        n = n->next;
+    }
+    return sum;
+}
+
+int main() {
+    struct Node b = {20, &tail};
+    struct Node a = {10, &b};
+    struct Node list[2];
+    list[1].value = 5;
+    struct Node *p = list;
+    p++;
+    return total(&a) + p->value;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 65"), "output: {}", output);
+}
+
+#[test]
+fn test_global_scalars() {
+    let code = r#"
+int counter;
+double total = 1.5;
+
+void bump(int by) {
+    counter += by;
+    total = total * 2;
+}
+
+int main() {
+    bump(3);
+    bump(4);
+    return counter + (total == 6.0) * 100;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("GADDR"), "output: {}", output);
+    assert!(output.contains("Program returned: 107"), "output: {}", output);
+}
+
+#[test]
+fn test_constant_expression_initializers() {
+    let code = r#"
+int table[4] = {1, 2 * 3, 10 / 3, -(4 + 1)};
+int count = sizeof(table) / sizeof(int);
+char wrapped = 300;
+unsigned char all_ones = ~0;
+double half = 1 / 2.0;
+long big = (long)1 << 40;
+int *third = &table[2];
+int picked = 4 > 3 ? 7 : 8;
+
+int main() {
+    return *third + count * 10 + (wrapped == 44) * 100 + (all_ones == 255) * 1000
+        + (half == 0.5) * 10000 + (big == 1099511627776) * 100000 + picked * 1000000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 7111143"), "output: {}", output);
+}
+
+#[test]
+fn test_static_locals_persist() {
+    let code = r#"
+int next_id() {
+    static int last = 100;
+    last++;
+    return last;
+}
+
+int calls() {
+    static int count;
+    static int *counter = &count;
+    *counter += 1;
+    return count;
+}
+
+int main() {
+    next_id();
+    next_id();
+    calls();
+    calls();
+    return next_id() + calls() * 1000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 3103"), "output: {}", output);
+}
+
+#[test]
+fn test_string_literals() {
+    let code = r#"
+char *greeting = "hello";
+
+int length(char *s) {
+    int n = 0;
+    while (*s) {
+        s++;
+        n++;
+    }
+    return n;
+}
+
+int main() {
+    char *same = "hello";
+    return length(greeting) + length("hi there") * 10 + (same == greeting) * 100 + ("abc"[2] == 'c') * 1000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("SADDR"), "output: {}", output);
+    assert!(output.contains("Program returned: 1185"), "output: {}", output);
+}
+
+#[test]
+fn test_char_array_from_string() {
+    let code = r#"
+char global[] = "abc";
+char *names[2] = {"ann", "bo"};
+
+int main() {
+    char word[] = "xyz";
+    char padded[6] = "ab";
+    word[0] = 'W';
+    return sizeof(global) + sizeof(word) * 10 + (word[0] == 'W' && word[1] == 'y') * 100
+        + (padded[1] == 'b' && padded[2] == 0 && padded[5] == 0) * 1000 + (names[1][1] == 'o') * 10000;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("COPY"), "output: {}", output);
+    assert!(output.contains("Program returned: 11144"), "output: {}", output);
+}
+
+#[test]
+fn test_string_literal_is_a_char_array() {
+    let code = r#"
+long global_size = sizeof("hello");
+char *greeting = 1 ? "hey" : "hi";
+
+int length(char *s) {
+    int n = 0;
+    while (s[n]) n++;
+    return n;
+}
+
+int first(void *p) {
+    char *c = p;
+    return *c;
+}
+
+int main() {
+    char *t = "hello" + 1;
+    long local_size = sizeof("hello");
+    int total = local_size * 1000 + global_size * 100 + sizeof("a\n") * 10;
+    total += length(greeting);
+    total += first("z") == 'z';
+    total += *t == 'e';
+    return total;
+}
+"#;
+
+    // sizeof counts the nul, anywhere else the literal is a char *
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 6635"), "output: {}", output);
+}
+
+#[test]
+fn test_string_literal_is_read_only() {
+    let code = r#"
+int main() {
+    char *s = "hello";
+    s[0] = 'j';
+    return 0;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(!success, "writing to a string literal should fail, output: {}", output);
+    assert!(!output.contains("Program returned"), "output: {}", output);
+    assert!(output.contains("write to read-only memory at address"), "output: {}", output);
+    assert!(output.contains("#0 pc "), "output: {}", output);
+    assert!(output.contains(" in main"), "output: {}", output);
+    assert!(!output.contains("panicked"), "output: {}", output);
+}
+
+#[test]
+fn test_runtime_errors_exit_cleanly() {
+    let cases = [
+        ("int main() { int *p = (int *)100000000; return *p; }", "memory access out of bounds at address 100000000"),
+        ("int main() { int *p = malloc(8); free(p); free(p); return 0; }", "memory error: free of address"),
+        ("int main() { int x = 1; int *p = realloc(&x, 16); return *p; }", "memory error: realloc of address"),
+        ("int f(int n) { int a[1000]; a[0] = n; return f(n + 1) + a[0]; } int main() { return f(0); }", "stack overflow: out of frame memory"),
+        ("int f(int n) { return f(n + 1) + 1; } int main() { return f(0); }", "stack overflow: out of registers"),
+    ];
+    for (code, error) in cases {
+        let (success, output) = run_compiler(code);
+        assert!(!success, "output: {}", output);
+        assert!(output.contains(&format!("runtime error: {}", error)), "expected '{}', output: {}", error, output);
+        assert!(output.contains("#0 pc "), "output: {}", output);
+        assert!(!output.contains("panicked"), "output: {}", output);
+        assert!(!output.contains("Program returned"), "output: {}", output);
+    }
+}
+
+#[test]
+fn test_heap_linked_list() {
+    let code = r#"
+struct Node {
+    int value;
+    struct Node *next;
+};
+
+struct Node *push(struct Node *head, int value) {
+    struct Node *node = malloc(sizeof(struct Node));
+    node->value = value;
+    node->next = head;
+    return node;
+}
+
+int main() {
+    struct Node *list = 0;
+    int i;
+    for (i = 1; i <= 10; i++) {
+        list = push(list, i);
+    }
+
+    int sum = 0;
+    while (list) {
+        struct Node *next = list->next;
+        sum = sum + list->value;
+        free(list);
+        list = next;
+    }
+    return sum;
+}
+"#;
+
+    let (success, output) = run_compiler_with_flags(code, &["--gc-stats"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("CALLN"), "output: {}", output);
+    assert!(output.contains("Program returned: 55"), "output: {}", output);
+    assert!(output.contains("allocations: 10 (160 bytes)"), "output: {}", output);
+    assert!(output.contains("frees: 10"), "output: {}", output);
+    assert!(output.contains("live at exit: 0 blocks (0 bytes)"), "output: {}", output);
+}
+
+#[test]
+fn test_call_keeps_pending_temporaries() {
+    // (x+1)*100 sits in a register while h runs, h's frame must start above it
+    let code = r#"
+int h() {
+    int a = 1;
+    int b = 2;
+    return a + b;
+}
+
+int add(int a, int b) { return a + b; }
+
+int main() {
+    int x = 7;
+    int r = (x + 1) * 100 + h();
+    int s = x * 10 + add(h(), x * 2 + h());
+    return r + s;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 893"), "output: {}", output);
+}
+
+#[test]
+fn test_calloc_and_realloc() {
+    let code = r#"
+int main() {
+    int *zeros = calloc(4, sizeof(int));
+    int *nums = malloc(2 * sizeof(int));
+    nums[0] = 7;
+    nums[1] = 8;
+    nums = realloc(nums, 100 * sizeof(int));
+    nums[99] = 9;
+    int total = zeros[0] + zeros[3] + nums[0] + nums[1] * 10 + nums[99] * 100;
+    free(zeros);
+    free(nums);
+    free(0);
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 987"), "output: {}", output);
+}
+
+#[test]
+fn test_invalid_free_fails() {
+    let code = r#"
+int main() {
+    char *p = malloc(16);
+    free(p + 1);
+    return 0;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(!success, "freeing a pointer into the middle of a block should fail, output: {}", output);
+    assert!(!output.contains("Program returned"), "output: {}", output);
+}
+
+#[test]
+fn test_gc_reclaims_unreachable_blocks() {
+    // 100 MiB of garbage doesn't fit in the heap unless something collects it
+    let code = r#"
+struct Pair {
+    int *first;
+    int *second;
+};
+
+struct Pair *kept;
+
+int main() {
+    kept = malloc(sizeof(struct Pair));
+    kept->first = malloc(sizeof(int));
+    kept->second = malloc(sizeof(int));
+    *kept->first = 3;
+    *kept->second = 4;
+
+    int i;
+    int failed = 0;
+    for (i = 0; i < 100; i++) {
+        char *garbage = malloc(1024 * 1024);
+        if (garbage == NULL) {
+            failed = 1;
+        } else {
+            garbage[0] = 1;
+        }
+    }
+    return *kept->first * 10 + *kept->second + failed * 100;
+}
+"#;
+
+    let (success, output) = run_compiler_with_flags(code, &["--gc", "--gc-stats"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 34"), "output: {}", output);
+    assert!(output.contains("collector: on"), "output: {}", output);
+    assert!(!output.contains("collections: 0"), "output: {}", output);
+    assert!(!output.contains("reclaimed: 0 blocks"), "output: {}", output);
+
+    // without it malloc runs out and returns null
+    let (success, output) = run_compiler_with_flags(code, &["--gc-stats"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 134"), "output: {}", output);
+    assert!(output.contains("collections: 0"), "output: {}", output);
+}
+
+#[test]
+fn test_checked_mode_passes_correct_programs() {
+    let code = r#"
+struct Node {
+    int value;
+    struct Node *next;
+};
+
+int sum(int *values, int count) {
+    int total = 0;
+    int i;
+    for (i = 0; i < count; i++) {
+        total = total + values[i];
+    }
+    return total;
+}
+
+int main() {
+    int local[4] = {1, 2, 3, 4};
+    struct Node *head = NULL;
+    int i;
+    for (i = 0; i < 3; i++) {
+        struct Node *node = malloc(sizeof(struct Node));
+        node->value = i * 10;
+        node->next = head;
+        head = node;
+    }
+
+    int *grown = calloc(2, sizeof(int));
+    grown = realloc(grown, 8 * sizeof(int));
+    grown[7] = 100;
+
+    int total = sum(local, 4) + grown[1] + grown[7];
+    while (head) {
+        struct Node *next = head->next;
+        total = total + head->value;
+        free(head);
+        head = next;
+    }
+    free(grown);
+    return total;
+}
+"#;
+
+    let (success, output) = run_compiler_with_flags(code, &["--checked"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 140"), "output: {}", output);
+}
+
+#[test]
+fn test_checked_out_of_bounds_index() {
+    let code = r#"
+int fill(int n) {
+    int values[4];
+    int i;
+    for (i = 0; i < n; i++) {
+        values[i] = i;
+    }
+    return values[0];
+}
+
+int main() {
+    return fill(5);
+}
+"#;
+
+    // unchecked the write lands in whatever is next to the array
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+
+    let (success, output) = run_compiler_with_flags(code, &["--checked"]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("out of bounds write of 4 bytes at offset 16 of local 'values' of 'fill' (16 bytes)"), "output: {}", output);
+    assert!(output.contains("#0 pc"), "output: {}", output);
+    assert!(output.contains("in fill"), "output: {}", output);
+    assert!(output.contains("in main"), "output: {}", output);
+}
+
+#[test]
+fn test_checked_heap_errors() {
+    let cases = [
+        ("int main() { char *p = malloc(10); p[10] = 1; return 0; }", "out of bounds write of 1 bytes at offset 10 of the 10 byte block"),
+        ("int main() { int *p = malloc(8); p[0] = 1; free(p); return p[0]; }", "use after free: read of 4 bytes at offset 0"),
+        ("int main() { int *p = malloc(8); free(p); free(p); return 0; }", "double free of the 8 byte block"),
+        ("int main() { int *p = malloc(8); free(p + 1); return 0; }", "invalid free of address"),
+        ("int main() { int *p = malloc(8); return *p; }", "read of uninitialized memory"),
+    ];
+    for (code, error) in cases {
+        let (success, output) = run_compiler_with_flags(code, &["--checked"]);
+        assert!(!success, "output: {}", output);
+        assert!(output.contains(error), "expected '{}', output: {}", error, output);
+    }
+}
+
+#[test]
+fn test_checked_pointer_errors() {
+    let cases = [
+        ("int main() { int *p = NULL; return *p; }", "null pointer dereference: read of 4 bytes at address 0"),
+        ("int main() { int x; int *p = &x; return *p; }", "read of uninitialized memory: 4 bytes at offset 0 of local 'x' of 'main'"),
+        ("int *f() { int x = 5; return &x; } int main() { return *f(); }", "use after return: read of 4 bytes at offset 0 of local 'x' of 'f'"),
+        ("int g[3]; int main() { int *p = g; return p[3]; }", "out of bounds read of 4 bytes at offset 12 of global 'g' (12 bytes)"),
+    ];
+    for (code, error) in cases {
+        let (success, output) = run_compiler_with_flags(code, &["--checked"]);
+        assert!(!success, "output: {}", output);
+        assert!(output.contains(error), "expected '{}', output: {}", error, output);
+    }
+}
+
+#[test]
+fn test_function_pointer_dispatch_table() {
+    let code = r#"
+typedef int (*binop)(int, int);
+
+int add(int a, int b) { return a + b; }
+int sub(int a, int b) { return a - b; }
+int mul(int a, int b) { return a * b; }
+
+binop ops[3] = {add, sub, &mul};
+
+binop pick(int i) { return ops[i]; }
+
+int main() {
+    int total = 0;
+    int i;
+    for (i = 0; i < 3; i++) {
+        int result = ops[i](10, 3);
+        total = total + result;
+    }
+    binop op = pick(2);
+    int product = op(2, 3);
+    return total + product;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 56"), "output: {}", output);
+}
+
+#[test]
+fn test_function_pointer_params_and_fields() {
+    let code = r#"
+struct Handler {
+    int id;
+    int (*run)(int);
+};
+
+int twice(int x) { return x * 2; }
+int square(int x) { return x * x; }
+
+int apply(int (*f)(int), int x) { return f(x); }
+
+int main() {
+    struct Handler h;
+    h.id = 1;
+    h.run = square;
+    struct Handler *hp = &h;
+    int (*fp)(int) = &twice;
+
+    int a = (*fp)(5);
+    int b = hp->run(4);
+    int c = apply(twice, 3);
+    int same = fp == twice;
+    return a + b + c + same;
+}
+"#;
+
+    let (success, output) = run_compiler(code);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 33"), "output: {}", output);
+
+    let (success, output) = run_compiler_with_flags(code, &["--checked"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 33"), "output: {}", output);
+}
+
+#[test]
+fn test_bad_function_pointer_calls_fail() {
+    let cases = [
+        ("int run(int (*f)(int)) { return f(1); } int main() { return run(NULL); }", "call through a null function pointer"),
+        ("int add(int a, int b) { return a + b; } int main() { int (*g)(int) = (int (*)(int)) add; return g(1); }", "call to 'add' with 1 args, it takes 2"),
+    ];
+    for (code, error) in cases {
+        let (success, output) = run_compiler(code);
+        assert!(!success, "output: {}", output);
+        assert!(output.contains(error), "expected '{}', output: {}", error, output);
+    }
+}
+
+#[test]
+fn test_operand_too_big_for_its_field() {
+    // x lands 300000 bytes into the data segment, past what GADDR's 18 bit Bx can name
+    let (success, output) = run_compiler("char big[300000];\nint x;\nint main(void) { return x; }");
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("error[E0300]: GADDR operand 300000 doesn't fit in 18 bits in function 'main'"), "output: {}", output);
+    assert!(output.contains("--> "), "output: {}", output);
+    assert!(!output.contains("panicked"), "output: {}", output);
+
+    // indexing gets there at runtime instead
+    let (success, output) = run_compiler("char big[300000];\nint main(void) { big[299999] = 5; return big[299999]; }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 5"), "output: {}", output);
+}
@@ -0,0 +1,154 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn cvm(args: &[&str]) -> (bool, String) {
+    Command::new("cargo")
+        .args(["build", "--quiet"])
+        .status()
+        .unwrap();
+
+    let output = Command::new("./target/debug/cvm")
+        .args(args)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    (output.status.success(), stdout + &stderr)
+}
+
+fn write_source(code: &str) -> String {
+    let id = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = format!("/tmp/test_disasm_{}.c", id);
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+fn disasm(code: &str, flags: &[&str]) -> String {
+    let path = write_source(code);
+    let mut args = vec!["disasm"];
+    args.extend(flags);
+    args.push(&path);
+    let (success, output) = cvm(&args);
+    assert!(success, "output: {}", output);
+    output
+}
+
+// the instruction line at pc, without its leading spaces
+fn at<'a>(output: &'a str, pc: &str) -> &'a str {
+    output.lines().map(str::trim_start).find(|line| line.starts_with(pc))
+        .unwrap_or_else(|| panic!("no instruction {} in: {}", pc, output))
+}
+
+const PROGRAM: &str = r#"int total = 0;
+char *msg = "hi";
+
+int twice(int x) { return x * 2; }
+
+int main(void) {
+    int i = 0;
+    while (i < 3) {
+        total += 7;
+        i++;
+    }
+    int (*f)(int) = twice;
+    int n = -i;
+    int m = !n;
+    int k = ~m;
+    double d = -2.5;
+    char *p = malloc(8);
+    free(p);
+    return f(k) + (int)d + msg[0];
+}
+"#;
+
+#[test]
+fn test_every_opcode_has_a_name() {
+    let output = disasm(PROGRAM, &[]);
+    assert!(!output.contains("UNKNOWN"), "output: {}", output);
+    for mnemonic in ["UNM", "NOT", "BNOT", "FUNM", "F2I", "CLOSURE", "CALLN", "CALL", "GADDR", "LOAD", "STORE", "TEST", "JMP", "RETURN"] {
+        assert!(output.contains(&format!(": {} ", mnemonic)), "no {} in output: {}", mnemonic, output);
+    }
+    assert!(output.contains("CALLN    r"), "output: {}", output);
+    assert!(output.contains(", malloc"), "output: {}", output);
+}
+
+#[test]
+fn test_jumps_go_to_labels() {
+    let output = disasm(PROGRAM, &[]);
+    let jumps: Vec<&str> = output.lines()
+        .filter_map(|line| line.split("JMP").nth(1))
+        .map(str::trim)
+        .collect();
+    assert!(!jumps.is_empty(), "output: {}", output);
+    for label in jumps {
+        assert!(label.starts_with('L'), "jump to {} in output: {}", label, output);
+        assert!(output.contains(&format!("\n{}:\n", label)), "no label {} in output: {}", label, output);
+    }
+
+    // the loop's JMP back lands on its condition, which starts at pc 1
+    assert!(output.contains("JMP      L0001"), "output: {}", output);
+    assert!(output.contains("L0001:\n  0001: LOADK"), "output: {}", output);
+}
+
+#[test]
+fn test_operands_are_annotated() {
+    let output = disasm(PROGRAM, &[]);
+    assert!(at(&output, "0000: LOADK    r1, K0").ends_with("; 2"), "output: {}", output);
+    assert!(output.contains("; twice"), "output: {}", output);
+    assert!(output.contains("; 2.5"), "output: {}", output);
+    assert!(output.contains("; total"), "output: {}", output);
+    assert!(output.contains("; msg"), "output: {}", output);
+    assert!(output.contains("=== Function: twice (F0, "), "output: {}", output);
+    assert!(output.contains("=== Function: main (F1, "), "output: {}", output);
+}
+
+#[test]
+fn test_source_lines_are_interleaved() {
+    let output = disasm(PROGRAM, &["--source"]);
+    assert!(output.contains("     8 |     while (i < 3) {\nL0001:"), "output: {}", output);
+    assert!(output.contains("    13 |     int n = -i;\n  "), "output: {}", output);
+
+    let plain = disasm(PROGRAM, &[]);
+    assert!(!plain.contains(" | "), "output: {}", plain);
+}
+
+#[test]
+fn test_bytecode_file_disassembles_the_same() {
+    let source = write_source(PROGRAM);
+    let file = source.replace(".c", ".cvmb");
+    let (success, output) = cvm(&["compile", "-o", &file, &source]);
+    assert!(success, "output: {}", output);
+
+    let (success, from_source) = cvm(&["disasm", "--source", &source]);
+    assert!(success, "output: {}", from_source);
+    let (success, from_file) = cvm(&["disasm", "--source", &file]);
+    assert!(success, "output: {}", from_file);
+    assert_eq!(from_source, from_file);
+}
+
+#[test]
+fn test_missing_source_is_left_out() {
+    let source = write_source("int main(void) { return 3; }");
+    let file = source.replace(".c", ".cvmb");
+    let (success, output) = cvm(&["compile", "-o", &file, &source]);
+    assert!(success, "output: {}", output);
+    std::fs::remove_file(&source).unwrap();
+
+    let (success, output) = cvm(&["disasm", "--source", &file]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("note: can't read source"), "output: {}", output);
+    assert!(output.contains("RETURN"), "output: {}", output);
+}
+
+#[test]
+fn test_normal_run_prints_the_disassembly() {
+    let path = write_source(PROGRAM);
+    let (success, output) = cvm(&[&path]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("======== BYTECODE ========"), "output: {}", output);
+    assert!(output.contains("; twice"), "output: {}", output);
+    assert!(output.contains("Program returned: 100"), "output: {}", output);
+}
@@ -0,0 +1,186 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// each file gets its own name so the reports can be told apart, a.c, b.c and so on
+fn run_compiler(files: &[&str]) -> (bool, String) {
+    let id = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let dir = format!("/tmp/test_link_{}", id);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let paths: Vec<String> = files.iter().enumerate()
+        .map(|(i, code)| {
+            let path = format!("{}/{}.c", dir, (b'a' + i as u8) as char);
+            std::fs::write(&path, code).unwrap();
+            path
+        })
+        .collect();
+
+    Command::new("cargo")
+        .args(["build", "--quiet"])
+        .status()
+        .unwrap();
+
+    let output = Command::new("./target/debug/cvm")
+        .args(&paths)
+        .output()
+        .unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    (output.status.success(), stdout + &stderr)
+}
+
+// ============ RESOLVING ACROSS FILES ============
+
+#[test]
+fn test_functions_and_globals_across_files() {
+    let main = r#"
+struct Point { int x; int y; };
+
+int add(int a, int b);
+int scale(struct Point p);
+extern int counter;
+extern int table[];
+extern char *greeting;
+void bump(void);
+
+int main(void) {
+    bump();
+    bump();
+    struct Point p;
+    p.x = 2;
+    p.y = 3;
+    int s = scale(p);
+    int sum = add(counter, s);
+    return sum + table[2] + greeting[0];
+}
+"#;
+    let lib = r#"
+struct Point { int x; int y; };
+
+int counter = 10;
+int table[3] = {1, 2, 3};
+char *greeting = "hi";
+static int calls;
+
+int add(int a, int b) { return a + b; }
+int scale(struct Point p) { return p.x * p.y; }
+void bump(void) { calls++; counter = counter + calls; }
+"#;
+
+    // counter ends up 13, 13 + 6 + 3 + 'h'
+    let (success, output) = run_compiler(&[main, lib]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 126"), "output: {}", output);
+
+    let (success, output) = run_compiler(&[lib, main]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 126"), "output: {}", output);
+}
+
+#[test]
+fn test_static_symbols_stay_in_their_file() {
+    let a = r#"
+static int value = 1;
+static int get(void) { return value; }
+int from_b(void);
+int main(void) { return get() * 10 + from_b(); }
+"#;
+    let b = r#"
+static int value = 2;
+static int get(void) { return value; }
+int from_b(void) { return get(); }
+"#;
+
+    let (success, output) = run_compiler(&[a, b]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 12"), "output: {}", output);
+}
+
+#[test]
+fn test_global_initializer_refers_to_another_file() {
+    let a = r#"
+extern int values[];
+int twice(int x);
+int *second = &values[1];
+int (*op)(int) = twice;
+int main(void) { return op(*second); }
+"#;
+    let b = r#"
+int values[2] = {4, 21};
+int twice(int x) { return x * 2; }
+"#;
+
+    let (success, output) = run_compiler(&[a, b]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 42"), "output: {}", output);
+}
+
+#[test]
+fn test_call_into_another_file_keeps_pending_temporaries() {
+    // f() * 100 waits in a register while g runs, g's locals can't land on it
+    let a = r#"
+int f(void);
+int g(void);
+int main(void) {
+    int x = 1;
+    return f() * 100 + g() + x - 1;
+}
+"#;
+    let b = r#"
+int f(void) { int a = 3; int b = 4; return a + b; }
+int g(void) { int a = 1; int b = 3; return a + b; }
+"#;
+
+    let (success, output) = run_compiler(&[a, b]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 704"), "output: {}", output);
+}
+
+#[test]
+fn test_unused_declarations_need_no_definition() {
+    let (success, output) = run_compiler(&["int nowhere(void); extern int gone; int main(void) { return 3; }"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("Program returned: 3"), "output: {}", output);
+}
+
+// ============ LINK ERRORS ============
+
+#[test]
+fn test_duplicate_definition() {
+    let (success, output) = run_compiler(&["int shared = 1;\nint main(void) { return shared; }", "int x;\nint shared = 2;"]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("error[E0200]: Duplicate definition of 'shared'"), "output: {}", output);
+    assert!(output.contains("b.c:2:1"), "output: {}", output);
+    assert!(output.contains("::: "), "output: {}", output);
+    assert!(output.contains("a.c:1:1"), "output: {}", output);
+    assert!(output.contains("first defined here"), "output: {}", output);
+}
+
+#[test]
+fn test_undefined_reference() {
+    let (success, output) = run_compiler(&["int missing(int x);\nint main(void) {\n    return missing(1);\n}", "int other(void) { return 0; }"]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("error[E0201]: Undefined reference to function 'missing'"), "output: {}", output);
+    assert!(output.contains("a.c:3:12"), "output: {}", output);
+    assert!(output.contains("declared here"), "output: {}", output);
+}
+
+#[test]
+fn test_static_is_not_visible_to_other_files() {
+    let (success, output) = run_compiler(&["extern int secret;\nint main(void) { return secret; }", "static int secret = 3;"]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("Undefined reference to variable 'secret'"), "output: {}", output);
+    assert!(output.contains("'secret' is static in"), "output: {}", output);
+}
+
+#[test]
+fn test_function_defined_as_variable() {
+    let (success, output) = run_compiler(&["int kind(void);\nint main(void) { return kind(); }", "int kind = 1;"]);
+    assert!(!success, "output: {}", output);
+    assert!(output.contains("error[E0202]: 'kind' is declared as a function but defined as a variable"), "output: {}", output);
+}
@@ -57,6 +57,18 @@ fn test_var_decl_array() {
     assert!(success);
 }
 
+#[test]
+fn test_var_decl_array_initializer() {
+    let (success, _) = run_compiler("void f(void) { int arr[] = {1, 2, 3,}; int grid[2][2] = {{1, 2}, {3}}; }");
+    assert!(success);
+}
+
+#[test]
+fn test_var_decl_array_initializer_unclosed() {
+    let (success, _) = run_compiler("void f(void) { int arr[3] = {1, 2; }");
+    assert!(!success);
+}
+
 #[test]
 fn test_var_decl_array_of_pointers() {
     let (success, _) = run_compiler("void f(void) { int* arr[10]; }");
@@ -247,6 +259,20 @@ fn test_typedef_struct() {
     assert!(success);
 }
 
+#[test]
+fn test_typedef_function_pointer() {
+    let (success, _) = run_compiler("typedef int (*binop)(int, int); int add(int a, int b) { return a + b; } void f(void) { binop op = add; }");
+    assert!(success);
+}
+
+// ============ FUNCTION POINTER DECLARATORS ============
+
+#[test]
+fn test_function_pointer_declarators() {
+    let (success, _) = run_compiler("int g(int x) { return x; } int (*global)(int); struct S { void (*cb)(void); }; int apply(int (*)(int), int); void f(void) { int (*table[2])(int) = {g, g}; int (**pp)(int) = &table[0]; global = (int (*)(int)) g; }");
+    assert!(success);
+}
+
 // ============ IF STATEMENTS ============
 
 #[test]
@@ -691,4 +717,40 @@ fn test_many_function_params() {
 fn test_nested_ternary() {
     let (success, _) = run_compiler("void f(void) { int a; int b; int c; int x = a ? b ? 1 : 2 : c ? 3 : 4; }");
     assert!(success);
-}
\ No newline at end of file
+}
+// ============ SYNTAX ERRORS ============
+
+#[test]
+fn test_syntax_error_expected_found() {
+    let (success, output) = run_compiler("void f(void) {\n    int x = 5\n}");
+    assert!(!success);
+    assert!(output.contains("error[E0100]: expected `;`, found `}`"));
+    assert!(output.contains(":3:1\n"));
+}
+
+#[test]
+fn test_syntax_error_missing_expression() {
+    let (success, output) = run_compiler("void f(void) { int x = ; }");
+    assert!(!success);
+    assert!(output.contains("expected expression, found `;`"));
+}
+
+#[test]
+fn test_syntax_errors_recover_at_semicolon() {
+    let (success, output) = run_compiler("void f(void) {\n    int x = ;\n    int y = 1 +;\n    int z = 3;\n}");
+    assert!(!success);
+    assert!(output.contains("Found 2 syntax error(s)"));
+    assert!(output.contains(":2:13\n"));
+    assert!(output.contains(":3:16\n"));
+}
+
+#[test]
+fn test_syntax_errors_recover_across_functions() {
+    let (success, output) = run_compiler("int f(int a,) { return a; }\nint g(void) { return 1 }\nint h(void) { return 2; }");
+    assert!(!success);
+    assert!(output.contains("Found 2 syntax error(s)"));
+    assert!(output.contains("expected type, found `)`"));
+    assert!(output.contains(":1:13\n"));
+    assert!(output.contains("expected `;`, found `}`"));
+    assert!(output.contains(":2:24\n"));
+}
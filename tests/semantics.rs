@@ -4,6 +4,10 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 fn run_compiler(code: &str) -> (bool, String) {
+    run_compiler_with_flags(code, &[])
+}
+
+fn run_compiler_with_flags(code: &str, flags: &[&str]) -> (bool, String) {
     let id = COUNTER.fetch_add(1, Ordering::SeqCst);
     let path = format!("/tmp/test_{}.c", id);
     
@@ -15,6 +19,7 @@ fn run_compiler(code: &str) -> (bool, String) {
         .unwrap();
     
     let output = Command::new("./target/debug/cvm")
+        .args(flags)
         .arg(&path)
         .output()
         .unwrap();
@@ -34,6 +39,23 @@ fn test_redeclaration() {
     assert!(output.contains("Duplicate"), "Expected 'Duplicate' in output: {}", output);
 }
 
+#[test]
+fn test_redefinition_at_file_scope() {
+    let (success, output) = run_compiler("int f(void) { return 1; }\nint f(void) { return 2; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("Redefinition of 'f'") && output.contains("previously defined here"), "output: {}", output);
+
+    let (success, output) = run_compiler("int g(int a);\nlong g(int a) { return a; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("Conflicting types for 'g'"), "output: {}", output);
+}
+
+#[test]
+fn test_repeated_file_scope_declarations() {
+    let (success, output) = run_compiler("int f(int a);\nint x;\nextern int x;\nint x = 1;\nint f(int a) { return a + x; }");
+    assert!(success, "output: {}", output);
+}
+
 #[test]
 fn test_undeclared() {
     let (success, output) = run_compiler("void f(void) { y = 10; }");
@@ -71,6 +93,61 @@ fn test_duplicate_label() {
     assert!(output.contains("Duplicate label"), "Expected 'Duplicate label' in output: {}", output);
 }
 
+#[test]
+fn test_goto_skips_initialization() {
+    let (success, output) = run_compiler("void f(void) { goto L; int x = 1; L: x = 2; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0016]"), "output: {}", output);
+    assert!(output.contains("'x' is initialized here"), "output: {}", output);
+}
+
+#[test]
+fn test_goto_forward_into_block_skips_initialization() {
+    let (success, output) = run_compiler("void f(int a) { goto L; if (a) { int x = a; L: x = 2; } }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0016]"), "output: {}", output);
+}
+
+#[test]
+fn test_goto_backward_into_block_skips_initialization() {
+    let (success, output) = run_compiler("void f(int a) { if (a) { int x = a; L: x = 2; } goto L; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0016]"), "output: {}", output);
+}
+
+#[test]
+fn test_goto_over_uninitialized_declaration() {
+    let (success, output) = run_compiler("void f(void) { goto L; int x; L: x = 2; }");
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("E0016"), "output: {}", output);
+}
+
+#[test]
+fn test_goto_over_static_declaration() {
+    // the static already holds 5 when main starts
+    let (success, output) = run_compiler("int main(void) { goto end; static int x = 5; end: return x; }");
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("E0016"), "output: {}", output);
+    assert!(output.contains("Program returned: 5"), "output: {}", output);
+}
+
+#[test]
+fn test_every_bad_goto_is_reported() {
+    let (success, output) = run_compiler("void f(void) {\n    goto first;\n    goto second;\n    goto L;\n    int x = 1;\nL:  x = 2;\n}");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("undefined label 'first'"), "output: {}", output);
+    assert!(output.contains("undefined label 'second'"), "output: {}", output);
+    assert!(output.contains("error[E0016]"), "output: {}", output);
+}
+
+#[test]
+fn test_goto_out_of_initialized_scope() {
+    let (success, output) = run_compiler("void f(void) { { int x = 1; if (x) goto out; } out: return; }");
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("E0016"), "output: {}", output);
+    assert!(!output.contains("undefined label"), "output: {}", output);
+}
+
 // ============ RETURN TYPE ERRORS ============
 
 #[test]
@@ -142,6 +219,165 @@ fn test_array_index_non_integer() {
     assert!(output.contains("integer") || output.contains("index"), "output: {}", output);
 }
 
+#[test]
+fn test_array_initializer_too_long() {
+    let (success, output) = run_compiler("void f(void) { int arr[2] = {1, 2, 3}; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0003]") && output.contains("Excess elements"), "output: {}", output);
+}
+
+#[test]
+fn test_array_needs_brace_initializer() {
+    let (success, output) = run_compiler("void f(void) { int arr[2] = 5; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("brace-enclosed"), "output: {}", output);
+}
+
+#[test]
+fn test_brace_initializer_on_scalar() {
+    let (success, output) = run_compiler("void f(void) { int x = {1, 2}; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("brace-enclosed"), "output: {}", output);
+}
+
+#[test]
+fn test_assign_to_array() {
+    let (success, output) = run_compiler("void f(void) { int a[2]; int b[2]; a = b; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0005]"), "output: {}", output);
+}
+
+#[test]
+fn test_array_decays_in_arithmetic() {
+    let (success, output) = run_compiler("int f(void) { int a[4]; int *end = a + 4; return end - a; }");
+    assert!(success, "output: {}", output);
+}
+
+// ============ GLOBAL / STATIC INITIALIZERS ============
+
+#[test]
+fn test_global_initializer_not_constant() {
+    let (success, output) = run_compiler("int f(void) { return 1; } int g = f();");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0017]"), "output: {}", output);
+}
+
+#[test]
+fn test_static_initializer_not_constant() {
+    let (success, output) = run_compiler("int f(int x) { static int y = x; return y; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0017]"), "output: {}", output);
+}
+
+#[test]
+fn test_constant_address_initializers() {
+    let (success, output) = run_compiler("int arr[4]; int *p = &arr[2]; int *q = arr; int f(void) { static int *r = &arr[1]; return *r; }");
+    assert!(success, "output: {}", output);
+}
+
+#[test]
+fn test_static_address_of_local_rejected() {
+    let (success, output) = run_compiler("int f(void) { int x = 1; static int *p = &x; return *p; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("not a compile-time constant"), "output: {}", output);
+}
+
+#[test]
+fn test_constant_division_by_zero() {
+    for code in [
+        "int x = 10 / 0;",
+        "int x = 10 % (2 - 2);",
+        "int f(void) { static int y = 1 / (char)0; return y; }",
+        "int x = 5 / (int)0.5;",
+        "int x = 5 % (int)0.5;",
+        "int x = 5 / (char)256;",
+    ] {
+        let (success, output) = run_compiler(code);
+        assert!(!success, "Expected failure, output: {}", output);
+        assert!(output.contains("error[E0017]: Division by zero in constant expression"), "output: {}", output);
+        assert!(!output.contains("panicked"), "output: {}", output);
+    }
+
+    // floats divide by zero fine, and a ternary only folds the branch it picks
+    let (success, output) = run_compiler("double inf = 1.0 / 0; int x = 1 ? 5 : 1 / 0;");
+    assert!(success, "output: {}", output);
+}
+
+#[test]
+fn test_constant_division_overflow() {
+    for code in ["long x = (-9223372036854775807 - 1) / -1;", "long x = (-9223372036854775807 - 1) % -1;"] {
+        let (success, output) = run_compiler(code);
+        assert!(!success, "Expected failure, output: {}", output);
+        assert!(output.contains("error[E0017]: Integer overflow in constant expression"), "output: {}", output);
+        assert!(!output.contains("panicked"), "output: {}", output);
+    }
+
+    // folded as long like codegen does, so an int's minimum divided by -1 is fine
+    let (success, output) = run_compiler("int x = (-2147483647 - 1) / -1;");
+    assert!(success, "output: {}", output);
+}
+
+#[test]
+fn test_string_initializer_too_long() {
+    let (success, output) = run_compiler("void f(void) { char s[2] = \"abc\"; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("too long"), "output: {}", output);
+}
+
+#[test]
+fn test_string_initializer_exact_length() {
+    let (success, output) = run_compiler("char s[3] = \"abc\"; char *p = \"abc\";");
+    assert!(success, "output: {}", output);
+}
+
+// ============ HEAP BUILTINS ============
+
+#[test]
+fn test_malloc_and_free_are_declared() {
+    let (success, output) = run_compiler("int main(void) { int *p = malloc(4 * sizeof(int)); free(p); return 0; }");
+    assert!(success, "output: {}", output);
+}
+
+#[test]
+fn test_free_wrong_argument_count() {
+    let (success, output) = run_compiler("int main(void) { int *p = malloc(4); free(p, p); return 0; }");
+    assert!(!success, "Expected failure, output: {}", output);
+}
+
+#[test]
+fn test_null_pointer_constants() {
+    let (success, output) = run_compiler("int *g = 0; int main(void) { char *p = NULL; int *q = 0; q = 0; free(0); return p == 0 || 0 != q; }");
+    assert!(success, "output: {}", output);
+}
+
+#[test]
+fn test_nonzero_int_is_not_a_pointer() {
+    let (success, output) = run_compiler("int main(void) { int *p = 1; return 0; }");
+    assert!(!success, "Expected failure, output: {}", output);
+}
+
+// ============ FUNCTION POINTERS ============
+
+#[test]
+fn test_function_pointer_assignment() {
+    let (success, output) = run_compiler("typedef int (*unop)(int); int neg(int x) { return -x; } unop table[1] = {neg}; int main(void) { int (*f)(int) = neg; unop g = &neg; f = 0; return g(1) + table[0](2); }");
+    assert!(success, "output: {}", output);
+}
+
+#[test]
+fn test_function_pointer_signature_mismatch() {
+    let (success, output) = run_compiler("int add(int a, int b) { return a + b; } int main(void) { int (*f)(int) = add; return 0; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("Type mismatch"), "output: {}", output);
+}
+
+#[test]
+fn test_function_pointer_wrong_argument_count() {
+    let (success, output) = run_compiler("int neg(int x) { return -x; } int main(void) { int (*f)(int) = neg; return f(1, 2); }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("Expected 1 arguments, got 2"), "output: {}", output);
+}
+
 // ============ SWITCH ERRORS ============
 
 #[test]
@@ -244,4 +480,193 @@ fn test_break_in_loop() {
 fn test_continue_in_loop() {
     let (success, output) = run_compiler("void f(void) { while(1) { continue; } }");
     assert!(success, "Expected success, output: {}", output);
-}
\ No newline at end of file
+}
+
+// ============ LOCATIONS ============
+
+#[test]
+fn test_error_reports_line_and_column() {
+    let (success, output) = run_compiler("void f(void) {\n    int x;\n    int x;\n}");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("3:9"), "Expected '3:9' in output: {}", output);
+}
+
+#[test]
+fn test_return_error_points_at_statement() {
+    let (success, output) = run_compiler("int f(void) {\n    int x = 1;\n      return;\n}");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0004]: Expected return value"), "output: {}", output);
+    assert!(output.contains(":3:7\n"), "output: {}", output);
+}
+
+// ============ DIAGNOSTICS ============
+
+#[test]
+fn test_redeclaration_code_and_label() {
+    let (success, output) = run_compiler("void f(void) {\n    int x;\n    int x;\n}");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0001]"), "output: {}", output);
+    assert!(output.contains("3 |     int x;\n  |         ^\n"), "output: {}", output);
+    assert!(output.contains("2 |     int x;\n  |         - previously declared here"), "output: {}", output);
+}
+
+#[test]
+fn test_duplicate_enumerator() {
+    let (success, output) = run_compiler("enum Color { RED, GREEN };\nenum Light { GREEN, OFF };");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0001]: Duplicate enumerator 'GREEN'"), "output: {}", output);
+    assert!(output.contains(":2:14\n"), "output: {}", output);
+    assert!(output.contains("previously declared here"), "output: {}", output);
+
+    let (success, output) = run_compiler("enum Color { RED, RED };");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("Duplicate enumerator 'RED'"), "output: {}", output);
+}
+
+#[test]
+fn test_redeclaration_points_at_the_name() {
+    let (success, output) = run_compiler("int count;\nunsigned long count;\nvoid f(int a, int (*a)(int)) {}\ntypedef int word;\ntypedef char word;");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains(":2:15\n"), "output: {}", output);
+    assert!(output.contains("2 | unsigned long count;\n  |               ^^^^^\n"), "output: {}", output);
+    assert!(output.contains(":3:21\n"), "output: {}", output);
+    assert!(output.contains(":5:14\n"), "output: {}", output);
+}
+
+#[test]
+fn test_undeclared_code() {
+    let (success, output) = run_compiler("void f(void) { y = 10; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0002]"), "output: {}", output);
+}
+
+#[test]
+fn test_lvalue_error_has_note() {
+    let (success, output) = run_compiler("void f(int a) { 5 = a; }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0005]"), "output: {}", output);
+    assert!(output.contains("= note:"), "output: {}", output);
+}
+
+#[test]
+fn test_duplicate_default_points_at_first() {
+    let (success, output) = run_compiler("void f(int a) { switch (a) { default: break; default: break; } }");
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[E0012]"), "output: {}", output);
+    assert!(output.contains("first default here"), "output: {}", output);
+}
+
+// ============ WARNINGS ============
+
+#[test]
+fn test_warn_unused_variable() {
+    let (success, output) = run_compiler("int main(void) { int x; return 0; }");
+    assert!(success, "Warnings should not fail the build, output: {}", output);
+    assert!(output.contains("warning[W0001]: unused variable 'x'"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_unused_parameter() {
+    let (success, output) = run_compiler("int f(int a, int b) { return a; }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0002]: unused parameter 'b'"), "output: {}", output);
+    assert!(!output.contains("parameter 'a'"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_unreachable_after_return() {
+    let (success, output) = run_compiler("int f(int a) {\n    return a;\n    a = 2;\n}");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0003]"), "output: {}", output);
+    assert!(output.contains(":3:5\n"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_unreachable_after_break() {
+    let (success, output) = run_compiler("void f(int a) { while (a) { break; a = 1; } }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0003]"), "output: {}", output);
+}
+
+#[test]
+fn test_unreachable_ends_at_label() {
+    let (success, output) = run_compiler("int f(int a) { goto done; a = 1; done: return a; }");
+    assert!(success, "output: {}", output);
+    assert_eq!(output.matches("W0003").count(), 1, "output: {}", output);
+}
+
+#[test]
+fn test_warn_missing_return() {
+    let (success, output) = run_compiler("int f(int a) { if (a) { return 1; } }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0004]: control reaches end of non-void function 'f'"), "output: {}", output);
+}
+
+#[test]
+fn test_no_missing_return_when_all_paths_return() {
+    let (success, output) = run_compiler("int f(int a) { if (a) { return 1; } else { return 2; } }\nint g(int a) { while (1) { if (a) return a; } }");
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("W0004"), "output: {}", output);
+}
+
+#[test]
+fn test_do_while_body_that_always_returns() {
+    let (success, output) = run_compiler("int f(int n) { do { return n; } while (0); }");
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("W0004"), "output: {}", output);
+
+    // a continue goes to the condition, which can be false
+    let (success, output) = run_compiler("int g(int n) { do { if (n) continue; return n; } while (--n); }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0004]: control reaches end of non-void function 'g'"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_assignment_in_condition() {
+    let (success, output) = run_compiler("int f(int a) { if (a = 0) { return 1; } return a; }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0005]: assignment used as a condition"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_unsigned_compared_with_zero() {
+    let (success, output) = run_compiler("int f(unsigned int u) { if (u < 0) { return 1; } return 0; }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0006]: comparison is always false"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_self_comparison() {
+    let (success, output) = run_compiler("int f(int a) { return a == a; }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0006]: comparison is always true"), "output: {}", output);
+}
+
+#[test]
+fn test_warn_unused_label() {
+    let (success, output) = run_compiler("int f(void) { unused: return 0; }");
+    assert!(success, "output: {}", output);
+    assert!(output.contains("warning[W0007]: unused label 'unused'"), "output: {}", output);
+}
+
+#[test]
+fn test_werror_fails_build() {
+    let (success, output) = run_compiler_with_flags("int main(void) { int x; return 0; }", &["-Werror"]);
+    assert!(!success, "Expected failure, output: {}", output);
+    assert!(output.contains("error[W0001]"), "output: {}", output);
+}
+
+#[test]
+fn test_w_suppresses_warnings() {
+    let (success, output) = run_compiler_with_flags("int main(void) { int x; return 0; }", &["-w", "-Werror"]);
+    assert!(success, "output: {}", output);
+    assert!(!output.contains("W0001"), "output: {}", output);
+}
+
+#[test]
+fn test_wno_disables_one_warning() {
+    let (success, output) = run_compiler_with_flags("int f(int a) { int x; return 0; }", &["-Wno-unused-parameter"]);
+    assert!(success, "output: {}", output);
+    assert!(output.contains("W0001"), "output: {}", output);
+    assert!(!output.contains("W0002"), "output: {}", output);
+}